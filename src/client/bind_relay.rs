@@ -0,0 +1,137 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use tokio::{
+    io,
+    net::{TcpListener, UdpSocket},
+    time::timeout,
+};
+
+use crate::{
+    http::{HttpRequest, HttpResponse},
+    utils::{Failure, NeckStream, ADDRESS_HEADER, FAILURE_HEADER},
+};
+
+use super::{
+    start_worker::{check_destination, weld_destination, Destination},
+    NeckClient,
+};
+
+/// How long to wait for the destination to connect the bound address.
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Answer a failure of a BIND request, the status does not matter as the server reads the reason from the header.
+async fn answer_failure(
+    stream: &NeckStream,
+    req: &HttpRequest,
+    failure: Failure,
+    message: &str,
+) -> io::Result<()> {
+    let (status, text) = failure.http_status();
+    HttpResponse::new(status, text, req.get_version())
+        .add_header_kv(FAILURE_HEADER, failure.as_str())
+        .add_payload(message.as_bytes())
+        .add_payload(b"\n")
+        .write_to_stream(stream)
+        .await
+}
+
+/// Get the local IP of the interface routed to the `peer`, which the peer is able to connect.
+/// NOTE: Connecting a UDP socket sends nothing, it only looks up the route.
+async fn local_ip_to(peer: SocketAddr) -> Option<IpAddr> {
+    let any = match peer {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind(SocketAddr::new(any, 0)).await.ok()?;
+    socket.connect(peer).await.ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// Answer a BIND request, which listens on an ephemeral port for the destination to connect back,
+/// such as the data connection of active-mode FTP.
+///
+/// After the 200 response, the worker sends two notices with the `Neck-Address` header:
+/// the bound address once listening, and the peer once connected, then the peer is welded.
+pub async fn relay_bind(
+    ctx: &NeckClient,
+    stream: &NeckStream,
+    req: &HttpRequest,
+) -> io::Result<()> {
+    // The destination is the host expected to connect back, which is checked like CONNECT.
+    let addrs = match check_destination(ctx, req.get_uri()).await {
+        Ok(Destination::Resolved(addrs)) => addrs,
+        Ok(Destination::Forbidden(reason)) => {
+            println!(
+                "[{}] Refused to bind for {}: {}",
+                stream.local_addr,
+                req.get_uri(),
+                reason
+            );
+            return answer_failure(stream, req, Failure::Forbidden, &reason).await;
+        }
+        Err(e) => return answer_failure(stream, req, Failure::Dns, &e.to_string()).await,
+    };
+
+    // Listen on the interface routed to the destination, or on all interfaces if the route is unknown.
+    let ip = local_ip_to(addrs[0])
+        .await
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let listener = match TcpListener::bind(SocketAddr::new(ip, 0)).await {
+        Ok(it) => it,
+        Err(e) => return answer_failure(stream, req, Failure::Other, &e.to_string()).await,
+    };
+    let bound = listener.local_addr()?;
+    println!(
+        "[{}] Bind {} for {}",
+        stream.local_addr,
+        bound,
+        req.get_uri()
+    );
+
+    HttpResponse::new(200, "Bind Established", req.get_version())
+        .write_to_stream(stream)
+        .await?;
+    HttpResponse::new(200, "Bound", req.get_version())
+        .add_header_kv(ADDRESS_HEADER, &bound.to_string())
+        .write_to_stream(stream)
+        .await?;
+
+    // Only the destination is accepted, unless it is an unspecified address.
+    let accept = async {
+        loop {
+            let (upstream, peer) = listener.accept().await?;
+            if addrs
+                .iter()
+                .any(|a| a.ip() == peer.ip() || a.ip().is_unspecified())
+            {
+                return Ok::<_, io::Error>((upstream, peer));
+            }
+            println!(
+                "[{}] Refused an unexpected peer {} at {}",
+                stream.local_addr, peer, bound
+            );
+        }
+    };
+
+    let (upstream, peer) = match timeout(ACCEPT_TIMEOUT, accept).await {
+        Ok(Ok(it)) => it,
+        Ok(Err(e)) => return answer_failure(stream, req, Failure::Other, &e.to_string()).await,
+        Err(_) => {
+            println!("[{}] No peer has connected {}", stream.local_addr, bound);
+            let message = "No peer has connected the bound address";
+            return answer_failure(stream, req, Failure::Timeout, message).await;
+        }
+    };
+    drop(listener);
+    println!("[{}] Accept {} at {}", stream.local_addr, peer, bound);
+
+    HttpResponse::new(200, "Accepted", req.get_version())
+        .add_header_kv(ADDRESS_HEADER, &peer.to_string())
+        .write_to_stream(stream)
+        .await?;
+
+    weld_destination(stream, upstream).await
+}
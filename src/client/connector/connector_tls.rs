@@ -1,25 +1,107 @@
-use crate::utils::connect;
+use native_tls::{Certificate, Identity};
+use openssl::x509::X509;
+use sha2::{Digest, Sha256};
+
+use crate::utils::{connect, parse_hex_digest, read_file, NeckError, NeckResult};
 
 use super::{
-    super::neck_url::NeckUrl,
+    super::{neck_url::NeckUrl, TlsOptions},
     {ConnResult, Connector},
 };
 
+/// Load the client identity from a PKCS#12 file, or from a pair of PEM certificate and key files.
+fn load_identity(tls: &TlsOptions) -> NeckResult<Option<Identity>> {
+    if let Some(path) = &tls.tls_pkcs12 {
+        let password = tls.tls_pkcs12_password.as_deref().unwrap_or_default();
+        return Ok(Some(Identity::from_pkcs12(&read_file(path)?, password)?));
+    }
+    match (&tls.tls_cert, &tls.tls_key) {
+        (Some(cert), Some(key)) => Ok(Some(Identity::from_pkcs8(
+            &read_file(cert)?,
+            &read_file(key)?,
+        )?)),
+        _ => Ok(None),
+    }
+}
+
+/// Load all certificates from a PEM bundle file.
+fn load_bundle(path: &str) -> NeckResult<Vec<Certificate>> {
+    let certs = X509::stack_from_pem(&read_file(path)?)?;
+    if certs.is_empty() {
+        return NeckError::wrap(format!("No certificate is found in '{}'", path));
+    }
+    certs
+        .iter()
+        .map(|cert| Ok(Certificate::from_der(&cert.to_der()?)?))
+        .collect()
+}
+
+/// Parse a SHA-256 fingerprint in hex, optionally separated by colons like "AB:CD:...".
+fn parse_pin(pin: &str) -> NeckResult<[u8; 32]> {
+    match parse_hex_digest(&pin.replace(':', "")) {
+        Some(it) => Ok(it),
+        None => NeckError::wrap(format!("Invalid SHA-256 fingerprint '{}'", pin)),
+    }
+}
+
+/// Format a SHA-256 fingerprint in hex.
+fn format_pin(pin: &[u8; 32]) -> String {
+    pin.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 pub struct TlsConnector {
     addr: String,
     domain: String,
     connector: tokio_native_tls::TlsConnector,
+    /// The SHA-256 fingerprints of the pinned server certificates.
+    pins: Vec<[u8; 32]>,
 }
 
 impl TlsConnector {
-    pub fn new(url: &NeckUrl, tls_domain: Option<String>) -> Self {
-        Self {
+    pub fn new(url: &NeckUrl, tls: &TlsOptions) -> NeckResult<Self> {
+        let mut builder = native_tls::TlsConnector::builder();
+
+        // Present a client certificate if provided.
+        if let Some(identity) = load_identity(tls)? {
+            builder.identity(identity);
+        }
+
+        // Trust private CAs if provided.
+        for path in &tls.tls_ca {
+            for cert in load_bundle(path)? {
+                builder.add_root_certificate(cert);
+            }
+        }
+
+        // The pinned certificates are checked after the handshake instead of the CA verification,
+        // so that a self-signed server certificate can be pinned.
+        let pins = tls
+            .tls_pin
+            .iter()
+            .map(|pin| parse_pin(pin))
+            .collect::<NeckResult<Vec<_>>>()?;
+
+        if tls.tls_insecure {
+            eprintln!("WARNING: The server certificate will NOT be verified (--tls-insecure).");
+            eprintln!("WARNING: Anyone on the network path can intercept the traffic.");
+        }
+
+        if tls.tls_insecure || !pins.is_empty() {
+            builder.danger_accept_invalid_certs(true);
+            builder.danger_accept_invalid_hostnames(true);
+        }
+
+        Ok(Self {
             addr: url.get_addr().into(),
             // If tls_domain is not set, get the hostname from URL.
-            domain: tls_domain.unwrap_or_else(|| url.get_hostname().into()),
+            domain: tls
+                .tls_domain
+                .clone()
+                .unwrap_or_else(|| url.get_hostname().into()),
             // Initialize the TlsConnector
-            connector: native_tls::TlsConnector::new().unwrap().into(),
-        }
+            connector: builder.build()?.into(),
+            pins,
+        })
     }
 }
 
@@ -32,6 +114,20 @@ impl Connector for TlsConnector {
             // Wrap the TcpStream with TlsSteram.
             let tls_stream = self.connector.connect(&self.domain, tcp_stream).await?;
 
+            // Check the fingerprint of the server certificate if any certificate is pinned.
+            if !self.pins.is_empty() {
+                let fingerprint: [u8; 32] = match tls_stream.get_ref().peer_certificate()? {
+                    Some(cert) => Sha256::digest(cert.to_der()?).into(),
+                    None => return NeckError::wrap("The server presents no certificate"),
+                };
+                if !self.pins.contains(&fingerprint) {
+                    return NeckError::wrap(format!(
+                        "The server certificate {} is not pinned",
+                        format_pin(&fingerprint)
+                    ));
+                }
+            }
+
             // Wrap the TlsSteram stream with NeckStream
             Ok(tls_stream.into())
         })
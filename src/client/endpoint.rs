@@ -0,0 +1,196 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering::SeqCst},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use tokio::time::Instant;
+
+use crate::utils::{NeckResult, NeckStream};
+
+use super::{connector::Connector, neck_url::NeckUrl};
+
+/// The maximum number of consecutive failures counted for the backoff, which waits 32 seconds at most.
+const MAX_FAILURES: u32 = 6;
+
+/// How long the other workers wait while a worker is probing a server which is down.
+const PROBE_WAIT: Duration = Duration::from_secs(1);
+
+/// How workers are shared between the servers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ServerMode {
+    /// Join the first available server in the given order.
+    #[default]
+    Failover,
+    /// Join all available servers, with an even share of workers.
+    Spread,
+}
+
+/// The health of a server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    /// The server is working, or it has not been tried yet.
+    Up,
+    /// The server has failed for a number of consecutive times, and it is retried after the time.
+    Down { failures: u32, retry_at: Instant },
+    /// The server cannot work with this client, with a reason, so it is never retried.
+    Incompatible(String),
+}
+
+/// A Neck Server to join, with its own health tracking and backoff.
+pub struct Endpoint {
+    pub url: NeckUrl,
+    connector: Box<dyn Connector>,
+    health: Mutex<Health>,
+    /// The number of workers connecting or joined to this server.
+    pub workers: AtomicU32,
+    /// A worker is probing the server after its backoff, the other workers wait for the result.
+    probing: AtomicBool,
+}
+
+impl Endpoint {
+    pub fn new(url: NeckUrl, connector: Box<dyn Connector>) -> Self {
+        Self {
+            url,
+            connector,
+            health: Mutex::new(Health::Up),
+            workers: AtomicU32::new(0),
+            probing: AtomicBool::new(false),
+        }
+    }
+
+    /// Create a connect from connector.
+    pub async fn connect(&self) -> NeckResult<NeckStream> {
+        self.connector.connect().await
+    }
+
+    pub fn health(&self) -> Health {
+        self.health.lock().unwrap().clone()
+    }
+
+    /// Check if a worker can join this server at the time.
+    /// A server which is down is available after its backoff, unless another worker is probing it.
+    pub fn is_available(&self, now: Instant) -> bool {
+        match &*self.health.lock().unwrap() {
+            Health::Up => true,
+            Health::Down { retry_at, .. } => *retry_at <= now && !self.probing.load(SeqCst),
+            Health::Incompatible(_) => false,
+        }
+    }
+
+    /// Claim the server for a worker, which is the only one to probe it if it is down.
+    /// Return false if another worker has claimed it first.
+    fn claim(&self) -> bool {
+        match &*self.health.lock().unwrap() {
+            Health::Down { .. } => self
+                .probing
+                .compare_exchange(false, true, SeqCst, SeqCst)
+                .is_ok(),
+            _ => true,
+        }
+    }
+
+    /// Get the time to retry, None if the server is not down.
+    pub fn retry_at(&self) -> Option<Instant> {
+        match &*self.health.lock().unwrap() {
+            Health::Down { retry_at, .. } => Some(*retry_at),
+            _ => None,
+        }
+    }
+
+    /// Describe the health and the number of workers, such as "127.0.0.1:1081 down after 2 failures, 0 workers".
+    pub fn status(&self) -> String {
+        let health = match self.health() {
+            Health::Up => String::from("up"),
+            Health::Down { failures, .. } => format!("down after {} failures", failures),
+            Health::Incompatible(reason) => format!("incompatible ({})", reason),
+        };
+        format!(
+            "{} {}, {} workers",
+            self.url.get_addr(),
+            health,
+            self.workers.load(SeqCst)
+        )
+    }
+
+    /// Mark the server up after a worker has joined it.
+    pub fn succeed(&self) {
+        let mut health = self.health.lock().unwrap();
+        self.probing.store(false, SeqCst);
+        if let Health::Down { .. } = *health {
+            println!(
+                "Server {} is up again, with {} workers",
+                self.url.get_addr(),
+                self.workers.load(SeqCst)
+            );
+            *health = Health::Up;
+        }
+    }
+
+    /// Mark the server down after a worker has failed with it, following exponential backoff.
+    pub fn fail(&self, now: Instant) {
+        let mut health = self.health.lock().unwrap();
+        let failures = match &*health {
+            Health::Up => 1,
+            // The other workers failing in the same round are not counted again.
+            Health::Down { retry_at, .. } if *retry_at > now => return,
+            Health::Down { failures, .. } => (failures + 1).min(MAX_FAILURES),
+            Health::Incompatible(_) => return,
+        };
+        self.probing.store(false, SeqCst);
+        let backoff = Duration::from_secs(1 << (failures - 1));
+        println!(
+            "Server {} is down, retry in {} seconds",
+            self.url.get_addr(),
+            backoff.as_secs()
+        );
+        *health = Health::Down {
+            failures,
+            retry_at: now + backoff,
+        };
+    }
+
+    /// Never retry the server, because it cannot work with this client.
+    pub fn reject(&self, message: String) {
+        eprintln!("Failed to join {}: {}", self.url.get_addr(), message);
+        *self.health.lock().unwrap() = Health::Incompatible(message);
+        self.probing.store(false, SeqCst);
+    }
+}
+
+/// The choice of a server for a worker.
+pub enum Selected<'a> {
+    Endpoint(&'a Endpoint),
+    /// All servers are down, so wait until the earliest one can be retried.
+    RetryAt(Instant),
+    /// No server can work with this client.
+    None,
+}
+
+/// Select a server for a worker from the `endpoints` in the given order, by the `mode`.
+pub fn select_endpoint(endpoints: &[Endpoint], mode: ServerMode, now: Instant) -> Selected<'_> {
+    let mut available = endpoints.iter().filter(|e| e.is_available(now));
+    let selected = match mode {
+        ServerMode::Failover => available.next(),
+        // The first one with the fewest workers, so that the workers are spread evenly.
+        ServerMode::Spread => available.min_by_key(|e| e.workers.load(SeqCst)),
+    };
+    // Only one worker probes a server which is down, the others select again after a moment.
+    if let Some(endpoint) = selected.filter(|e| e.claim()) {
+        return Selected::Endpoint(endpoint);
+    }
+    let retry_at = |e: &Endpoint| {
+        let t = e.retry_at()?;
+        Some(match e.probing.load(SeqCst) {
+            true => t.max(now + PROBE_WAIT),
+            false => t,
+        })
+    };
+    match endpoints.iter().filter_map(retry_at).min() {
+        Some(retry_at) => Selected::RetryAt(retry_at),
+        None => Selected::None,
+    }
+}
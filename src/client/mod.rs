@@ -1,9 +1,15 @@
+mod bind_relay;
 mod connector;
+mod endpoint;
 mod neck_client;
 mod neck_url;
+mod options;
 mod start_worker;
 mod token_bucket;
+mod udp_relay;
 
 mod tests;
 
+pub use endpoint::*;
 pub use neck_client::*;
+pub use options::*;
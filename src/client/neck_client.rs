@@ -1,69 +1,167 @@
-use std::sync::Arc;
+use std::{
+    process::exit,
+    ptr,
+    sync::{
+        atomic::{AtomicU32, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
+};
 
-use tokio::sync::{
-    mpsc::{self, Receiver, Sender},
-    Mutex,
+use tokio::{
+    select,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    time::{sleep, Instant},
 };
 
-use crate::utils::{NeckResult, NeckStream};
+use crate::{
+    framed::FRAMED,
+    mux::MUX,
+    rules::Rules,
+    utils::{Capabilities, BIND_RELAY, HEARTBEAT, UDP_RELAY},
+};
 
 use super::{
     connector::{Connector, TcpConnector, TlsConnector},
     neck_url::NeckUrl,
+    select_endpoint,
     start_worker::start_worker,
     token_bucket::TokenBucket,
+    ClientOptions, Endpoint, Health, Selected, ServerMode, TlsOptions,
 };
 
-fn create_connector(url: &NeckUrl, tls_domain: Option<String>) -> Box<dyn Connector> {
+fn create_connector(url: &NeckUrl, tls: &TlsOptions) -> Box<dyn Connector> {
     if url.is_https() {
-        return Box::new(TlsConnector::new(url, tls_domain));
+        return match TlsConnector::new(url, tls) {
+            Ok(v) => Box::new(v),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        };
     }
     Box::new(TcpConnector::new(url))
 }
 
+fn load_rules(entries: &[String], file: &Option<String>) -> Rules {
+    match Rules::load(entries, file) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+/// How often the status of the servers is checked, which is printed only if it has changed.
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
 pub struct NeckClient {
-    pub url: NeckUrl,
-    pub workers: u32,
+    /// The servers to join, in the given order.
+    pub endpoints: Vec<Endpoint>,
+    /// How workers are shared between the servers.
+    pub mode: ServerMode,
+    /// The range of concurrent workers, which are scaled by the demand of the server.
+    pub min_workers: u32,
+    pub max_workers: u32,
+    /// The number of running workers.
+    pub running: AtomicU32,
+    /// The number of workers wanted by the server, the surplus workers retire by themselves.
+    pub target: AtomicU32,
+    /// The number of connections waiting for CONNECT requests.
+    pub idle: AtomicU32,
     pub bucket: TokenBucket,
-    connector: Box<dyn Connector>,
+    /// The access rules for destinations requested by the server.
+    pub rules: Rules,
+    /// Refuse destinations that resolve to loopback, link-local or cloud metadata addresses.
+    pub block_local: bool,
+    /// Offer the framed mode when joining.
+    pub framed: bool,
+    /// Offer the multiplexed mode when joining.
+    pub mux: bool,
+    /// The worker group declared when joining.
+    pub group: Option<String>,
     sender: Sender<Event>,
     receiver: Mutex<Receiver<Event>>,
 }
 
 pub enum Event {
-    Joined,
-    Failed,
+    /// A server and the worker cannot work with each other.
+    Incompatible,
 }
 
 impl NeckClient {
-    pub fn new(
-        url: String,
-        workers: Option<u32>,
-        connections: Option<u32>,
-        tls_domain: Option<String>,
-    ) -> Self {
+    pub fn new(options: ClientOptions) -> Self {
         let (sender, receiver) = mpsc::channel::<Event>(32);
 
-        let a = url.into();
-        let connector = create_connector(&a, tls_domain);
+        // Create a connector for each server while considering the TLS configuration.
+        let endpoints = options
+            .urls
+            .into_iter()
+            .map(|raw| {
+                let url = NeckUrl::from(raw);
+                let connector = create_connector(&url, &options.tls);
+                Endpoint::new(url, connector)
+            })
+            .collect();
+
+        // The number of concurrent workers defaults 8, and it is not scaled unless a range is given.
+        let workers = options.workers.unwrap_or(8);
+        let (min_workers, max_workers) = match (options.min_workers, options.max_workers) {
+            (Some(min), Some(max)) => (min, max),
+            (Some(min), None) => (min, workers.max(min)),
+            (None, Some(max)) => (workers.min(max), max),
+            (None, None) => (workers, workers),
+        };
+        if min_workers > max_workers {
+            eprintln!("The --min-workers must not be greater than --max-workers");
+            exit(1);
+        }
+        let workers = workers.clamp(min_workers, max_workers);
+
         Self {
-            url: a,
-            // The number of concurrent workers defaults 8.
-            workers: workers.unwrap_or(8),
-            // Create a connector while considering the TLS configuration.
-            connector,
+            endpoints,
+            // Join the servers in failover mode by default.
+            mode: options.mode.unwrap_or_default(),
+            min_workers,
+            max_workers,
+            running: AtomicU32::new(0),
+            target: AtomicU32::new(workers),
+            idle: AtomicU32::new(0),
             // Store the channel handler.
             sender,
             // The receiver is mutable, so wrap it with a Mutex to ensure the NeckClient remains immutable.
             receiver: Mutex::new(receiver),
             // The number of maximum provided connections defaults 200
-            bucket: TokenBucket::new(connections.unwrap_or(200) as usize),
+            bucket: TokenBucket::new(options.connections.unwrap_or(200) as usize),
+            rules: load_rules(&options.rules, &options.rules_file),
+            block_local: options.block_local,
+            framed: options.framed,
+            mux: options.mux,
+            group: options.group,
+        }
+    }
+
+    /// Get the capabilities offered when joining.
+    pub fn get_capabilities(&self) -> Capabilities {
+        // The worker always answers PING, ASSOCIATE and BIND requests.
+        let mut capabilities = Capabilities::default();
+        capabilities.add(HEARTBEAT).add(UDP_RELAY).add(BIND_RELAY);
+        if self.mux {
+            capabilities.add(MUX);
         }
+        if self.framed {
+            capabilities.add(FRAMED);
+        }
+        capabilities
     }
 
-    /// Create a connect from connector.
-    pub async fn connect(&self) -> NeckResult<NeckStream> {
-        self.connector.connect().await
+    /// Select a server for a worker by the mode.
+    pub fn select_endpoint(&self) -> Selected<'_> {
+        select_endpoint(&self.endpoints, self.mode, Instant::now())
     }
 
     /// Dispatch an event.
@@ -71,26 +169,81 @@ impl NeckClient {
         let _ = self.sender.send(event).await;
     }
 
+    /// Check if a server before the `endpoint` is up in the failover mode, so that idle workers should move to it.
+    /// NOTE: A server which is down is probed by a single worker after its backoff, the others move once it is up.
+    pub fn has_preferred(&self, endpoint: &Endpoint) -> bool {
+        self.mode == ServerMode::Failover
+            && self
+                .endpoints
+                .iter()
+                .take_while(|e| !ptr::eq(*e, endpoint))
+                .any(|e| e.health() == Health::Up)
+    }
+
+    /// Scale the workers by the `demand` of the server, based on the current idle connections.
+    pub fn scale(self: &Arc<Self>, demand: i64) {
+        let idle = self.idle.load(SeqCst) as i64;
+        let target = (idle + demand).clamp(self.min_workers as i64, self.max_workers as i64) as u32;
+        if self.target.swap(target, SeqCst) != target {
+            println!("Scale to {} workers by the demand of the server", target);
+        }
+
+        // The surplus workers retire by themselves when they are idle.
+        self.start_workers();
+    }
+
+    /// Start more workers until the target is reached.
+    fn start_workers(self: &Arc<Self>) {
+        let target = self.target.load(SeqCst);
+        while self
+            .running
+            .fetch_update(SeqCst, SeqCst, |v| (v < target).then_some(v + 1))
+            .is_ok()
+        {
+            tokio::spawn(start_worker(self.clone()));
+        }
+    }
+
+    /// Retire a worker if there are more running workers than the target, return true if retired.
+    pub fn retire(&self) -> bool {
+        let target = self.target.load(SeqCst);
+        self.running
+            .fetch_update(SeqCst, SeqCst, |v| (v > target).then(|| v - 1))
+            .is_ok()
+    }
+
     /// Wait and process events.
     async fn wait(&self) {
         let mut receiver = self.receiver.lock().await;
 
-        // Initialize some counters.
-        let mut failed_count = 0u32;
-
         // Read event from channel.
         while let Some(event) = receiver.recv().await {
             match event {
-                // If anyone successfully joins the server, reset the failed counter.
-                Event::Joined => failed_count = 0,
-                // Increase the failed counter.
-                Event::Failed => failed_count += 1,
+                // Retrying is pointless if no server can work with this client, so exit.
+                Event::Incompatible => {
+                    let incompatible = |e: &Endpoint| matches!(e.health(), Health::Incompatible(_));
+                    if self.endpoints.iter().all(incompatible) {
+                        exit(1);
+                    }
+                }
             }
-            // If the failed counter exceeds the number of connections, print an error message.
-            if failed_count > self.workers {
-                eprintln!("Failed to connect {}", self.url.get_addr());
-                // Reset failed counter to debounce the error message printing.
-                failed_count = 0;
+        }
+    }
+
+    /// Print the health and the number of workers of each server, whenever they have changed.
+    async fn report_status(&self) {
+        let mut last = String::new();
+        loop {
+            sleep(STATUS_INTERVAL).await;
+            let status = self
+                .endpoints
+                .iter()
+                .map(Endpoint::status)
+                .collect::<Vec<_>>()
+                .join("; ");
+            if status != last {
+                println!("Servers: {}", status);
+                last = status;
             }
         }
     }
@@ -101,11 +254,12 @@ impl NeckClient {
         let shared_ctx = Arc::new(self);
 
         // Create threads for each client connection.
-        for _ in 0..shared_ctx.workers {
-            tokio::spawn(start_worker(shared_ctx.clone()));
-        }
+        shared_ctx.start_workers();
 
-        // Wait and process events.
-        shared_ctx.wait().await;
+        // Wait and process events, while reporting the status of the servers.
+        select! {
+            _ = shared_ctx.wait() => (),
+            _ = shared_ctx.report_status() => (),
+        }
     }
 }
@@ -0,0 +1,107 @@
+use clap::Args;
+
+use crate::rules::is_valid_group;
+
+use super::ServerMode;
+
+#[derive(Args, Debug, Default)]
+pub struct ClientOptions {
+    /// Proxy server URLs, joined by the --mode.
+    #[arg(required = true, value_name = "URL")]
+    pub urls: Vec<String>,
+
+    /// How workers are shared between the servers: failover (the first available server in order) or spread (all available servers evenly) defaults failover.
+    #[arg(long, value_name = "MODE", value_enum, hide_possible_values = true)]
+    pub mode: Option<ServerMode>,
+
+    /// The number of maximum provided connections defaults 200
+    #[arg(short, long)]
+    pub connections: Option<u32>,
+
+    /// The number of concurrent workers defaults 8.
+    #[arg(short, long)]
+    pub workers: Option<u32>,
+
+    /// Scale down to this number of concurrent workers when the server has too many idle workers, defaults the number of workers.
+    #[arg(long, value_name = "N")]
+    pub min_workers: Option<u32>,
+
+    /// Scale up to this number of concurrent workers when the server wants more idle workers, defaults the number of workers.
+    #[arg(long, value_name = "N")]
+    pub max_workers: Option<u32>,
+
+    /// Add an access rule like "allow *.example.com 443" or "deny 10.0.0.0/8" (repeatable, the first match wins).
+    #[arg(long = "rule", value_name = "RULE")]
+    pub rules: Vec<String>,
+
+    /// Load access rules from a file, one rule per line.
+    #[arg(long, value_name = "FILE")]
+    pub rules_file: Option<String>,
+
+    /// Refuse destinations that resolve to loopback, link-local or cloud metadata addresses.
+    #[clap(long, action)]
+    pub block_local: bool,
+
+    /// Offer the framed mode, so that a connection is reused after each tunnel instead of reconnecting.
+    #[clap(long, action)]
+    pub framed: bool,
+
+    /// Offer the multiplexed mode, so that many tunnels share a few long-lived connections.
+    #[clap(long, action)]
+    pub mux: bool,
+
+    /// Join as a member of this worker group, so that the server sends the destinations routed to the group here.
+    #[arg(long, value_name = "NAME", value_parser = parse_group)]
+    pub group: Option<String>,
+
+    #[command(flatten)]
+    pub tls: TlsOptions,
+}
+
+fn parse_group(raw: &str) -> Result<String, String> {
+    if is_valid_group(raw) {
+        Ok(raw.to_string())
+    } else {
+        Err("only letters, digits, '-', '_' and '.' are allowed".to_string())
+    }
+}
+
+#[derive(Args, Debug, Default)]
+pub struct TlsOptions {
+    /// Specify the domain for TLS, using the hostname of addr by default.
+    #[arg(long)]
+    pub tls_domain: Option<String>,
+
+    /// Present a client certificate from a PEM file, with the private key from --tls-key.
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "tls_key",
+        conflicts_with = "tls_pkcs12"
+    )]
+    pub tls_cert: Option<String>,
+
+    /// The PEM private key file (PKCS#8) for the client certificate.
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    pub tls_key: Option<String>,
+
+    /// Present a client certificate and its private key from a PKCS#12 file.
+    #[arg(long, value_name = "FILE")]
+    pub tls_pkcs12: Option<String>,
+
+    /// The password of the PKCS#12 file.
+    #[arg(long, value_name = "PASSWORD", requires = "tls_pkcs12")]
+    pub tls_pkcs12_password: Option<String>,
+
+    /// Trust CA certificates in this PEM bundle file, in addition to the system roots (repeatable).
+    #[arg(long = "tls-ca", value_name = "FILE")]
+    pub tls_ca: Vec<String>,
+
+    /// Pin the server certificate by its hex SHA-256 fingerprint, instead of verifying its issuer (repeatable).
+    #[arg(long = "tls-pin", value_name = "SHA256")]
+    pub tls_pin: Vec<String>,
+
+    /// Disable the verification of the server certificate, which is DANGEROUS and only for testing.
+    #[clap(long, action, conflicts_with_all = ["tls_ca", "tls_pin"])]
+    pub tls_insecure: bool,
+}
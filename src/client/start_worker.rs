@@ -1,27 +1,112 @@
-use std::{ops::Add, sync::Arc, time::Duration};
+use std::{
+    net::SocketAddr,
+    sync::{atomic::Ordering::SeqCst, Arc},
+    time::Duration,
+};
 
 use tokio::{
     io,
-    time::{self, timeout},
+    net::TcpStream,
+    select,
+    time::{self, timeout, Instant},
 };
 
 use crate::{
-    http::{HttpRequest, HttpResponse},
-    utils::{connect, NeckError, NeckResult, NeckStream},
+    framed::FRAMED,
+    http::{HttpCommon, HttpRequest, HttpResponse},
+    mux::{Mux, MUX},
+    rules::is_local_ip,
+    utils::{
+        connect, get_demand, get_protocol_version, resolve, select_protocol_version, Capabilities,
+        Failure, NeckError, NeckResult, NeckStream, CAPABILITIES_HEADER, FAILURE_HEADER,
+        GROUP_HEADER, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, SOFTWARE, VERSION_HEADER,
+    },
+};
+
+use super::{
+    bind_relay::relay_bind, udp_relay::relay_udp, Endpoint, Event::*, NeckClient, Selected,
 };
 
-use super::{Event::*, NeckClient};
+/// Regard the connection with the server as dead if nothing is received for this duration.
+/// If the server has selected the heartbeat, it never happens unless the server has gone away silently,
+/// otherwise an idle connection is recycled after this duration.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(180);
 
-async fn wait_until_http_proxy_connect(stream: &NeckStream) -> NeckResult<HttpRequest> {
+/// Count a connection as idle while it is alive.
+struct IdleGuard<'a>(&'a NeckClient);
+
+impl<'a> IdleGuard<'a> {
+    fn new(ctx: &'a NeckClient) -> Self {
+        ctx.idle.fetch_add(1, SeqCst);
+        Self(ctx)
+    }
+}
+
+impl Drop for IdleGuard<'_> {
+    fn drop(&mut self) {
+        self.0.idle.fetch_sub(1, SeqCst);
+    }
+}
+
+/// The end of waiting on an idle connection.
+enum Waited {
+    /// A CONNECT request is received.
+    Connect(HttpRequest),
+    /// The worker has retired, because the server wants fewer idle workers.
+    Retired,
+    /// The worker leaves for a preferred server, which is available again in the failover mode.
+    Moved,
+}
+
+/// Wait for a CONNECT request, and answer other requests in the meantime.
+/// If the connection is held by a worker of the `endpoint`, the worker may retire or move to another server.
+async fn wait_until_http_proxy_connect(
+    ctx: &Arc<NeckClient>,
+    stream: &NeckStream,
+    endpoint: Option<&Endpoint>,
+) -> NeckResult<Waited> {
+    let idle = IdleGuard::new(ctx);
     loop {
         // Wait for an HTTP request with a timeout setting.
-        // Normally, the Neck server will constantly sends out PING requests to all workers,
-        // so this timeout event is never triggered.
-        let req = timeout(Duration::from_secs(180), HttpRequest::read_from(stream)).await??;
+        // Normally, the Neck server will constantly sends out PING requests to all idle workers,
+        // so this timeout event is never triggered, unless the server has gone away silently.
+        let req = timeout(IDLE_TIMEOUT, HttpRequest::read_from(stream)).await??;
 
         match req.get_method() {
-            // If method is "CONNECT" return the `req` directly.
-            "CONNECT" => return Ok(req),
+            // If method is "CONNECT" (or "ASSOCIATE" for UDP, "BIND" for incoming connections) return the `req` directly.
+            "CONNECT" | "ASSOCIATE" | "BIND" => {
+                // The connection is no longer idle, when the demand is applied.
+                drop(idle);
+                if let Some(demand) = get_demand(&req.headers) {
+                    ctx.scale(demand);
+                }
+                return Ok(Waited::Connect(req));
+            }
+
+            // If method is "PING", answer it to keep alive, and wait for the next request (the timer is reset).
+            "PING" => {
+                HttpResponse::new(200, "OK", req.get_version())
+                    .write_to_stream(stream)
+                    .await?;
+
+                // Retire when the server has too many idle workers, the connection is closed by dropping.
+                if let Some(demand) = get_demand(&req.headers) {
+                    ctx.scale(demand);
+                    if demand < 0 && endpoint.is_some() && ctx.retire() {
+                        println!("[{}] Retired an idle worker", stream.local_addr);
+                        return Ok(Waited::Retired);
+                    }
+                }
+
+                // Move when a server before this one is available again.
+                if endpoint.is_some_and(|e| ctx.has_preferred(e)) {
+                    println!(
+                        "[{}] Move an idle worker to a preferred server",
+                        stream.local_addr
+                    );
+                    return Ok(Waited::Moved);
+                }
+            }
 
             // Otherwise, respond with a 405 status code, and wait for the next request.
             _ => {
@@ -34,17 +119,23 @@ async fn wait_until_http_proxy_connect(stream: &NeckStream) -> NeckResult<HttpRe
     }
 }
 
-/// Create a connection and try to join the NeckServer.
-async fn connect_and_join(ctx: &NeckClient) -> NeckResult<NeckStream> {
+/// Create a connection and try to join the NeckServer of the `endpoint`.
+async fn connect_and_join(ctx: &Arc<NeckClient>, endpoint: &Endpoint) -> NeckResult<NeckStream> {
     // Attempt to connect NeckServer.
-    let stream = ctx.connect().await?;
+    let mut stream = endpoint.connect().await?;
 
     // Attempt to send a request with Upgrade: neck.
-    HttpRequest::new("GET", ctx.url.get_tail(), "HTTP/1.1")
-        .add_header_kv("Host", &ctx.url.get_host())
+    let mut req = HttpRequest::new("GET", endpoint.url.get_tail(), "HTTP/1.1");
+    req.add_header_kv("Host", &endpoint.url.get_host())
         .add_header("Connection: Upgrade")
         .add_header("Upgrade: neck")
-        .add_header_option(ctx.url.get_authorization())
+        .add_header_kv(VERSION_HEADER, &PROTOCOL_VERSION.to_string())
+        .add_header_kv("User-Agent", SOFTWARE)
+        .add_header_kv(CAPABILITIES_HEADER, &ctx.get_capabilities().to_string());
+    if let Some(group) = &ctx.group {
+        req.add_header_kv(GROUP_HEADER, group);
+    }
+    req.add_header_option(endpoint.url.get_authorization())
         .write_to_stream(&stream)
         .await?;
 
@@ -53,20 +144,130 @@ async fn connect_and_join(ctx: &NeckClient) -> NeckResult<NeckStream> {
 
     // Return the stream object if a 200 status code received.
     if res.get_status() == 101 {
-        // Tell master, this connection has joined.
-        ctx.dispatch_event(Joined).await;
+        // Older servers send no version header, which means version 1.
+        let offered = get_protocol_version(&res.headers);
+        if select_protocol_version(offered, MIN_PROTOCOL_VERSION).is_none() {
+            let message = format!(
+                "The server speaks protocol version '{}' (supported {} to {})",
+                res.headers.get_header_value(VERSION_HEADER).unwrap_or("1"),
+                MIN_PROTOCOL_VERSION,
+                PROTOCOL_VERSION
+            );
+            endpoint.reject(message.clone());
+            ctx.dispatch_event(Incompatible).await;
+            return NeckError::wrap(message);
+        }
+
+        // A capability is used only if the server selects it, older servers will ignore the offer.
+        stream.capabilities =
+            Capabilities::from_headers(&res.headers).intersection(&ctx.get_capabilities());
+        stream.peer_agent = res.headers.get_header_value("Server").map(String::from);
+
+        // This connection has joined, so the server is up.
+        endpoint.succeed();
+        if let Some(demand) = get_demand(&res.headers) {
+            ctx.scale(demand);
+        }
 
         // Return the connected stream.
         return Ok(stream);
     }
 
+    // The server cannot work with this worker, retrying is pointless.
+    if res.get_status() == 426 {
+        let payload = res.get_payload().clone().unwrap_or_default();
+        let message = String::from_utf8_lossy(&payload).trim().to_string();
+        endpoint.reject(message);
+        ctx.dispatch_event(Incompatible).await;
+    }
+
     // Otherwise, return a standard error object.
     NeckError::wrap(format!("Failed to join, get status {}", res.get_status())).into()
 }
 
-async fn connect_upstream_and_weld(stream: &NeckStream, req: &HttpRequest) -> io::Result<()> {
+/// The destination of a CONNECT request, which has been checked.
+pub enum Destination {
+    /// The destination is forbidden, with a reason.
+    Forbidden(String),
+    /// The destination has been resolved.
+    /// NOTE: These addresses must be used to connect, otherwise the DNS may answer differently next time.
+    Resolved(Vec<SocketAddr>),
+}
+
+/// Check the destination with the access rules, then check every resolved address with the rules,
+/// and with the local blocks if `block_local` is enabled, so a name resolving into a denied block is forbidden.
+/// NOTE: The destination is resolved here, so that an error means that the DNS has failed.
+pub async fn check_destination(ctx: &NeckClient, uri: &str) -> NeckResult<Destination> {
+    let decision = ctx.rules.check(uri);
+    if !decision.allowed {
+        let rule = decision.rule.map(|r| r.to_string()).unwrap_or_default();
+        return Ok(Destination::Forbidden(format!("Denied by rule '{}'", rule)));
+    }
+
+    let addrs = resolve(uri).await?;
+    for addr in &addrs {
+        let decision = ctx.rules.check_resolved(addr);
+        if !decision.allowed {
+            let rule = decision.rule.map(|r| r.to_string()).unwrap_or_default();
+            return Ok(Destination::Forbidden(format!(
+                "Resolved to {} denied by rule '{}'",
+                addr.ip(),
+                rule
+            )));
+        }
+        if ctx.block_local && is_local_ip(&addr.ip()) {
+            return Ok(Destination::Forbidden(format!(
+                "Resolved to a local address {}",
+                addr.ip()
+            )));
+        }
+    }
+
+    Ok(Destination::Resolved(addrs))
+}
+
+async fn connect_upstream_and_weld(
+    ctx: &NeckClient,
+    stream: &NeckStream,
+    req: &HttpRequest,
+) -> io::Result<()> {
+    // A UDP association carries datagrams to many destinations, which are checked one by one.
+    if req.get_method() == "ASSOCIATE" {
+        return relay_udp(ctx, stream, req).await;
+    }
+
+    // A BIND request waits for the destination to connect back.
+    if req.get_method() == "BIND" {
+        return relay_bind(ctx, stream, req).await;
+    }
+
+    let upstream = match check_destination(ctx, req.get_uri()).await {
+        // Refuse the forbidden destination without dialing.
+        Ok(Destination::Forbidden(reason)) => {
+            println!(
+                "[{}] Refused to connect {}: {}",
+                stream.local_addr,
+                req.get_uri(),
+                reason
+            );
+
+            HttpResponse::new(403, "Forbidden", req.get_version())
+                .add_header_kv(FAILURE_HEADER, Failure::Forbidden.as_str())
+                .add_payload(reason.as_bytes())
+                .add_payload(b"\n")
+                .write_to_stream(stream)
+                .await?;
+
+            return Ok(());
+        }
+        Ok(Destination::Resolved(addrs)) => connect(&addrs[..])
+            .await
+            .map_err(|e| (Failure::from_error(&e), e)),
+        Err(e) => Err((Failure::Dns, e)),
+    };
+
     // Attempt to connect the upstream server.
-    match connect(req.get_uri()).await {
+    match upstream {
         // If the connection is established successfully.
         Ok(upstream) => {
             println!("[{}] Connect to {}", stream.local_addr, req.get_uri());
@@ -76,15 +277,20 @@ async fn connect_upstream_and_weld(stream: &NeckStream, req: &HttpRequest) -> io
                 .write_to_stream(&stream)
                 .await?;
 
-            // Weld stream and upstream toggle.
-            stream.weld(&NeckStream::from(upstream)).await;
+            weld_destination(stream, upstream).await?;
         }
         // Cannot connect to upstream server.
-        Err(e) => {
-            println!("[{}] Faild to connect {}", stream.local_addr, req.get_uri());
+        Err((failure, e)) => {
+            println!(
+                "[{}] Faild to connect {}: {}",
+                stream.local_addr,
+                req.get_uri(),
+                failure
+            );
 
-            // Answer a 503 status.
+            // Answer a 503 status with the reason, so that the server can tell it from a broken worker.
             HttpResponse::new(503, "Service Unavailable", req.get_version())
+                .add_header_kv(FAILURE_HEADER, failure.as_str())
                 .add_payload(e.to_string().as_bytes())
                 .add_payload(b"\n")
                 .write_to_stream(&stream)
@@ -94,49 +300,123 @@ async fn connect_upstream_and_weld(stream: &NeckStream, req: &HttpRequest) -> io
     Ok(())
 }
 
-async fn setup_connection(ctx: &NeckClient) -> NeckResult<()> {
+/// Weld stream and upstream toggle.
+/// In the framed mode, an error means the connection with the server is broken.
+pub async fn weld_destination(stream: &NeckStream, upstream: TcpStream) -> io::Result<()> {
+    let upstream = NeckStream::from(upstream);
+    if stream.capabilities.has(FRAMED) {
+        upstream.weld_framed(stream).await?;
+    } else {
+        stream.weld(&upstream).await;
+    }
+    Ok(())
+}
+
+/// Serve the streams opened by the server over a multiplexed connection, until the connection is closed.
+async fn serve_mux(ctx: &Arc<NeckClient>, stream: NeckStream) -> NeckResult<()> {
+    // The server sends PING frames constantly, so the connection is regarded as dead if idle for too long.
+    let mux = Mux::client(stream, Some(IDLE_TIMEOUT));
+
+    let accept = async {
+        while let Some(stream) = mux.accept().await {
+            // Each stream works like a dedicated connection, starting with a CONNECT request.
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                if let Ok(Waited::Connect(req)) =
+                    wait_until_http_proxy_connect(&ctx, &stream, None).await
+                {
+                    let _ = connect_upstream_and_weld(&ctx, &stream, &req).await;
+                }
+            });
+        }
+    };
+
+    select! {
+        v = mux.run() => v,
+        _ = accept => Ok(()),
+    }
+}
+
+/// Set up a connection, and serve it until it is used.
+/// Return false if the worker has retired, because the server wants fewer idle workers.
+async fn setup_connection(ctx: &Arc<NeckClient>, endpoint: &Endpoint) -> NeckResult<bool> {
     let token = ctx.bucket.acquire().await;
 
     // Create a connection and try to join the NeckServer.
-    let stream = connect_and_join(ctx).await?;
+    let stream = connect_and_join(ctx, endpoint).await?;
+
+    // A multiplexed connection is held until it is closed, with the token.
+    if stream.capabilities.has(MUX) {
+        let result = serve_mux(ctx, stream).await;
+        drop(token);
+        return result.map(|_| true);
+    }
 
     // Wait for any received CONNECT requests.
-    let req = wait_until_http_proxy_connect(&stream).await?;
+    let req = match wait_until_http_proxy_connect(ctx, &stream, Some(endpoint)).await? {
+        Waited::Connect(it) => it,
+        Waited::Retired => return Ok(false),
+        Waited::Moved => return Ok(true),
+    };
 
     // If a CONNECT request is received, spawn a new asynchronous routine to handle subsequent matters.
     // The current routine should be released to handle the next requests.
+    let ctx = ctx.clone();
     tokio::spawn(async move {
-        // Attempt to connect the upstream server and weld, some io exceptions will be ignored here.
-        if let Err(_) = connect_upstream_and_weld(&stream, &req).await {
-            // There is nothing to handle here, as the above function has taken care of everything.
+        let mut req = req;
+        loop {
+            // Attempt to connect the upstream server and weld, some io exceptions will be ignored here.
+            // There is nothing to handle for them, as the function has taken care of everything.
+            if connect_upstream_and_weld(&ctx, &stream, &req)
+                .await
+                .is_err()
+                || !stream.capabilities.has(FRAMED)
+            {
+                break;
+            }
+
+            // In the framed mode, the connection is clean after the tunnel, so wait for the next CONNECT request.
+            req = match wait_until_http_proxy_connect(&ctx, &stream, None).await {
+                Ok(Waited::Connect(it)) => it,
+                _ => break,
+            };
         }
 
         // The token will be held until this routine is complete, therefore drop it manually at this point.
         drop(token);
     });
 
-    Ok(())
+    Ok(true)
 }
 
 pub async fn start_worker(ctx: Arc<NeckClient>) {
-    // Initialize a failure counter.
-    let mut failures: u8 = 0;
-
     loop {
-        failures = match setup_connection(&ctx).await {
-            // Reset failure counter if the taks success.
-            Ok(_) => 0,
-            // Increase the failure counter (maximum of 6).
-            #[allow(unused_variables)]
-            Err(e) => {
-                // eprintln!("{}", e.to_string());
-                failures.add(1).min(6)
+        // Retire before connecting, if the server has wanted fewer workers meanwhile.
+        if ctx.retire() {
+            return;
+        }
+
+        // Select a server by the mode, or wait until a server can be retried (following exponential backoff).
+        let endpoint = match ctx.select_endpoint() {
+            Selected::Endpoint(it) => it,
+            Selected::RetryAt(retry_at) => {
+                time::sleep_until(retry_at).await;
+                continue;
             }
+            // No server can work with this client, the process is exiting.
+            Selected::None => return,
         };
-        // If the failure counter is not zero, sleep for a few seconds (following exponential backoff).
-        if failures > 0 {
-            ctx.dispatch_event(Failed).await;
-            time::sleep(Duration::from_secs(1 << (failures - 1))).await;
+
+        endpoint.workers.fetch_add(1, SeqCst);
+        let result = setup_connection(&ctx, endpoint).await;
+        endpoint.workers.fetch_sub(1, SeqCst);
+
+        match result {
+            Ok(true) => {}
+            // The worker has retired.
+            Ok(false) => return,
+            // Back off the server, the other servers may be selected meanwhile.
+            Err(_) => endpoint.fail(Instant::now()),
         }
     }
 }
@@ -0,0 +1,101 @@
+use std::net::SocketAddr;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{
+    http::{HttpRequest, HttpResponse},
+    utils::{tests::stream_pair, Failure, ADDRESS_HEADER},
+};
+
+use super::{super::bind_relay::relay_bind, create_client};
+
+fn get_address(res: &HttpResponse) -> SocketAddr {
+    res.headers
+        .get_header_value(ADDRESS_HEADER)
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_relay_bind() {
+    let ctx = create_client(&[]);
+    let (server, worker) = stream_pair().await;
+
+    let relay = tokio::spawn(async move {
+        let req = HttpRequest::read_from(&worker).await.unwrap();
+        relay_bind(&ctx, &worker, &req).await
+    });
+
+    HttpRequest::new("BIND", "127.0.0.1:21", "HTTP/1.1")
+        .write_to_stream(&server)
+        .await
+        .unwrap();
+    let res = HttpResponse::read_from(&server).await.unwrap();
+    assert_eq!(res.get_status(), 200);
+
+    // The bound address is on the interface routed to the destination.
+    let bound = get_address(&HttpResponse::read_from(&server).await.unwrap());
+    assert_eq!(bound.ip().to_string(), "127.0.0.1");
+
+    let mut peer = TcpStream::connect(bound).await.unwrap();
+    let res = HttpResponse::read_from(&server).await.unwrap();
+    assert_eq!(res.get_status(), 200);
+    assert_eq!(get_address(&res), peer.local_addr().unwrap());
+
+    // The peer is welded with the stream.
+    peer.write_all(b"hello").await.unwrap();
+    let mut buf = [0; 5];
+    server
+        .reader
+        .lock()
+        .await
+        .read_exact(&mut buf)
+        .await
+        .unwrap();
+    assert_eq!(&buf, b"hello");
+
+    relay.abort();
+}
+
+#[tokio::test]
+async fn test_relay_bind_denied() {
+    let ctx = create_client(&["deny 127.0.0.2"]);
+    let (server, worker) = stream_pair().await;
+
+    tokio::spawn(async move {
+        let req = HttpRequest::read_from(&worker).await.unwrap();
+        relay_bind(&ctx, &worker, &req).await
+    });
+
+    HttpRequest::new("BIND", "127.0.0.2:21", "HTTP/1.1")
+        .write_to_stream(&server)
+        .await
+        .unwrap();
+    let res = HttpResponse::read_from(&server).await.unwrap();
+    assert_eq!(res.get_status(), 403);
+    assert_eq!(Failure::from_headers(&res.headers), Failure::Forbidden);
+}
+
+#[tokio::test]
+async fn test_relay_bind_resolved_denied() {
+    // The name is allowed, but it resolves into a denied block.
+    let ctx = create_client(&["allow localhost", "deny 127.0.0.0/8", "deny ::1"]);
+    let (server, worker) = stream_pair().await;
+
+    tokio::spawn(async move {
+        let req = HttpRequest::read_from(&worker).await.unwrap();
+        relay_bind(&ctx, &worker, &req).await
+    });
+
+    HttpRequest::new("BIND", "localhost:21", "HTTP/1.1")
+        .write_to_stream(&server)
+        .await
+        .unwrap();
+    let res = HttpResponse::read_from(&server).await.unwrap();
+    assert_eq!(res.get_status(), 403);
+    assert_eq!(Failure::from_headers(&res.headers), Failure::Forbidden);
+}
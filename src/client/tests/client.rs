@@ -0,0 +1,12 @@
+use std::sync::Arc;
+
+use super::super::{ClientOptions, NeckClient};
+
+/// Create a client of an unreachable server with the access `rules`.
+pub fn create_client(rules: &[&str]) -> Arc<NeckClient> {
+    Arc::new(NeckClient::new(ClientOptions {
+        urls: vec!["http://127.0.0.1:1".to_string()],
+        rules: rules.iter().map(|v| v.to_string()).collect(),
+        ..Default::default()
+    }))
+}
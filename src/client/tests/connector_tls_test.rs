@@ -0,0 +1,114 @@
+use native_tls::Identity;
+use openssl::hash::MessageDigest;
+use tokio::net::TcpListener;
+
+use crate::utils::tests::TestCert;
+
+use super::super::{
+    connector::{Connector, TlsConnector},
+    neck_url::NeckUrl,
+    TlsOptions,
+};
+
+/// Start a TLS server with a certificate, and return its URL.
+async fn start_server(cert: &TestCert) -> NeckUrl {
+    let identity = Identity::from_pkcs8(&cert.cert_pem(), &cert.key_pem()).unwrap();
+    let acceptor =
+        tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::new(identity).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let _ = acceptor.accept(stream).await;
+        }
+    });
+
+    NeckUrl::from(format!("https://{}", addr))
+}
+
+fn options() -> TlsOptions {
+    TlsOptions {
+        tls_domain: Some("localhost".to_string()),
+        ..Default::default()
+    }
+}
+
+async fn connect(url: &NeckUrl, tls: &TlsOptions) -> bool {
+    TlsConnector::new(url, tls).unwrap().connect().await.is_ok()
+}
+
+#[tokio::test]
+async fn test_untrusted() {
+    let server = TestCert::self_signed("localhost");
+    let url = start_server(&server).await;
+
+    // The self-signed certificate is not trusted by the system roots.
+    assert!(!connect(&url, &options()).await);
+}
+
+#[tokio::test]
+async fn test_ca_bundle() {
+    let server = TestCert::self_signed("localhost");
+    let url = start_server(&server).await;
+
+    // The bundle contains an unrelated CA before the server certificate.
+    let mut bundle = TestCert::self_signed("other-ca").cert_pem();
+    bundle.extend(server.cert_pem());
+    let path = TestCert::write_temp("bundle.crt", &bundle);
+    let tls = TlsOptions {
+        tls_ca: vec![path.clone()],
+        ..options()
+    };
+    assert!(connect(&url, &tls).await);
+
+    // A file without any certificate is refused.
+    std::fs::write(&path, b"not a certificate\n").unwrap();
+    assert!(TlsConnector::new(&url, &tls).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_pin() {
+    let server = TestCert::self_signed("localhost");
+    let url = start_server(&server).await;
+
+    // The fingerprint is formatted like "openssl x509 -fingerprint -sha256".
+    let fingerprint = server
+        .cert
+        .digest(MessageDigest::sha256())
+        .unwrap()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":");
+    let tls = TlsOptions {
+        tls_pin: vec![fingerprint],
+        ..options()
+    };
+    assert!(connect(&url, &tls).await);
+
+    let tls = TlsOptions {
+        tls_pin: vec!["00".repeat(32)],
+        ..options()
+    };
+    assert!(!connect(&url, &tls).await);
+
+    let tls = TlsOptions {
+        tls_pin: vec!["xyz".to_string()],
+        ..options()
+    };
+    assert!(TlsConnector::new(&url, &tls).is_err());
+}
+
+#[tokio::test]
+async fn test_insecure() {
+    let server = TestCert::self_signed("localhost");
+    let url = start_server(&server).await;
+
+    let tls = TlsOptions {
+        tls_insecure: true,
+        ..options()
+    };
+    assert!(connect(&url, &tls).await);
+}
@@ -0,0 +1,139 @@
+use std::{sync::atomic::Ordering::SeqCst, time::Duration};
+
+use tokio::time::Instant;
+
+use super::super::{
+    connector::TcpConnector, neck_url::NeckUrl, select_endpoint, Endpoint, Health, Selected,
+    ServerMode,
+};
+
+fn endpoints(n: usize) -> Vec<Endpoint> {
+    (0..n)
+        .map(|i| {
+            let url = NeckUrl::from(format!("http://127.0.0.1:{}", 1081 + i));
+            let connector = Box::new(TcpConnector::new(&url));
+            Endpoint::new(url, connector)
+        })
+        .collect()
+}
+
+/// Get the index of the selected server, or the seconds to wait.
+fn select(endpoints: &[Endpoint], mode: ServerMode, now: Instant) -> Result<usize, Option<u64>> {
+    match select_endpoint(endpoints, mode, now) {
+        Selected::Endpoint(e) => Ok(endpoints.iter().position(|v| std::ptr::eq(v, e)).unwrap()),
+        Selected::RetryAt(t) => Err(Some((t - now).as_secs())),
+        Selected::None => Err(None),
+    }
+}
+
+#[test]
+fn test_backoff() {
+    let e = endpoints(1).remove(0);
+    let now = Instant::now();
+    let secs = Duration::from_secs;
+
+    // The backoff doubles in each round, and the other failures in the same round are ignored.
+    e.fail(now);
+    e.fail(now);
+    assert_eq!(e.retry_at(), Some(now + secs(1)));
+    assert!(!e.is_available(now));
+    assert!(e.is_available(now + secs(1)));
+    e.fail(now + secs(1));
+    assert_eq!(e.retry_at(), Some(now + secs(3)));
+    for _ in 0..10 {
+        e.fail(e.retry_at().unwrap());
+    }
+    let retry_at = e.retry_at().unwrap();
+    assert!(matches!(e.health(), Health::Down { failures: 6, .. }));
+
+    // A worker has joined, so the backoff is reset.
+    e.succeed();
+    assert_eq!(e.health(), Health::Up);
+    e.fail(retry_at);
+    assert_eq!(e.retry_at(), Some(retry_at + secs(1)));
+
+    // An incompatible server is never retried.
+    e.reject("Too old".to_string());
+    e.fail(now);
+    assert!(!e.is_available(retry_at + secs(100)));
+}
+
+#[test]
+fn test_failover() {
+    let endpoints = endpoints(3);
+    let now = Instant::now();
+    let mode = ServerMode::Failover;
+
+    // The first available server in order.
+    assert_eq!(select(&endpoints, mode, now), Ok(0));
+    endpoints[0].fail(now);
+    assert_eq!(select(&endpoints, mode, now), Ok(1));
+    endpoints[1].reject("Too old".to_string());
+    assert_eq!(select(&endpoints, mode, now), Ok(2));
+
+    // Wait for the earliest retry if all servers are down.
+    endpoints[2].fail(now);
+    endpoints[2].fail(now + Duration::from_secs(1));
+    assert_eq!(select(&endpoints, mode, now), Err(Some(1)));
+
+    // Go back to the first server when it recovers.
+    assert_eq!(
+        select(&endpoints, mode, now + Duration::from_secs(1)),
+        Ok(0)
+    );
+
+    endpoints[0].reject("Too old".to_string());
+    endpoints[2].reject("Too old".to_string());
+    assert_eq!(select(&endpoints, mode, now), Err(None));
+}
+
+#[test]
+fn test_spread() {
+    let endpoints = endpoints(3);
+    let now = Instant::now();
+    let mode = ServerMode::Spread;
+
+    // The server with the fewest workers, ties are broken by the order.
+    let mut selected = vec![];
+    for _ in 0..6 {
+        let i = select(&endpoints, mode, now).unwrap();
+        endpoints[i].workers.fetch_add(1, SeqCst);
+        selected.push(i);
+    }
+    assert_eq!(selected, vec![0, 1, 2, 0, 1, 2]);
+
+    // The workers are shared by the other servers if one is down.
+    endpoints[1].workers.store(0, SeqCst);
+    endpoints[1].fail(now);
+    assert_eq!(select(&endpoints, mode, now), Ok(0));
+}
+
+#[test]
+fn test_probe() {
+    let endpoints = endpoints(2);
+    let now = Instant::now();
+    let secs = Duration::from_secs;
+    let mode = ServerMode::Failover;
+
+    // Only one worker probes the first server after its backoff, the others stay on the second one.
+    endpoints[0].fail(now);
+    assert_eq!(select(&endpoints, mode, now + secs(1)), Ok(0));
+    assert!(!endpoints[0].is_available(now + secs(1)));
+    assert_eq!(select(&endpoints, mode, now + secs(1)), Ok(1));
+    assert!(endpoints[0].status().contains("down after 1 failures"));
+
+    // The others wait for the probe instead of retrying at once.
+    endpoints[1].reject("Too old".to_string());
+    assert_eq!(select(&endpoints, mode, now + secs(1)), Err(Some(1)));
+
+    // The probe has failed, so the next one waits for the backoff.
+    endpoints[0].fail(now + secs(1));
+    assert_eq!(select(&endpoints, mode, now + secs(1)), Err(Some(2)));
+    assert_eq!(select(&endpoints, mode, now + secs(3)), Ok(0));
+
+    // The probe has joined, so the server is available to all workers.
+    endpoints[0].succeed();
+    assert_eq!(select(&endpoints, mode, now + secs(3)), Ok(0));
+    assert_eq!(select(&endpoints, mode, now + secs(3)), Ok(0));
+    assert_eq!(endpoints[0].status(), "127.0.0.1:1081 up, 0 workers");
+}
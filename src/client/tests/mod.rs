@@ -1 +1,22 @@
 mod token_bucket_test;
+
+#[cfg(test)]
+mod connector_tls_test;
+
+#[cfg(test)]
+mod neck_client_test;
+
+#[cfg(test)]
+mod endpoint_test;
+
+#[cfg(test)]
+mod udp_relay_test;
+
+#[cfg(test)]
+mod bind_relay_test;
+
+#[cfg(test)]
+mod client;
+
+#[cfg(test)]
+pub use client::*;
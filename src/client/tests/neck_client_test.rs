@@ -0,0 +1,60 @@
+use std::sync::{atomic::Ordering::SeqCst, Arc};
+
+use super::super::{ClientOptions, NeckClient};
+
+fn create_client(
+    workers: u32,
+    min_workers: Option<u32>,
+    max_workers: Option<u32>,
+) -> Arc<NeckClient> {
+    Arc::new(NeckClient::new(ClientOptions {
+        urls: vec!["http://127.0.0.1:1".to_string()],
+        workers: Some(workers),
+        min_workers,
+        max_workers,
+        ..Default::default()
+    }))
+}
+
+// NOTE: The spawned workers do not run until the test awaits, so the counters are not changed by them.
+#[tokio::test]
+async fn test_scale() {
+    let ctx = create_client(8, Some(2), Some(20));
+    assert_eq!(ctx.target.load(SeqCst), 8);
+
+    // The target is the idle connections plus the demand, within the range.
+    ctx.idle.store(3, SeqCst);
+    ctx.scale(2);
+    assert_eq!(ctx.target.load(SeqCst), 5);
+    assert_eq!(ctx.running.load(SeqCst), 5);
+    ctx.scale(100);
+    assert_eq!(ctx.target.load(SeqCst), 20);
+    assert_eq!(ctx.running.load(SeqCst), 20);
+
+    // The surplus workers retire one by one.
+    ctx.scale(-100);
+    assert_eq!(ctx.target.load(SeqCst), 2);
+    assert_eq!(ctx.running.load(SeqCst), 20);
+    for _ in 0..18 {
+        assert!(ctx.retire());
+    }
+    assert!(!ctx.retire());
+    assert_eq!(ctx.running.load(SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_scale_range() {
+    // Without a range, the number of workers is fixed.
+    let ctx = create_client(8, None, None);
+    ctx.scale(100);
+    assert_eq!(ctx.target.load(SeqCst), 8);
+    ctx.scale(-100);
+    assert_eq!(ctx.target.load(SeqCst), 8);
+
+    // The range is extended to include the number of workers, if only one bound is given.
+    let ctx = create_client(8, None, Some(4));
+    assert_eq!((ctx.min_workers, ctx.max_workers), (4, 4));
+    assert_eq!(ctx.target.load(SeqCst), 4);
+    let ctx = create_client(8, Some(2), None);
+    assert_eq!((ctx.min_workers, ctx.max_workers), (2, 8));
+}
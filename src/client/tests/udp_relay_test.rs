@@ -0,0 +1,128 @@
+use std::net::SocketAddr;
+
+use tokio::net::UdpSocket;
+
+use crate::{
+    framed::{Frame, DATA},
+    http::{HttpRequest, HttpResponse},
+    socks5::Host,
+    utils::tests::stream_pair,
+};
+
+use super::{super::udp_relay::relay_udp, create_client};
+
+/// Start a UDP echo server at the `addr`, which answers each datagram with a prefix.
+async fn start_echo(addr: &str) -> SocketAddr {
+    let socket = UdpSocket::bind(addr).await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 65536];
+        while let Ok((size, from)) = socket.recv_from(&mut buf).await {
+            let mut reply = b"echo:".to_vec();
+            reply.extend(&buf[..size]);
+            socket.send_to(&reply, from).await.unwrap();
+        }
+    });
+    addr
+}
+
+fn datagram(host: &str, data: &[u8]) -> Frame {
+    let mut payload = Vec::new();
+    Host::from(host.parse::<SocketAddr>().unwrap()).encode(&mut payload);
+    payload.extend(data);
+    Frame::data(&payload)
+}
+
+#[tokio::test]
+async fn test_relay_udp() {
+    let echo = start_echo("127.0.0.1:0").await;
+    let ctx = create_client(&["deny 127.0.0.2"]);
+    let (server, worker) = stream_pair().await;
+
+    let relay = tokio::spawn(async move {
+        let req = HttpRequest::read_from(&worker).await.unwrap();
+        relay_udp(&ctx, &worker, &req).await
+    });
+
+    HttpRequest::new("ASSOCIATE", "0.0.0.0:0", "HTTP/1.1")
+        .write_to_stream(&server)
+        .await
+        .unwrap();
+    let res = HttpResponse::read_from(&server).await.unwrap();
+    assert_eq!(res.get_status(), 200);
+
+    // The denied destination is dropped, and the echo is received with its source.
+    let mut writer = server.writer.lock().await;
+    datagram("127.0.0.2:9", b"lost")
+        .write_to(&mut *writer)
+        .await
+        .unwrap();
+    datagram(&echo.to_string(), b"hello")
+        .write_to(&mut *writer)
+        .await
+        .unwrap();
+    let frame = Frame::read_from(&mut *server.reader.lock().await)
+        .await
+        .unwrap();
+    assert_eq!(frame.kind, DATA);
+    let (source, data) = Host::decode(&frame.payload).unwrap();
+    assert_eq!(source.to_string(), echo.to_string());
+    assert_eq!(data, b"echo:hello");
+
+    // The destinations are remembered, so the later datagrams are treated the same.
+    datagram("127.0.0.2:9", b"lost again")
+        .write_to(&mut *writer)
+        .await
+        .unwrap();
+    datagram(&echo.to_string(), b"again")
+        .write_to(&mut *writer)
+        .await
+        .unwrap();
+    let frame = Frame::read_from(&mut *server.reader.lock().await)
+        .await
+        .unwrap();
+    assert_eq!(Host::decode(&frame.payload).unwrap().1, b"echo:again");
+
+    // The association is ended by an END frame.
+    Frame::end().write_to(&mut *writer).await.unwrap();
+    relay.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_relay_udp_ipv6() {
+    // The host has no IPv6, so there is nothing to relay.
+    if std::net::UdpSocket::bind("[::1]:0").is_err() {
+        return;
+    }
+    let echo = start_echo("[::1]:0").await;
+    let ctx = create_client(&[]);
+    let (server, worker) = stream_pair().await;
+
+    let relay = tokio::spawn(async move {
+        let req = HttpRequest::read_from(&worker).await.unwrap();
+        relay_udp(&ctx, &worker, &req).await
+    });
+
+    HttpRequest::new("ASSOCIATE", "0.0.0.0:0", "HTTP/1.1")
+        .write_to_stream(&server)
+        .await
+        .unwrap();
+    let res = HttpResponse::read_from(&server).await.unwrap();
+    assert_eq!(res.get_status(), 200);
+
+    // The IPv6 destination is relayed by the IPv6 socket.
+    let mut writer = server.writer.lock().await;
+    datagram(&echo.to_string(), b"hello")
+        .write_to(&mut *writer)
+        .await
+        .unwrap();
+    let frame = Frame::read_from(&mut *server.reader.lock().await)
+        .await
+        .unwrap();
+    let (source, data) = Host::decode(&frame.payload).unwrap();
+    assert_eq!(source.to_string(), echo.to_string());
+    assert_eq!(data, b"echo:hello");
+
+    Frame::end().write_to(&mut *writer).await.unwrap();
+    relay.await.unwrap().unwrap();
+}
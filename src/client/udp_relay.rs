@@ -0,0 +1,161 @@
+use std::{collections::HashMap, net::SocketAddr};
+
+use tokio::{io, net::UdpSocket, select};
+
+use std::future::pending;
+
+use crate::{
+    framed::{Frame, END, MAX_PAYLOAD},
+    http::{HttpRequest, HttpResponse},
+    socks5::Host,
+    utils::{Failure, NeckStream, FAILURE_HEADER},
+};
+
+use super::{
+    start_worker::{check_destination, Destination},
+    NeckClient,
+};
+
+/// The maximum number of destinations remembered by an association, they are forgotten all at once when it is full.
+const MAX_DESTINATIONS: usize = 1024;
+
+/// Check and resolve the destination of a datagram, to an address which a relay socket can send to.
+/// An IPv6 address is only used if the IPv6 socket is available (`ipv6`).
+async fn resolve_datagram(ctx: &NeckClient, host: &Host, ipv6: bool) -> Result<SocketAddr, String> {
+    match check_destination(ctx, &host.to_string()).await {
+        Ok(Destination::Forbidden(reason)) => Err(reason),
+        Ok(Destination::Resolved(addrs)) => addrs
+            .into_iter()
+            .find(|addr| addr.is_ipv4() || ipv6)
+            .ok_or_else(|| String::from("No IPv4 address is resolved, and IPv6 is unavailable")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Receive a datagram on the IPv6 socket, or wait forever if it is unavailable.
+async fn recv_from(socket: Option<&UdpSocket>, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+    match socket {
+        Some(socket) => socket.recv_from(buf).await,
+        None => pending().await,
+    }
+}
+
+/// Answer an ASSOCIATE request, and relay the datagrams carried by DATA frames on a UDP socket of each address family,
+/// until the server sends an END frame or the connection is closed.
+/// NOTE: The IPv6 socket is optional, the association works for IPv4 only on a host without IPv6.
+pub async fn relay_udp(ctx: &NeckClient, stream: &NeckStream, req: &HttpRequest) -> io::Result<()> {
+    let socket = match UdpSocket::bind("0.0.0.0:0").await {
+        Ok(it) => it,
+        Err(e) => {
+            HttpResponse::new(503, "Service Unavailable", req.get_version())
+                .add_header_kv(FAILURE_HEADER, Failure::Other.as_str())
+                .add_payload(e.to_string().as_bytes())
+                .add_payload(b"\n")
+                .write_to_stream(stream)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let socket6 = UdpSocket::bind("[::]:0").await.ok();
+
+    match socket6.as_ref().map(UdpSocket::local_addr).transpose()? {
+        Some(addr6) => println!(
+            "[{}] Associate UDP at {} and {}",
+            stream.local_addr,
+            socket.local_addr()?,
+            addr6
+        ),
+        None => println!(
+            "[{}] Associate UDP at {} (IPv6 is unavailable)",
+            stream.local_addr,
+            socket.local_addr()?
+        ),
+    }
+
+    HttpResponse::new(200, "Association Established", req.get_version())
+        .write_to_stream(stream)
+        .await?;
+
+    let (mut r, mut w) = tokio::join!(stream.reader.lock(), stream.writer.lock());
+
+    // Send each datagram from the server to its destination, the refused ones are dropped.
+    // Each destination is checked and resolved once for the association, None if it is refused.
+    let outgoing = async {
+        let mut destinations: HashMap<String, Option<SocketAddr>> = HashMap::new();
+        while let Ok(frame) = Frame::read_from(&mut *r).await {
+            if frame.kind == END {
+                break;
+            }
+            let Some((host, data)) = Host::decode(&frame.payload) else {
+                continue;
+            };
+            let key = host.to_string();
+            let addr = match destinations.get(&key) {
+                Some(it) => *it,
+                None => {
+                    let addr = match resolve_datagram(ctx, &host, socket6.is_some()).await {
+                        Ok(it) => Some(it),
+                        Err(reason) => {
+                            println!(
+                                "[{}] Refused to send to {}: {}",
+                                stream.local_addr, host, reason
+                            );
+                            None
+                        }
+                    };
+                    if destinations.len() >= MAX_DESTINATIONS {
+                        destinations.clear();
+                    }
+                    destinations.insert(key, addr);
+                    addr
+                }
+            };
+            let socket = match addr {
+                Some(SocketAddr::V4(_)) => Some(&socket),
+                Some(SocketAddr::V6(_)) => socket6.as_ref(),
+                None => None,
+            };
+            if let Some((socket, addr)) = socket.zip(addr) {
+                let _ = socket.send_to(data, addr).await;
+            }
+        }
+    };
+
+    // Send each datagram received by the sockets to the server, with its source.
+    let incoming = async {
+        let mut buf = vec![0; 65536];
+        let mut buf6 = vec![0; 65536];
+        loop {
+            let (data, from) = select! {
+                v = socket.recv_from(&mut buf) => match v {
+                    Ok((size, from)) => (&buf[..size], from),
+                    Err(_) => break,
+                },
+                v = recv_from(socket6.as_ref(), &mut buf6) => match v {
+                    Ok((size, from)) => (&buf6[..size], from),
+                    Err(_) => break,
+                },
+            };
+            // An IPv4-mapped source is reported as IPv4.
+            let from = SocketAddr::new(from.ip().to_canonical(), from.port());
+            let mut payload = Vec::new();
+            Host::from(from).encode(&mut payload);
+            payload.extend(data);
+            if payload.len() > MAX_PAYLOAD {
+                continue;
+            }
+            if Frame::data(&payload).write_to(&mut *w).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    select! {
+        _ = outgoing => (),
+        _ = incoming => (),
+    }
+
+    println!("[{}] UDP association ended", stream.local_addr);
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+mod tests;
+
+/// The capability name of the framed mode, which is negotiated with the `Neck-Capabilities` header when joining.
+pub const FRAMED: &str = "framed";
+
+/// A DATA frame carries a chunk of the tunnel data.
+pub const DATA: u8 = 0;
+/// An END frame indicates that the sender will not send any DATA frame for the current tunnel.
+pub const END: u8 = 1;
+
+/// The maximum payload size of a frame.
+pub const MAX_PAYLOAD: usize = 16 * 1024;
+
+/// A frame of the framed mode, which is encoded as a 1-byte kind, a 2-byte big-endian length, and the payload.
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub kind: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn data(payload: &[u8]) -> Self {
+        Self {
+            kind: DATA,
+            payload: payload.to_vec(),
+        }
+    }
+
+    pub fn end() -> Self {
+        Self {
+            kind: END,
+            payload: Vec::new(),
+        }
+    }
+
+    pub async fn read_from<T: AsyncRead + Unpin>(reader: &mut T) -> io::Result<Self> {
+        let kind = reader.read_u8().await?;
+        if kind != DATA && kind != END {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown frame kind {}", kind),
+            ));
+        }
+        let size = reader.read_u16().await? as usize;
+        if size > MAX_PAYLOAD {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Frame too long"));
+        }
+        let mut payload = vec![0; size];
+        reader.read_exact(&mut payload).await?;
+        Ok(Self { kind, payload })
+    }
+
+    pub async fn write_to<T: AsyncWrite + Unpin>(&self, writer: &mut T) -> io::Result<()> {
+        // Write the whole frame at once, to avoid sending the header in a separate packet.
+        let mut buf = Vec::with_capacity(3 + self.payload.len());
+        buf.push(self.kind);
+        buf.extend((self.payload.len() as u16).to_be_bytes());
+        buf.extend(&self.payload);
+        writer.write_all(&buf).await?;
+        writer.flush().await
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    http::HttpRequest,
+    utils::{tests::stream_pair, NeckStream},
+};
+
+use super::super::{Frame, DATA, END, MAX_PAYLOAD};
+
+async fn read_frame(stream: &NeckStream) -> Frame {
+    Frame::read_from(&mut *stream.reader.lock().await)
+        .await
+        .unwrap()
+}
+
+async fn read_all(stream: &NeckStream) -> Vec<u8> {
+    let mut buf = Vec::new();
+    stream
+        .reader
+        .lock()
+        .await
+        .read_to_end(&mut buf)
+        .await
+        .unwrap();
+    buf
+}
+
+#[tokio::test]
+async fn test_round_trip() {
+    let mut buf = Vec::new();
+    Frame::data(b"hello").write_to(&mut buf).await.unwrap();
+    Frame::end().write_to(&mut buf).await.unwrap();
+    assert_eq!(buf, b"\x00\x00\x05hello\x01\x00\x00");
+
+    let mut reader = &buf[..];
+    assert_eq!(
+        Frame::read_from(&mut reader).await.unwrap(),
+        Frame::data(b"hello")
+    );
+    assert_eq!(Frame::read_from(&mut reader).await.unwrap(), Frame::end());
+    assert!(Frame::read_from(&mut reader).await.is_err());
+}
+
+#[tokio::test]
+async fn test_bad_frames() {
+    // Unknown kind.
+    assert!(Frame::read_from(&mut &b"\x07\x00\x00"[..]).await.is_err());
+
+    // Too long.
+    let size = (MAX_PAYLOAD as u16 + 1).to_be_bytes();
+    assert!(Frame::read_from(&mut &[DATA, size[0], size[1]][..])
+        .await
+        .is_err());
+
+    // Truncated payload.
+    assert!(Frame::read_from(&mut &b"\x00\x00\x05hel"[..])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_weld_framed() {
+    let (link, peer) = stream_pair().await;
+    let (local, remote) = stream_pair().await;
+    let link = Arc::new(link);
+
+    let l = link.clone();
+    let weld = tokio::spawn(async move { local.weld_framed(&l).await });
+
+    // The local data is carried by DATA frames.
+    remote
+        .writer
+        .lock()
+        .await
+        .write_all(b"hello")
+        .await
+        .unwrap();
+    assert_eq!(read_frame(&peer).await, Frame::data(b"hello"));
+
+    // DATA frames from the peer are unwrapped.
+    Frame::data(b"world")
+        .write_to(&mut *peer.writer.lock().await)
+        .await
+        .unwrap();
+    let mut buf = [0; 5];
+    remote
+        .reader
+        .lock()
+        .await
+        .read_exact(&mut buf)
+        .await
+        .unwrap();
+    assert_eq!(&buf, b"world");
+
+    // The local EOF is sent as an END frame, but the peer can still send data until its END frame.
+    remote.shutdown().await.unwrap();
+    assert_eq!(read_frame(&peer).await.kind, END);
+    {
+        let mut writer = peer.writer.lock().await;
+        Frame::data(b"bye").write_to(&mut *writer).await.unwrap();
+        Frame::end().write_to(&mut *writer).await.unwrap();
+    }
+    assert_eq!(read_all(&remote).await, b"bye");
+    assert!(weld.await.unwrap().is_ok());
+
+    // The link is clean, and can be used for the next request.
+    HttpRequest::new("CONNECT", "example.com:443", "HTTP/1.1")
+        .write_to_stream(&link)
+        .await
+        .unwrap();
+    assert_eq!(
+        HttpRequest::read_from(&peer).await.unwrap().get_uri(),
+        "example.com:443"
+    );
+}
+
+#[tokio::test]
+async fn test_weld_framed_peer_end() {
+    let (link, peer) = stream_pair().await;
+    let (local, remote) = stream_pair().await;
+
+    let weld = tokio::spawn(async move { local.weld_framed(&link).await });
+
+    // The END frame from the peer closes the local side, and is answered with an END frame.
+    Frame::end()
+        .write_to(&mut *peer.writer.lock().await)
+        .await
+        .unwrap();
+    assert_eq!(read_frame(&peer).await.kind, END);
+    assert!(read_all(&remote).await.is_empty());
+    assert!(weld.await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_weld_framed_broken_link() {
+    let (link, peer) = stream_pair().await;
+    let (local, _remote) = stream_pair().await;
+
+    let weld = tokio::spawn(async move { local.weld_framed(&link).await });
+
+    // The link is closed without an END frame.
+    drop(peer);
+    assert!(weld.await.unwrap().is_err());
+}
@@ -0,0 +1,356 @@
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use tokio::io::{
+    self, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+    BufReader, ReadBuf,
+};
+
+use super::Headers;
+
+/// Maximun allowed size for a chunk size line or a trailer line.
+const MAX_LINE: u64 = 16 * 1024;
+
+/// How the end of a message body is determined (RFC 9112 section 6.3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyLength {
+    /// There is no body, such as a GET request, the response of a HEAD request or a 304 response.
+    Empty,
+    /// The body has a Content-Length.
+    Fixed(u64),
+    /// The body is sent with the chunked transfer coding.
+    Chunked,
+    /// The body ends when the connection is closed, which is only possible for a response.
+    UntilClose,
+}
+
+fn bad_request(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn truncated(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, message.to_string())
+}
+
+/// Check if the last transfer coding is chunked, None if there is no Transfer-Encoding.
+/// NOTE: The codings may be split into several headers, so the last one is in the last header.
+pub(crate) fn is_chunked(headers: &Headers) -> Option<bool> {
+    let codings = headers.get_header_values("Transfer-Encoding").last()?;
+    let last = codings.rsplit(',').next().unwrap_or_default();
+    Some(last.trim().eq_ignore_ascii_case("chunked"))
+}
+
+/// Parse a number of 1*DIGIT (or 1*HEXDIG for the radix 16).
+/// NOTE: Unlike `u64::from_str_radix`, a sign is not accepted, which another parser may read differently.
+fn parse_number(s: &str, radix: u32) -> Option<u64> {
+    if s.is_empty() || !s.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    u64::from_str_radix(s, radix).ok()
+}
+
+/// Get the Content-Length, None if there is no Content-Length.
+/// NOTE: A duplicate Content-Length is rejected even if the values are the same, because only the first one is checked.
+fn get_content_length(headers: &Headers) -> io::Result<Option<u64>> {
+    let mut values = headers.get_header_values("Content-Length");
+    let Some(value) = values.next() else {
+        return Ok(None);
+    };
+    if values.next().is_some() {
+        return Err(bad_request("Duplicate Content-Length"));
+    }
+    match parse_number(value.trim_end(), 10) {
+        Some(it) => Ok(Some(it)),
+        None => Err(bad_request("Bad Content-Length")),
+    }
+}
+
+impl BodyLength {
+    /// Get the body length of a request.
+    /// NOTE: A request with both Transfer-Encoding and Content-Length is rejected, instead of being forwarded with both,
+    /// so that the upstream cannot frame it differently (RFC 9112 section 6.1). So is a request without chunked as the last coding.
+    pub fn of_request(headers: &Headers) -> io::Result<Self> {
+        match is_chunked(headers) {
+            Some(_) if headers.get_header_value("Content-Length").is_some() => {
+                return Err(bad_request("Both Transfer-Encoding and Content-Length"))
+            }
+            Some(true) => return Ok(Self::Chunked),
+            Some(false) => return Err(bad_request("Bad Transfer-Encoding")),
+            None => (),
+        }
+        Ok(match get_content_length(headers)? {
+            Some(0) | None => Self::Empty,
+            Some(len) => Self::Fixed(len),
+        })
+    }
+
+    /// Get the body length of a response to a request with the `method`.
+    pub fn of_response(method: &str, status: u16, headers: &Headers) -> io::Result<Self> {
+        if method == "HEAD" || (100..200).contains(&status) || status == 204 || status == 304 {
+            return Ok(Self::Empty);
+        }
+        match is_chunked(headers) {
+            Some(true) => return Ok(Self::Chunked),
+            Some(false) => return Ok(Self::UntilClose),
+            None => (),
+        }
+        Ok(match get_content_length(headers)? {
+            Some(0) => Self::Empty,
+            Some(len) => Self::Fixed(len),
+            None => Self::UntilClose,
+        })
+    }
+}
+
+/// Parse a chunk size line, where the size in hex is optionally followed by extensions after a semicolon.
+/// NOTE: The line is passed through as it is, so nothing but hex digits is accepted in the size.
+fn parse_chunk_size(line: &[u8]) -> io::Result<u64> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end_matches(['\r', '\n']);
+    let size = line.split(';').next().unwrap_or_default();
+    parse_number(size, 16).ok_or_else(|| bad_request("Bad chunk size"))
+}
+
+/// Check if a line (including the line break) is empty.
+fn is_empty_line(line: &[u8]) -> bool {
+    line == b"\r\n" || line == b"\n"
+}
+
+/// The position in a body, which is kept between polls.
+enum State {
+    /// Reading a body with the remaining size.
+    Fixed(u64),
+    /// Reading a chunk size line.
+    ChunkSize,
+    /// Reading chunk data with the remaining size.
+    ChunkData(u64),
+    /// Reading the CRLF after chunk data.
+    ChunkEnd,
+    /// Skipping the trailer lines after the last chunk.
+    Trailers,
+    /// Reading until the connection is closed.
+    UntilClose,
+    Done,
+}
+
+/// A streaming body, which reads the content of a message from the reader by its length without buffering it.
+/// The chunked transfer coding is decoded, and the trailers after the last chunk are discarded.
+/// NOTE: The trailers cannot be merged into the header section which has been sent (RFC 9110 section 6.5.1),
+/// a body is passed through with `copy_body` to keep them.
+pub struct Body<'a, R> {
+    reader: &'a mut BufReader<R>,
+    state: State,
+    /// The partial line which has been read.
+    line: Vec<u8>,
+}
+
+impl<'a, R: AsyncRead + Unpin> Body<'a, R> {
+    pub fn new(reader: &'a mut BufReader<R>, length: BodyLength) -> Self {
+        let state = match length {
+            BodyLength::Empty => State::Done,
+            BodyLength::Fixed(len) => State::Fixed(len),
+            BodyLength::Chunked => State::ChunkSize,
+            BodyLength::UntilClose => State::UntilClose,
+        };
+        Self {
+            reader,
+            state,
+            line: Vec::new(),
+        }
+    }
+
+    /// Poll a line including the line break.
+    fn poll_line(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Vec<u8>>> {
+        loop {
+            let buf = ready!(Pin::new(&mut *self.reader).poll_fill_buf(cx))?;
+            if buf.is_empty() {
+                return Poll::Ready(Err(truncated("Unterminated line")));
+            }
+            let (size, done) = match buf.iter().position(|c| *c == b'\n') {
+                Some(pos) => (pos + 1, true),
+                None => (buf.len(), false),
+            };
+            self.line.extend(&buf[..size]);
+            Pin::new(&mut *self.reader).consume(size);
+            if done {
+                return Poll::Ready(Ok(std::mem::take(&mut self.line)));
+            }
+            if self.line.len() as u64 > MAX_LINE {
+                return Poll::Ready(Err(bad_request("Line too long")));
+            }
+        }
+    }
+
+    /// Poll the data of the body, at most `remaining` bytes, and return the size which has been read.
+    fn poll_data(
+        &mut self,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+        remaining: u64,
+    ) -> Poll<io::Result<u64>> {
+        let buf = ready!(Pin::new(&mut *self.reader).poll_fill_buf(cx))?;
+        if buf.is_empty() {
+            return Poll::Ready(Err(truncated("Body is truncated")));
+        }
+        let remaining = usize::try_from(remaining).unwrap_or(usize::MAX);
+        let size = buf.len().min(out.remaining()).min(remaining);
+        out.put_slice(&buf[..size]);
+        Pin::new(&mut *self.reader).consume(size);
+        Poll::Ready(Ok(size as u64))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Body<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if out.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            match this.state {
+                State::Fixed(0) => this.state = State::Done,
+                State::Fixed(remaining) => {
+                    let size = ready!(this.poll_data(cx, out, remaining))?;
+                    this.state = State::Fixed(remaining - size);
+                    return Poll::Ready(Ok(()));
+                }
+                State::ChunkSize => {
+                    let line = ready!(this.poll_line(cx))?;
+                    this.state = match parse_chunk_size(&line)? {
+                        0 => State::Trailers,
+                        size => State::ChunkData(size),
+                    };
+                }
+                State::ChunkData(remaining) => {
+                    let size = ready!(this.poll_data(cx, out, remaining))?;
+                    this.state = match remaining - size {
+                        0 => State::ChunkEnd,
+                        rest => State::ChunkData(rest),
+                    };
+                    return Poll::Ready(Ok(()));
+                }
+                State::ChunkEnd => {
+                    let line = ready!(this.poll_line(cx))?;
+                    if !is_empty_line(&line) {
+                        return Poll::Ready(Err(bad_request("Bad chunk")));
+                    }
+                    this.state = State::ChunkSize;
+                }
+                State::Trailers => {
+                    let line = ready!(this.poll_line(cx))?;
+                    if is_empty_line(&line) {
+                        this.state = State::Done;
+                    }
+                }
+                State::UntilClose => return Pin::new(&mut *this.reader).poll_read(cx, out),
+                State::Done => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+/// A writer of a body with the chunked transfer coding, where each write is sent as a chunk.
+pub struct ChunkedWriter<'a, W> {
+    writer: &'a mut W,
+}
+
+impl<'a, W: AsyncWrite + Unpin> ChunkedWriter<'a, W> {
+    pub fn new(writer: &'a mut W) -> Self {
+        Self { writer }
+    }
+
+    /// Write a chunk, nothing is written for empty data because an empty chunk is the last chunk.
+    pub async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.writer
+            .write_all(format!("{:x}\r\n", data.len()).as_bytes())
+            .await?;
+        self.writer.write_all(data).await?;
+        self.writer.write_all(b"\r\n").await
+    }
+
+    /// Write the last chunk with the trailers.
+    pub async fn finish(self, trailers: &Headers) -> io::Result<()> {
+        self.writer.write_all(b"0\r\n").await?;
+        trailers.write_to(self.writer).await?;
+        self.writer.write_all(b"\r\n").await?;
+        self.writer.flush().await
+    }
+}
+
+/// Read a line including the CRLF, which is kept as raw bytes to be passed through.
+async fn read_raw_line<T: AsyncRead + Unpin>(reader: &mut BufReader<T>) -> io::Result<Vec<u8>> {
+    let mut line = Vec::new();
+    (&mut *reader)
+        .take(MAX_LINE)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if !line.ends_with(b"\n") {
+        return Err(truncated("Unterminated line"));
+    }
+    Ok(line)
+}
+
+/// Copy exactly `len` bytes.
+async fn copy_exact<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    len: u64,
+) -> io::Result<()> {
+    let copied = io::copy(&mut (&mut *reader).take(len), writer).await?;
+    if copied < len {
+        return Err(truncated("Body is truncated"));
+    }
+    Ok(())
+}
+
+/// Copy a body from the `reader` to the `writer` by its length, without buffering it.
+/// Unlike reading a `Body`, the chunked transfer coding is passed through as it is, including the chunk extensions and trailers.
+pub async fn copy_body<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    length: BodyLength,
+) -> io::Result<()> {
+    match length {
+        BodyLength::Empty => (),
+        BodyLength::Fixed(len) => copy_exact(reader, writer, len).await?,
+        BodyLength::UntilClose => {
+            io::copy(reader, writer).await?;
+        }
+        BodyLength::Chunked => loop {
+            // The size line is checked before it is passed through, so a malformed one never reaches the writer.
+            let line = read_raw_line(reader).await?;
+            let size = parse_chunk_size(&line)?;
+            writer.write_all(&line).await?;
+
+            // The last chunk is followed by the trailers, which end with an empty line.
+            if size == 0 {
+                loop {
+                    let line = read_raw_line(reader).await?;
+                    writer.write_all(&line).await?;
+                    if is_empty_line(&line) {
+                        break;
+                    }
+                }
+                break;
+            }
+
+            // The chunk data is followed by a CRLF.
+            copy_exact(reader, writer, size).await?;
+            let line = read_raw_line(reader).await?;
+            if !is_empty_line(&line) {
+                return Err(bad_request("Bad chunk"));
+            }
+            writer.write_all(&line).await?;
+        },
+    }
+    writer.flush().await
+}
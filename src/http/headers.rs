@@ -90,6 +90,14 @@ impl Headers {
             .map(|v| v.get_value())
     }
 
+    /// Get all values of a header by name (case-insensitive), in the order of the rows.
+    pub fn get_header_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.0
+            .iter()
+            .filter(move |l| l.eq_name(name))
+            .map(|v| v.get_value())
+    }
+
     /// Set a header value by name (case-insensitive).
     #[allow(unused)]
     pub fn set_header(&mut self, name: &str, value: &str) {
@@ -1,3 +1,4 @@
+mod body;
 mod first_line;
 mod headers;
 mod protocol;
@@ -7,6 +8,7 @@ mod utils;
 
 mod tests;
 
+pub use body::*;
 pub use first_line::*;
 pub use headers::*;
 pub use protocol::*;
@@ -5,34 +5,36 @@ use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::io::BufReader;
 
 use super::utils::read_lines;
-use super::{FirstLine, HeaderRow, Headers};
+use super::{is_chunked, Body, BodyLength, ChunkedWriter, FirstLine, HeaderRow, Headers};
+
+/// Maximun allowed size for a payload which is read as a whole, larger bodies must be read as a `Body`.
+const MAX_PAYLOAD: u64 = 16 * 1024;
 
 // Read payload as a Vec<u8>.
+// NOTE: A message without Content-Length or chunked coding is regarded as no payload, instead of reading until closed,
+// because the messages between the server and workers are followed by the tunnel on the same connection.
 async fn read_payload<T: AsyncRead + Unpin>(
     stream: &mut BufReader<T>,
     headers: &Headers,
 ) -> io::Result<Vec<u8>> {
-    let mut buf = Vec::<u8>::new();
-    // Get the Content-Length field.
-    if let Some(value) = headers.get_header_value("Content-Length") {
-        // Parse it into a integer.
-        let len = match value.parse::<u64>() {
-            Ok(it) => it,
-            Err(_) => Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Bad Content-Length",
-            ))?,
-        };
-        // Check body size limit.
-        if len > 16 * 1024u64 {
+    let length = match BodyLength::of_request(headers) {
+        Ok(BodyLength::Fixed(len)) if len > MAX_PAYLOAD => {
             Err(io::Error::new(io::ErrorKind::OutOfMemory, "Body too long"))?
         }
-        if len > 0 {
-            // Read bytes.
-            stream.take(len).read_to_end(&mut buf).await?;
-        }
+        Ok(it) => it,
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidInput, e))?,
+    };
+
+    // Read bytes, one more byte is read to check the size limit of a chunked body.
+    let mut buf = Vec::<u8>::new();
+    Body::new(stream, length)
+        .take(MAX_PAYLOAD + 1)
+        .read_to_end(&mut buf)
+        .await?;
+    if buf.len() as u64 > MAX_PAYLOAD {
+        Err(io::Error::new(io::ErrorKind::OutOfMemory, "Body too long"))?
     }
-    return Ok(buf);
+    Ok(buf)
 }
 
 pub trait HttpCommon {
@@ -122,11 +124,28 @@ impl HttpProtocol {
         self
     }
 
-    /// Write all data to an AsyncWrite
+    /// Write all data to an AsyncWrite.
+    /// If the headers declare the chunked coding, the payload is sent as a chunk,
+    /// otherwise the Content-Length is recalculated for the payload.
+    /// Without a payload, the headers are passed through, so that the body can be streamed after them.
     pub async fn write_to<T: AsyncWrite + Unpin>(&self, w: &mut T) -> io::Result<()> {
         self.first_line.write_to(w).await?;
 
+        let chunked = is_chunked(&self.headers) == Some(true);
         match self.payload.as_ref() {
+            // Pass the headers through, the payload is encoded by the declared coding.
+            Some(payload) if chunked => {
+                for h in self.headers.iter() {
+                    if !h.eq_name("Content-Length") {
+                        h.write_to(w).await?;
+                    }
+                }
+                w.write_all(b"\r\n").await?;
+
+                let mut chunks = ChunkedWriter::new(w);
+                chunks.write(payload).await?;
+                return chunks.finish(&Headers::from(Vec::new())).await;
+            }
             // Recalculate the actual value of Content-Length.
             Some(payload) => {
                 let mut content_type_sent = false;
@@ -0,0 +1,181 @@
+use tokio::io::{AsyncReadExt, BufReader};
+
+use super::super::{
+    copy_body, Body, BodyLength, ChunkedWriter, Headers, HttpProtocol, HttpResponse,
+};
+
+fn headers(rows: &[&str]) -> Headers {
+    rows.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_request_length() {
+    let of = |rows: &[&str]| BodyLength::of_request(&headers(rows)).ok();
+    assert_eq!(of(&[]), Some(BodyLength::Empty));
+    assert_eq!(of(&["Content-Length: 0"]), Some(BodyLength::Empty));
+    assert_eq!(of(&["Content-Length: 12"]), Some(BodyLength::Fixed(12)));
+    assert_eq!(of(&["Content-Length: x"]), None);
+
+    // Only digits are accepted, and a duplicate Content-Length is rejected.
+    assert_eq!(of(&["Content-Length: +12"]), None);
+    assert_eq!(of(&["Content-Length: 1 2"]), None);
+    assert_eq!(of(&["Content-Length: 12, 12"]), None);
+    assert_eq!(of(&["Content-Length: 12", "Content-Length: 12"]), None);
+    assert_eq!(of(&["Content-Length: 12", "Content-Length: 13"]), None);
+
+    // Chunked must be the last coding, even if the codings are split into several headers.
+    let chunked = ["Transfer-Encoding: gzip", "Transfer-Encoding: chunked"];
+    assert_eq!(of(&chunked), Some(BodyLength::Chunked));
+    assert_eq!(of(&["Transfer-Encoding: chunked, gzip"]), None);
+    assert_eq!(of(&["Transfer-Encoding: gzip"]), None);
+
+    // A request with both Transfer-Encoding and Content-Length is rejected, so that it cannot be framed differently.
+    let both = ["Transfer-Encoding: gzip, chunked", "Content-Length: 12"];
+    assert_eq!(of(&both), None);
+}
+
+#[test]
+fn test_response_length() {
+    let of = |method, status, rows: &[&str]| {
+        BodyLength::of_response(method, status, &headers(rows)).unwrap()
+    };
+    assert_eq!(of("GET", 200, &[]), BodyLength::UntilClose);
+    assert_eq!(of("GET", 200, &["Content-Length: 5"]), BodyLength::Fixed(5));
+    assert_eq!(
+        of("GET", 200, &["Transfer-Encoding: chunked"]),
+        BodyLength::Chunked
+    );
+    assert_eq!(
+        of("GET", 200, &["Transfer-Encoding: gzip"]),
+        BodyLength::UntilClose
+    );
+
+    // Some responses never have a body.
+    assert_eq!(of("HEAD", 200, &["Content-Length: 5"]), BodyLength::Empty);
+    assert_eq!(of("GET", 204, &[]), BodyLength::Empty);
+    assert_eq!(of("GET", 304, &["Content-Length: 5"]), BodyLength::Empty);
+}
+
+async fn copy(raw: &[u8], length: BodyLength) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut reader = BufReader::new(raw);
+    let mut body = Vec::new();
+    copy_body(&mut reader, &mut body, length).await?;
+    let rest = reader.buffer().to_vec();
+    Ok((body, rest))
+}
+
+#[tokio::test]
+async fn test_copy_body() {
+    let (body, rest) = copy(b"helloNEXT", BodyLength::Fixed(5)).await.unwrap();
+    assert_eq!(body, b"hello");
+    assert_eq!(rest, b"NEXT");
+    assert!(copy(b"hel", BodyLength::Fixed(5)).await.is_err());
+
+    // The chunked body is passed through with the extensions and trailers, and stops at its end.
+    let chunked = b"5\r\nhello\r\n6;x=1\r\n world\r\n0\r\nX-Trailer: 1\r\n\r\n";
+    let raw = [&chunked[..], b"NEXT"].concat();
+    let (body, rest) = copy(&raw, BodyLength::Chunked).await.unwrap();
+    assert_eq!(body, chunked);
+    assert_eq!(rest, b"NEXT");
+
+    assert!(copy(b"z\r\n", BodyLength::Chunked).await.is_err());
+    assert!(copy(b"+5\r\nhello\r\n0\r\n\r\n", BodyLength::Chunked)
+        .await
+        .is_err());
+    assert!(copy(b" 5\r\nhello\r\n0\r\n\r\n", BodyLength::Chunked)
+        .await
+        .is_err());
+    assert!(copy(b"5 ;x=1\r\nhello\r\n0\r\n\r\n", BodyLength::Chunked)
+        .await
+        .is_err());
+
+    // A malformed size line is not passed through.
+    let mut body = Vec::new();
+    let raw = b"5\r\nhello\r\n+5\r\nworld\r\n0\r\n\r\n";
+    assert!(copy_body(
+        &mut BufReader::new(&raw[..]),
+        &mut body,
+        BodyLength::Chunked
+    )
+    .await
+    .is_err());
+    assert_eq!(body, b"5\r\nhello\r\n");
+    assert!(copy(b"5\r\nhelloX\r\n", BodyLength::Chunked).await.is_err());
+    assert!(copy(b"5\r\nhel", BodyLength::Chunked).await.is_err());
+}
+
+async fn decode(raw: &[u8], length: BodyLength) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut reader = BufReader::new(raw);
+    let mut content = Vec::new();
+    Body::new(&mut reader, length)
+        .read_to_end(&mut content)
+        .await?;
+    let rest = reader.buffer().to_vec();
+    Ok((content, rest))
+}
+
+#[tokio::test]
+async fn test_body() {
+    let (content, rest) = decode(b"helloNEXT", BodyLength::Fixed(5)).await.unwrap();
+    assert_eq!(content, b"hello");
+    assert_eq!(rest, b"NEXT");
+    assert!(decode(b"hel", BodyLength::Fixed(5)).await.is_err());
+
+    let (content, _) = decode(b"until closed", BodyLength::UntilClose)
+        .await
+        .unwrap();
+    assert_eq!(content, b"until closed");
+
+    // The chunked coding is decoded, and the trailers are skipped.
+    let raw = b"5\r\nhello\r\n6;x=1\r\n world\r\n0\r\nX-Trailer: 1\r\n\r\nNEXT";
+    let (content, rest) = decode(raw, BodyLength::Chunked).await.unwrap();
+    assert_eq!(content, b"hello world");
+    assert_eq!(rest, b"NEXT");
+
+    assert!(decode(b"5\r\nhelloX\r\n", BodyLength::Chunked)
+        .await
+        .is_err());
+    assert!(decode(b"5\r\nhello\r\n", BodyLength::Chunked)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_chunked_writer() {
+    let mut buf = Vec::new();
+    let mut writer = ChunkedWriter::new(&mut buf);
+    writer.write(b"hello").await.unwrap();
+    writer.write(b"").await.unwrap();
+    writer.write(&[b'x'; 26]).await.unwrap();
+    let trailers: Headers = vec!["X-Trailer: 1".to_string()].into();
+    writer.finish(&trailers).await.unwrap();
+
+    let mut expected = b"5\r\nhello\r\n1a\r\n".to_vec();
+    expected.extend([b'x'; 26]);
+    expected.extend(b"\r\n0\r\nX-Trailer: 1\r\n\r\n");
+    assert_eq!(buf, expected);
+
+    let (content, rest) = decode(&buf, BodyLength::Chunked).await.unwrap();
+    assert_eq!(content.len(), 31);
+    assert!(rest.is_empty());
+}
+
+#[tokio::test]
+async fn test_chunked_payload() {
+    // A payload is sent as a chunk if the chunked coding is declared, without a Content-Length.
+    let mut res = HttpResponse::new(200, "OK", "HTTP/1.1");
+    res.add_header("Transfer-Encoding: chunked")
+        .add_header("Content-Length: 1")
+        .add_payload(b"hello");
+    let mut buf = Vec::new();
+    res.write_to(&mut buf).await.unwrap();
+    let text = String::from_utf8(buf.clone()).unwrap();
+    assert!(!text.contains("Content-Length"));
+    assert!(text.ends_with("\r\n\r\n5\r\nhello\r\n0\r\n\r\n"));
+
+    // And it is read back as a whole.
+    let res = HttpProtocol::read_from(&mut BufReader::new(&buf[..]))
+        .await
+        .unwrap();
+    assert_eq!(res.payload.as_deref(), Some(&b"hello"[..]));
+}
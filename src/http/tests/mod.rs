@@ -1,3 +1,6 @@
+#[cfg(test)]
+mod body_test;
+
 #[cfg(test)]
 mod first_line_test;
 
@@ -1,10 +1,14 @@
 use clap::{Parser, Subcommand};
-use client::NeckClient;
-use server::{NeckServer, Starter};
+use client::{ClientOptions, NeckClient};
+use server::{NeckServer, ServerOptions, Starter};
 
 mod client;
+mod framed;
 mod http;
+mod mux;
+mod rules;
 mod server;
+mod socks4;
 mod socks5;
 mod utils;
 
@@ -18,35 +22,9 @@ struct Args {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Start a Neck HTTP proxy server
-    Serve {
-        /// Binding the listening address defaults "0.0.0.0:1081"
-        addr: Option<String>,
-
-        /// The maximum allowed number of workers defaults 200.
-        #[arg(long)]
-        max_workers: Option<u32>,
-
-        /// Proxy directly from the server without creating a worker pool.
-        #[clap(long, action)]
-        direct: bool,
-    },
+    Serve(ServerOptions),
     /// Create some worker connections and join the pool of the server
-    Join {
-        /// Proxy server URL.
-        url: String,
-
-        /// The number of maximum provided connections defaults 200
-        #[arg(short, long)]
-        connections: Option<u32>,
-
-        /// The number of concurrent workers defaults 8.
-        #[arg(short, long)]
-        workers: Option<u32>,
-
-        /// Specify the domain for TLS, using the hostname of addr by default.
-        #[arg(long)]
-        tls_domain: Option<String>,
-    },
+    Join(ClientOptions),
 }
 
 #[tokio::main]
@@ -54,25 +32,14 @@ async fn main() {
     let args = Args::parse();
 
     match args.command {
-        Commands::Serve {
-            addr,
-            max_workers,
-            direct,
-        } => {
+        Commands::Serve(options) => {
             // Start server
-            NeckServer::new(addr, direct, max_workers).start().await;
+            NeckServer::new(options).start().await;
         }
 
-        Commands::Join {
-            url,
-            connections,
-            workers,
-            tls_domain,
-        } => {
+        Commands::Join(options) => {
             // Start client
-            NeckClient::new(url, workers, connections, tls_domain)
-                .start()
-                .await;
+            NeckClient::new(options).start().await;
         }
     }
 }
@@ -0,0 +1,80 @@
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::framed::MAX_PAYLOAD;
+
+/// A DATA frame carries a chunk of the stream data.
+pub const DATA: u8 = 0;
+/// A FIN frame indicates that the sender will not send any DATA frame on the stream (half-close).
+pub const FIN: u8 = 1;
+/// An OPEN frame opens a new stream, which is sent by the server only.
+pub const OPEN: u8 = 2;
+/// A WINDOW frame grants the peer more bytes to send on the stream, with a 4-byte big-endian increment.
+pub const WINDOW: u8 = 3;
+/// A RST frame aborts the stream in both directions.
+pub const RST: u8 = 4;
+/// A PING frame must be answered with a PONG frame, the stream ID is always 0.
+pub const PING: u8 = 5;
+pub const PONG: u8 = 6;
+
+/// A frame of the multiplexed mode,
+/// which is encoded as a 1-byte kind, a 4-byte big-endian stream ID, a 2-byte big-endian length, and the payload.
+#[derive(Debug, PartialEq)]
+pub struct MuxFrame {
+    pub kind: u8,
+    pub id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl MuxFrame {
+    pub fn new(kind: u8, id: u32, payload: &[u8]) -> Self {
+        Self {
+            kind,
+            id,
+            payload: payload.to_vec(),
+        }
+    }
+
+    pub fn window(id: u32, increment: u32) -> Self {
+        Self::new(WINDOW, id, &increment.to_be_bytes())
+    }
+
+    /// Get the increment of a WINDOW frame.
+    pub fn get_increment(&self) -> io::Result<u32> {
+        match <[u8; 4]>::try_from(&self.payload[..]) {
+            Ok(it) => Ok(u32::from_be_bytes(it)),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Bad WINDOW frame",
+            )),
+        }
+    }
+
+    pub async fn read_from<T: AsyncRead + Unpin>(reader: &mut T) -> io::Result<Self> {
+        let kind = reader.read_u8().await?;
+        if kind > PONG {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown frame kind {}", kind),
+            ));
+        }
+        let id = reader.read_u32().await?;
+        let size = reader.read_u16().await? as usize;
+        if size > MAX_PAYLOAD {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Frame too long"));
+        }
+        let mut payload = vec![0; size];
+        reader.read_exact(&mut payload).await?;
+        Ok(Self { kind, id, payload })
+    }
+
+    pub async fn write_to<T: AsyncWrite + Unpin>(&self, writer: &mut T) -> io::Result<()> {
+        // Write the whole frame at once, to avoid sending the header in a separate packet.
+        let mut buf = Vec::with_capacity(7 + self.payload.len());
+        buf.push(self.kind);
+        buf.extend(self.id.to_be_bytes());
+        buf.extend((self.payload.len() as u16).to_be_bytes());
+        buf.extend(&self.payload);
+        writer.write_all(&buf).await?;
+        writer.flush().await
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    io::{duplex, split, AsyncReadExt, AsyncWriteExt, DuplexStream},
+    join,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        Mutex, Notify, Semaphore,
+    },
+    time::timeout,
+};
+
+use crate::{
+    framed::MAX_PAYLOAD,
+    utils::{NeckError, NeckResult, NeckStream, SupportedStream},
+};
+
+mod frame;
+mod tests;
+
+pub use frame::*;
+
+/// The capability name of the multiplexed mode, which is negotiated with the `Neck-Capabilities` header when joining.
+pub const MUX: &str = "mux";
+
+/// The number of bytes that each side may send on a stream before the peer grants more with WINDOW frames.
+pub const INITIAL_WINDOW: u32 = 256 * 1024;
+
+/// The buffer size between a logical stream and its virtual NeckStream.
+const DUPLEX_BUFFER: usize = 64 * 1024;
+
+struct Entry {
+    /// The received data of the stream, None indicates a FIN.
+    /// NOTE: This channel is unbounded, but it holds at most the receive window, which is enforced on each DATA frame.
+    sender: UnboundedSender<Option<Vec<u8>>>,
+    /// The send credit of the stream, in bytes. It is closed if the stream is reset.
+    credit: Arc<Semaphore>,
+    /// The receive window of the stream, in bytes, which is the most that the peer may still send.
+    window: Arc<AtomicU32>,
+}
+
+type Accepted = (
+    UnboundedSender<NeckStream>,
+    Mutex<UnboundedReceiver<NeckStream>>,
+);
+
+/// A multiplexed connection, which carries many logical streams over a single link.
+/// Each logical stream is presented as a virtual NeckStream.
+pub struct Mux {
+    link: NeckStream,
+    streams: Mutex<HashMap<u32, Entry>>,
+    next_id: AtomicU32,
+    /// The streams opened by the peer, None if the peer is not allowed to open streams.
+    accepted: Option<Accepted>,
+    pong: Notify,
+    /// Close the link if nothing is received within this duration.
+    idle_timeout: Option<Duration>,
+}
+
+impl Mux {
+    /// Create a multiplexed connection on the server side, which opens streams.
+    pub fn server(link: NeckStream) -> Arc<Self> {
+        Arc::new(Self::new(link, None, None))
+    }
+
+    /// Create a multiplexed connection on the worker side, which accepts streams opened by the server.
+    pub fn client(link: NeckStream, idle_timeout: Option<Duration>) -> Arc<Self> {
+        let (sender, receiver) = unbounded_channel();
+        Arc::new(Self::new(
+            link,
+            Some((sender, Mutex::new(receiver))),
+            idle_timeout,
+        ))
+    }
+
+    fn new(link: NeckStream, accepted: Option<Accepted>, idle_timeout: Option<Duration>) -> Self {
+        Self {
+            link,
+            streams: Mutex::new(HashMap::new()),
+            next_id: AtomicU32::new(1),
+            accepted,
+            pong: Notify::new(),
+            idle_timeout,
+        }
+    }
+
+    /// Get the underlying link.
+    pub fn get_link(&self) -> &NeckStream {
+        &self.link
+    }
+
+    /// Get the number of open streams.
+    pub async fn len(&self) -> usize {
+        self.streams.lock().await.len()
+    }
+
+    async fn send(&self, frame: MuxFrame) -> NeckResult<()> {
+        frame.write_to_stream(&self.link).await?;
+        Ok(())
+    }
+
+    /// Open a new stream.
+    pub async fn open(self: &Arc<Self>) -> NeckResult<NeckStream> {
+        let id = self.next_id.fetch_add(1, SeqCst);
+
+        // Register the stream before sending the OPEN frame, so that no reply would be missed.
+        let stream = self.create_stream(id).await;
+        if let Err(e) = self.send(MuxFrame::new(OPEN, id, &[])).await {
+            self.streams.lock().await.remove(&id);
+            return Err(e);
+        }
+
+        Ok(stream)
+    }
+
+    /// Wait for a stream opened by the peer.
+    pub async fn accept(&self) -> Option<NeckStream> {
+        let (_, receiver) = self.accepted.as_ref()?;
+        receiver.lock().await.recv().await
+    }
+
+    /// Send a PING frame, and wait for the PONG frame.
+    pub async fn ping(&self, deadline: Duration) -> NeckResult<()> {
+        self.send(MuxFrame::new(PING, 0, &[])).await?;
+        match timeout(deadline, self.pong.notified()).await {
+            Ok(_) => Ok(()),
+            Err(_) => NeckError::wrap("No reply to PING"),
+        }
+    }
+
+    /// Receive and dispatch frames until the link is broken, then reset all streams.
+    pub async fn run(self: &Arc<Self>) -> NeckResult<()> {
+        let result = self.dispatch().await;
+        self.close().await;
+        result
+    }
+
+    /// Shutdown the link and reset all streams.
+    pub async fn close(&self) {
+        // Closing the credit stops sending, and dropping the sender closes the virtual NeckStream.
+        for (_, entry) in self.streams.lock().await.drain() {
+            entry.credit.close();
+        }
+        let _ = self.link.shutdown().await;
+    }
+
+    async fn dispatch(self: &Arc<Self>) -> NeckResult<()> {
+        let mut reader = self.link.reader.lock().await;
+        loop {
+            let frame = match self.idle_timeout {
+                Some(t) => match timeout(t, MuxFrame::read_from(&mut *reader)).await {
+                    Ok(v) => v?,
+                    Err(_) => return NeckError::wrap("Idle timeout"),
+                },
+                None => MuxFrame::read_from(&mut *reader).await?,
+            };
+
+            match frame.kind {
+                OPEN => match &self.accepted {
+                    Some((sender, _)) => {
+                        let stream = self.create_stream(frame.id).await;
+                        let _ = sender.send(stream);
+                    }
+                    None => self.send(MuxFrame::new(RST, frame.id, &[])).await?,
+                },
+                DATA | FIN => {
+                    let mut streams = self.streams.lock().await;
+                    let Some(entry) = streams.get(&frame.id) else {
+                        continue;
+                    };
+                    // The peer must not send more than the window, otherwise the stream is reset.
+                    let size = frame.payload.len() as u32;
+                    if entry
+                        .window
+                        .fetch_update(SeqCst, SeqCst, |w| w.checked_sub(size))
+                        .is_err()
+                    {
+                        if let Some(entry) = streams.remove(&frame.id) {
+                            entry.credit.close();
+                        }
+                        drop(streams);
+                        println!(
+                            "[{}] Stream {} exceeded the window",
+                            self.link.peer_addr, frame.id
+                        );
+                        self.send(MuxFrame::new(RST, frame.id, &[])).await?;
+                        continue;
+                    }
+                    let data = (frame.kind == DATA).then_some(frame.payload);
+                    let _ = entry.sender.send(data);
+                }
+                WINDOW => {
+                    let increment = frame.get_increment()?;
+                    if let Some(entry) = self.streams.lock().await.get(&frame.id) {
+                        entry.credit.add_permits(increment as usize);
+                    }
+                }
+                RST => {
+                    if let Some(entry) = self.streams.lock().await.remove(&frame.id) {
+                        entry.credit.close();
+                    }
+                }
+                PING => self.send(MuxFrame::new(PONG, 0, &[])).await?,
+                PONG => self.pong.notify_one(),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Register a stream, and start pumping data between the link and a virtual NeckStream.
+    async fn create_stream(self: &Arc<Self>, id: u32) -> NeckStream {
+        let (inner, outer) = duplex(DUPLEX_BUFFER);
+        let (sender, receiver) = unbounded_channel();
+        let credit = Arc::new(Semaphore::new(INITIAL_WINDOW as usize));
+        let window = Arc::new(AtomicU32::new(INITIAL_WINDOW));
+
+        self.streams.lock().await.insert(
+            id,
+            Entry {
+                sender,
+                credit: credit.clone(),
+                window: window.clone(),
+            },
+        );
+        tokio::spawn(self.clone().pump(id, inner, receiver, credit, window));
+
+        SupportedStream::Virtual(outer, self.link.peer_addr, self.link.local_addr).into()
+    }
+
+    async fn pump(
+        self: Arc<Self>,
+        id: u32,
+        inner: DuplexStream,
+        mut receiver: UnboundedReceiver<Option<Vec<u8>>>,
+        credit: Arc<Semaphore>,
+        window: Arc<AtomicU32>,
+    ) {
+        let (mut r, mut w) = split(inner);
+
+        // Send the data written to the virtual NeckStream, within the credit.
+        let outgoing = async {
+            let mut buf = vec![0; MAX_PAYLOAD];
+            loop {
+                let size = r.read(&mut buf).await.unwrap_or(0);
+                if size == 0 {
+                    break;
+                }
+                match credit.acquire_many(size as u32).await {
+                    Ok(permit) => permit.forget(),
+                    // The stream has been reset.
+                    Err(_) => return,
+                }
+                if self
+                    .send(MuxFrame::new(DATA, id, &buf[..size]))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            let _ = self.send(MuxFrame::new(FIN, id, &[])).await;
+        };
+
+        // Write the received data to the virtual NeckStream, and grant the consumed bytes back to the peer.
+        let incoming = async {
+            let mut consumed = 0;
+            while let Some(Some(data)) = receiver.recv().await {
+                if w.write_all(&data).await.is_err() {
+                    // The virtual NeckStream has been dropped, nobody will read the data anymore.
+                    let _ = self.send(MuxFrame::new(RST, id, &[])).await;
+                    credit.close();
+                    return;
+                }
+                consumed += data.len() as u32;
+                if consumed >= INITIAL_WINDOW / 4 {
+                    // Open the window before granting, so the granted bytes are never seen as an overflow.
+                    window.fetch_add(consumed, SeqCst);
+                    let _ = self.send(MuxFrame::window(id, consumed)).await;
+                    consumed = 0;
+                }
+            }
+            let _ = w.shutdown().await;
+        };
+
+        join!(outgoing, incoming);
+
+        self.streams.lock().await.remove(&id);
+    }
+}
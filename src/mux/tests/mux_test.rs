@@ -0,0 +1,214 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::{sleep, timeout},
+};
+
+use crate::{
+    framed::MAX_PAYLOAD,
+    utils::{tests::stream_pair, NeckStream},
+};
+
+use super::super::{Mux, MuxFrame, DATA, INITIAL_WINDOW, OPEN, RST, WINDOW};
+
+/// Create a pair of multiplexed connections, and run both of them.
+async fn mux_pair() -> (Arc<Mux>, Arc<Mux>) {
+    let (a, b) = stream_pair().await;
+    let server = Mux::server(a);
+    let client = Mux::client(b, Some(Duration::from_secs(10)));
+    for mux in [server.clone(), client.clone()] {
+        tokio::spawn(async move { mux.run().await });
+    }
+    (server, client)
+}
+
+async fn write(stream: &NeckStream, data: &[u8]) {
+    stream.writer.lock().await.write_all(data).await.unwrap();
+}
+
+async fn read_exact(stream: &NeckStream, size: usize) -> Vec<u8> {
+    let mut buf = vec![0; size];
+    stream
+        .reader
+        .lock()
+        .await
+        .read_exact(&mut buf)
+        .await
+        .unwrap();
+    buf
+}
+
+#[tokio::test]
+async fn test_frame() {
+    let mut buf = Vec::new();
+    MuxFrame::new(DATA, 7, b"hi")
+        .write_to(&mut buf)
+        .await
+        .unwrap();
+    assert_eq!(buf, b"\x00\x00\x00\x00\x07\x00\x02hi");
+
+    let frame = MuxFrame::read_from(&mut &buf[..]).await.unwrap();
+    assert_eq!(frame, MuxFrame::new(DATA, 7, b"hi"));
+
+    assert_eq!(MuxFrame::window(1, 1000).get_increment().unwrap(), 1000);
+    assert!(MuxFrame::new(WINDOW, 1, b"\x00").get_increment().is_err());
+
+    // Unknown kind.
+    assert!(
+        MuxFrame::read_from(&mut &b"\x09\x00\x00\x00\x01\x00\x00"[..])
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_open_and_close() {
+    let (server, client) = mux_pair().await;
+
+    let a = server.open().await.unwrap();
+    let b = client.accept().await.unwrap();
+
+    write(&a, b"ping").await;
+    assert_eq!(read_exact(&b, 4).await, b"ping");
+    write(&b, b"pong").await;
+    assert_eq!(read_exact(&a, 4).await, b"pong");
+
+    // Streams are independent of each other.
+    let c = server.open().await.unwrap();
+    let d = client.accept().await.unwrap();
+    write(&d, b"second").await;
+    assert_eq!(read_exact(&c, 6).await, b"second");
+    assert_eq!(server.len().await, 2);
+
+    // Dropping a stream sends a FIN, which is seen as an EOF by the peer.
+    drop(a);
+    let mut rest = Vec::new();
+    b.reader.lock().await.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+    drop(b);
+
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(server.len().await, 1);
+    assert_eq!(client.len().await, 1);
+}
+
+#[tokio::test]
+async fn test_flow_control() {
+    let (server, client) = mux_pair().await;
+
+    // The peer of the first stream does not read anything.
+    let stalled = server.open().await.unwrap();
+    let _stalled_peer = client.accept().await.unwrap();
+    let size = INITIAL_WINDOW as usize * 4;
+    let writer = tokio::spawn(async move {
+        write(&stalled, &vec![0; size]).await;
+    });
+
+    // The stalled stream cannot block the others.
+    let a = server.open().await.unwrap();
+    let b = client.accept().await.unwrap();
+    write(&a, b"hello").await;
+    let received = timeout(Duration::from_secs(1), read_exact(&b, 5)).await;
+    assert_eq!(received.unwrap(), b"hello");
+
+    // The writer of the stalled stream is blocked by the window.
+    assert!(!writer.is_finished());
+}
+
+#[tokio::test]
+async fn test_large_transfer() {
+    let (server, client) = mux_pair().await;
+
+    let a = server.open().await.unwrap();
+    let b = client.accept().await.unwrap();
+
+    // Much more than the window, so WINDOW frames are required.
+    let data = (0..INITIAL_WINDOW * 4)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<u8>>();
+    let expected = data.clone();
+    let writer = tokio::spawn(async move {
+        write(&a, &data).await;
+        a.shutdown().await.unwrap();
+    });
+
+    let mut received = Vec::new();
+    b.reader
+        .lock()
+        .await
+        .read_to_end(&mut received)
+        .await
+        .unwrap();
+    writer.await.unwrap();
+    assert!(received == expected);
+}
+
+#[tokio::test]
+async fn test_ping() {
+    let (server, _client) = mux_pair().await;
+    assert!(server.ping(Duration::from_secs(1)).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_broken_link() {
+    let (a, b) = stream_pair().await;
+    let server = Mux::server(a);
+    let s = server.clone();
+    let run = tokio::spawn(async move { s.run().await });
+
+    let stream = server.open().await.unwrap();
+
+    // The peer receives the OPEN frame, then closes the link.
+    let frame = MuxFrame::read_from(&mut *b.reader.lock().await)
+        .await
+        .unwrap();
+    assert_eq!(frame.kind, OPEN);
+    drop(b);
+
+    // The link is broken, so the stream is closed.
+    assert!(run.await.unwrap().is_err());
+    let mut rest = Vec::new();
+    let _ = stream.reader.lock().await.read_to_end(&mut rest).await;
+    assert!(rest.is_empty());
+    assert_eq!(server.len().await, 0);
+}
+
+#[tokio::test]
+async fn test_window_overflow() {
+    let (a, peer) = stream_pair().await;
+    let server = Mux::server(a);
+    let s = server.clone();
+    tokio::spawn(async move { s.run().await });
+
+    let stream = server.open().await.unwrap();
+    let mut reader = peer.reader.lock().await;
+    let open = MuxFrame::read_from(&mut *reader).await.unwrap();
+    assert_eq!(open.kind, OPEN);
+
+    // A misbehaving peer ignores the window, while nothing is read on the other side,
+    // so no more than the duplex buffer is granted.
+    let chunk = vec![0; MAX_PAYLOAD];
+    let mut writer = peer.writer.lock().await;
+    for _ in 0..INITIAL_WINDOW as usize * 2 / MAX_PAYLOAD {
+        MuxFrame::new(DATA, open.id, &chunk)
+            .write_to(&mut *writer)
+            .await
+            .unwrap();
+    }
+    drop(writer);
+
+    // The stream is reset instead of buffering the excess.
+    let reset = async {
+        loop {
+            let frame = MuxFrame::read_from(&mut *reader).await.unwrap();
+            if frame.kind != WINDOW {
+                return frame;
+            }
+        }
+    };
+    let frame = timeout(Duration::from_secs(1), reset).await.unwrap();
+    assert_eq!(frame, MuxFrame::new(RST, open.id, &[]));
+    assert_eq!(server.len().await, 0);
+    drop(stream);
+}
@@ -0,0 +1,113 @@
+use crate::utils::{read_list_file, NeckError, NeckResult};
+
+use super::{parse_ports, split_host_port, HostPattern};
+
+/// The host and ports of an entry, which is shared by access rules and routes.
+///
+/// The host can be "*", a domain glob, an IP address or a CIDR block.
+/// The ports can be "*" or a list of ports and ranges, such as "80,8000-9000", all ports are matched if omitted.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    pub host: HostPattern,
+    pub ports: Vec<(u16, u16)>,
+}
+
+impl Matcher {
+    /// Check if the host and port are matched.
+    pub fn matches(&self, host: &str, port: u16) -> bool {
+        (self.ports.is_empty() || self.ports.iter().any(|(s, e)| (*s..=*e).contains(&port)))
+            && self.host.matches(host)
+    }
+}
+
+/// Parse an entry in the format of "<head> <host> [ports]", the head is checked by `parse_head`.
+/// Return the entry with its whitespaces normalized, the parsed head and the matcher.
+pub fn parse_entry<T>(
+    raw: &str,
+    kind: &str,
+    parse_head: impl FnOnce(&str) -> Option<T>,
+) -> NeckResult<(String, T, Matcher)> {
+    let bad = || NeckError::wrap(format!("Bad {} '{}'", kind, raw));
+
+    let mut parts = raw.split_whitespace();
+
+    let head = match parts.next().and_then(parse_head) {
+        Some(it) => it,
+        None => return bad(),
+    };
+
+    let host = match parts.next().and_then(HostPattern::parse) {
+        Some(it) => it,
+        None => return bad(),
+    };
+
+    let ports = match parts.next().map(parse_ports) {
+        Some(Some(it)) => it,
+        Some(None) => return bad(),
+        None => Vec::new(),
+    };
+
+    // Too many parts.
+    if parts.next().is_some() {
+        return bad();
+    }
+
+    let normalized = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+    Ok((normalized, head, Matcher { host, ports }))
+}
+
+/// An entry of an ordered list, such as an access rule or a route.
+pub trait Entry: Sized {
+    fn parse(raw: &str) -> NeckResult<Self>;
+
+    fn matcher(&self) -> &Matcher;
+}
+
+/// An ordered list of entries, the first matched entry wins.
+#[derive(Debug, Clone)]
+pub struct EntryList<T>(Vec<T>);
+
+impl<T> Default for EntryList<T> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<T: Entry> EntryList<T> {
+    /// Load entries from a list and an optional file, one entry per line.
+    pub fn load(entries: &[String], file: &Option<String>) -> NeckResult<Self> {
+        let mut list = Self::default();
+        for entry in entries {
+            list.add(entry)?;
+        }
+        if let Some(path) = file {
+            for line in read_list_file(path)? {
+                list.add(&line)?;
+            }
+        }
+        Ok(list)
+    }
+
+    /// Append an entry to the end of the list.
+    pub fn add(&mut self, raw: &str) -> NeckResult<()> {
+        self.0.push(T::parse(raw)?);
+        Ok(())
+    }
+
+    /// Iterate the entries in order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.0.iter()
+    }
+
+    /// Find the first entry matching a destination with separated host and port.
+    pub fn find_host(&self, host: &str, port: u16) -> Option<&T> {
+        self.0.iter().find(|e| e.matcher().matches(host, port))
+    }
+
+    /// Find the first entry matching a destination in the format of "host:port".
+    /// NOTE: If the port is absent, it is treated as 0, which only matches the entries without ports.
+    pub fn find(&self, addr: &str) -> Option<&T> {
+        let (host, port) = split_host_port(addr);
+        self.find_host(host, port.unwrap_or(0))
+    }
+}
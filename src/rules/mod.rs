@@ -0,0 +1,123 @@
+mod entry;
+mod pattern;
+mod routes;
+
+mod tests;
+
+use std::{
+    fmt::Display,
+    net::{IpAddr, SocketAddr},
+};
+
+use crate::utils::NeckResult;
+
+pub use entry::*;
+pub use pattern::*;
+pub use routes::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+/// An access rule in the format of "<allow|deny> <host> [ports]", for example:
+///
+/// allow *.example.com 80,443
+/// deny 10.0.0.0/8
+/// deny * 25
+///
+/// The host and ports are matched by a `Matcher`, the same as those of a route.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    raw: String,
+    pub action: Action,
+    pub matcher: Matcher,
+}
+
+impl Entry for Rule {
+    fn parse(raw: &str) -> NeckResult<Self> {
+        let (raw, action, matcher) = parse_entry(raw, "rule", |head| {
+            match head.to_ascii_lowercase().as_str() {
+                "allow" => Some(Action::Allow),
+                "deny" => Some(Action::Deny),
+                _ => None,
+            }
+        })?;
+        Ok(Self {
+            raw,
+            action,
+            matcher,
+        })
+    }
+
+    fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// The result of checking a destination.
+pub struct Decision<'a> {
+    pub allowed: bool,
+    /// The matched rule, None if no rule is matched.
+    pub rule: Option<&'a Rule>,
+}
+
+impl<'a> Decision<'a> {
+    fn new(rule: Option<&'a Rule>) -> Self {
+        Self {
+            allowed: rule.is_none_or(|r| r.action == Action::Allow),
+            rule,
+        }
+    }
+}
+
+/// An ordered list of access rules, the first matched rule wins.
+/// If no rule is matched, the destination is allowed.
+pub type Rules = EntryList<Rule>;
+
+impl Rules {
+    /// Check a resolved address of a destination, with the IP and CIDR rules only.
+    /// NOTE: The domain and "*" rules have been applied to the host name, so they are skipped here,
+    /// otherwise "allow *.example.com" followed by "deny *" would deny every resolved address.
+    pub fn check_resolved(&self, addr: &SocketAddr) -> Decision<'_> {
+        let host = addr.ip().to_string();
+        Decision::new(self.iter().find(|r| {
+            matches!(r.matcher.host, HostPattern::Cidr(..)) && r.matcher.matches(&host, addr.port())
+        }))
+    }
+
+    /// Check a destination in the format of "host:port".
+    /// NOTE: If the port is absent, it is treated as 0, which only matches the rules without ports.
+    pub fn check(&self, addr: &str) -> Decision<'_> {
+        Decision::new(self.find(addr))
+    }
+}
+
+/// The loopback, link-local and cloud metadata blocks, which should not be reached by a request from outside.
+const LOCAL_BLOCKS: &[(&str, u8)] = &[
+    ("0.0.0.0", 8),
+    ("127.0.0.0", 8),
+    ("169.254.0.0", 16),
+    // Alibaba Cloud metadata.
+    ("100.100.100.200", 32),
+    ("::", 128),
+    ("::1", 128),
+    ("fe80::", 10),
+    // AWS metadata over IPv6.
+    ("fd00:ec2::254", 128),
+];
+
+/// Check if an IP address is a loopback, link-local or cloud metadata address.
+pub fn is_local_ip(ip: &IpAddr) -> bool {
+    LOCAL_BLOCKS.iter().any(|(net, prefix)| {
+        net.parse::<IpAddr>()
+            .is_ok_and(|net| cidr_contains(&net, *prefix, ip))
+    })
+}
@@ -0,0 +1,198 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+/// Match a text against a glob pattern (case-insensitive).
+/// The `*` matches any sequence of characters (including dots), and the `?` matches exactly one character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p = pattern.to_ascii_lowercase().into_bytes();
+    let t = text.to_ascii_lowercase().into_bytes();
+
+    let (mut pi, mut ti) = (0, 0);
+
+    // The position of the last `*` in pattern, and the position in text where it starts matching.
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == b'?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == b'*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            // Backtrack, let the last `*` consume one more character.
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+
+    // The remaining pattern can only be a sequence of `*`.
+    p[pi..].iter().all(|c| *c == b'*')
+}
+
+/// Check if the first `prefix` bits of two numbers are equal.
+fn prefix_eq(a: u128, b: u128, prefix: u8, bits: u8) -> bool {
+    let shift = (bits - prefix) as u32;
+    a.checked_shr(shift).unwrap_or(0) == b.checked_shr(shift).unwrap_or(0)
+}
+
+/// Check if an IP address is contained in a CIDR block.
+pub fn cidr_contains(net: &IpAddr, prefix: u8, ip: &IpAddr) -> bool {
+    match (net, ip) {
+        (IpAddr::V4(n), IpAddr::V4(i)) => {
+            prefix_eq(u32::from(*n) as u128, u32::from(*i) as u128, prefix, 32)
+        }
+        (IpAddr::V6(n), IpAddr::V6(i)) => prefix_eq(u128::from(*n), u128::from(*i), prefix, 128),
+        // An IPv4-mapped IPv6 address, such as ::ffff:10.0.0.1, is the same as an IPv4 address.
+        (IpAddr::V4(_), IpAddr::V6(i)) => i
+            .to_ipv4_mapped()
+            .is_some_and(|v| cidr_contains(net, prefix, &IpAddr::V4(v))),
+        _ => false,
+    }
+}
+
+/// Parse a part of a numeric IPv4 address, which is decimal, hex with "0x", or octal with a leading "0".
+fn parse_ipv4_part(raw: &str) -> Option<u32> {
+    let (digits, radix) = match raw.strip_prefix("0x").or_else(|| raw.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None if raw.len() > 1 && raw.starts_with('0') => (&raw[1..], 8),
+        None => (raw, 10),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    u32::from_str_radix(digits, radix).ok()
+}
+
+/// Parse an IPv4 address in the numeric forms accepted by resolvers (inet_aton),
+/// such as "167772161", "0x0a000001", "012.0.0.1" or "10.1", where the last part fills the remaining bytes.
+fn parse_numeric_ipv4(host: &str) -> Option<Ipv4Addr> {
+    let parts = host
+        .split('.')
+        .map(parse_ipv4_part)
+        .collect::<Option<Vec<_>>>()?;
+    let (last, init) = parts.split_last()?;
+    if init.len() > 3 || init.iter().any(|p| *p > 255) {
+        return None;
+    }
+    let bits = 8 * (4 - init.len() as u32);
+    if last.checked_shr(bits).unwrap_or(0) != 0 {
+        return None;
+    }
+    let high = init.iter().fold(0u32, |acc, p| (acc << 8) | p);
+    Some(Ipv4Addr::from(high.checked_shl(bits).unwrap_or(0) | last))
+}
+
+/// Parse a host as an IP address in its canonical form, so all the forms of the same address are matched alike.
+/// The numeric IPv4 forms are accepted, and an IPv4-mapped IPv6 address, such as ::ffff:10.0.0.1, is converted to IPv4.
+pub fn parse_ip(host: &str) -> Option<IpAddr> {
+    let ip = match host.parse::<IpAddr>() {
+        Ok(it) => it,
+        Err(_) => IpAddr::V4(parse_numeric_ipv4(host)?),
+    };
+    Some(ip.to_canonical())
+}
+
+/// Remove one trailing dot of a fully qualified domain, such as "example.com.".
+fn strip_root(host: &str) -> &str {
+    host.strip_suffix('.').unwrap_or(host)
+}
+
+/// Split an address in the format of "host:port" into host and port.
+/// The brackets of an IPv6 host are removed, and the port is None if it is not present.
+pub fn split_host_port(addr: &str) -> (&str, Option<u16>) {
+    // For example: "[::1]:443" or "[::1]"
+    if let Some(rest) = addr.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((host, tail)) => (host, tail.strip_prefix(':').and_then(|p| p.parse().ok())),
+            None => (addr, None),
+        };
+    }
+    match addr.rsplit_once(':') {
+        // An IPv6 address without port, such as "::1".
+        Some(_) if addr.parse::<IpAddr>().is_ok() => (addr, None),
+        Some((host, port)) => match port.parse() {
+            Ok(port) => (host, Some(port)),
+            Err(_) => (addr, None),
+        },
+        None => (addr, None),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostPattern {
+    /// The "*" matches all hosts.
+    Any,
+    /// An IP address or a CIDR block, which only matches IP hosts.
+    Cidr(IpAddr, u8),
+    /// A domain glob, such as "*.example.com".
+    Domain(String),
+}
+
+impl HostPattern {
+    pub fn parse(raw: &str) -> Option<Self> {
+        if raw == "*" {
+            return Some(HostPattern::Any);
+        }
+
+        // For example: "10.0.0.0/8" or "fd00::/8"
+        if let Some((ip, prefix)) = raw.split_once('/') {
+            let ip: IpAddr = ip.parse().ok()?;
+            let prefix: u8 = prefix.parse().ok()?;
+            let bits = if ip.is_ipv4() { 32 } else { 128 };
+            if prefix > bits {
+                return None;
+            }
+            // An IPv4-mapped block, such as ::ffff:10.0.0.0/104, is the same as an IPv4 block.
+            return Some(match ip.to_canonical() {
+                IpAddr::V4(v4) if ip.is_ipv6() && prefix >= 96 => {
+                    HostPattern::Cidr(IpAddr::V4(v4), prefix - 96)
+                }
+                _ => HostPattern::Cidr(ip, prefix),
+            });
+        }
+
+        // A single IP address is the same as a CIDR block with a full prefix.
+        let (host, _) = split_host_port(raw);
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            let ip = ip.to_canonical();
+            return Some(HostPattern::Cidr(ip, if ip.is_ipv4() { 32 } else { 128 }));
+        }
+
+        Some(HostPattern::Domain(strip_root(raw).to_string()))
+    }
+
+    /// Check if the pattern matches the host, which is normalized first,
+    /// so "example.com." matches like "example.com", and "0x0a000001" matches like "10.0.0.1".
+    pub fn matches(&self, host: &str) -> bool {
+        let host = strip_root(host);
+        match self {
+            HostPattern::Any => true,
+            HostPattern::Cidr(net, prefix) => {
+                parse_ip(host).is_some_and(|ip| cidr_contains(net, *prefix, &ip))
+            }
+            HostPattern::Domain(glob) => glob_match(glob, host),
+        }
+    }
+}
+
+/// Parse a list of port ranges, such as "80,443,8000-9000".
+/// The "*" means all ports, which is represented as an empty list.
+pub fn parse_ports(raw: &str) -> Option<Vec<(u16, u16)>> {
+    if raw == "*" {
+        return Some(Vec::new());
+    }
+    raw.split(',')
+        .map(|item| {
+            let (start, end) = item.split_once('-').unwrap_or((item, item));
+            let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+            if start <= end {
+                Some((start, end))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
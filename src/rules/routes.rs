@@ -0,0 +1,61 @@
+use std::fmt::Display;
+
+use crate::utils::NeckResult;
+
+use super::{parse_entry, Entry, EntryList, Matcher};
+
+/// Check if a worker group name is valid, which is made of letters, digits, '-', '_' and '.'.
+pub fn is_valid_group(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// A route in the format of "<group> <host> [ports]", which sends the matched destinations to a worker group, for example:
+///
+/// zone-b 10.2.0.0/16
+/// zone-c *.c.internal 443
+///
+/// The host and ports are matched by a `Matcher`, the same as those of an access rule.
+#[derive(Debug, Clone)]
+pub struct Route {
+    raw: String,
+    pub group: String,
+    pub matcher: Matcher,
+}
+
+impl Entry for Route {
+    fn parse(raw: &str) -> NeckResult<Self> {
+        let valid = |group: &str| is_valid_group(group).then(|| group.to_string());
+        let (raw, group, matcher) = parse_entry(raw, "route", valid)?;
+        Ok(Self {
+            raw,
+            group,
+            matcher,
+        })
+    }
+
+    fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+}
+
+impl Display for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// An ordered list of routes, the first matched route wins.
+/// If no route is matched, the destination is sent to the workers without a group.
+pub type Routes = EntryList<Route>;
+
+impl Routes {
+    /// Get the worker group of a destination in the format of "host:port", None for the workers without a group.
+    /// NOTE: If the port is absent, it is treated as 0, which only matches the routes without ports.
+    pub fn get_group(&self, addr: &str) -> Option<&str> {
+        self.find(addr).map(|r| r.group.as_str())
+    }
+}
@@ -0,0 +1,4 @@
+#[cfg(test)]
+mod routes_test;
+#[cfg(test)]
+mod rules_test;
@@ -0,0 +1,46 @@
+use super::super::{is_valid_group, Entry, Route, Routes};
+
+#[test]
+fn test_parse() {
+    assert!(Route::parse("zone-b 10.2.0.0/16").is_ok());
+    assert!(Route::parse("zone_c.1 *.c.internal 443,8443").is_ok());
+    assert!(Route::parse("zone-b").is_err());
+    assert!(Route::parse("zone/b *").is_err());
+    assert!(Route::parse("zone-b 10.0.0.0/33").is_err());
+    assert!(Route::parse("zone-b * 80 extra").is_err());
+    assert_eq!(
+        Route::parse(" zone-b   *.b.internal ").unwrap().to_string(),
+        "zone-b *.b.internal"
+    );
+}
+
+#[test]
+fn test_is_valid_group() {
+    assert!(is_valid_group("zone-b"));
+    assert!(is_valid_group("Zone_C.1"));
+    assert!(!is_valid_group(""));
+    assert!(!is_valid_group("zone b"));
+    assert!(!is_valid_group("zone,b"));
+    assert!(!is_valid_group(&"a".repeat(65)));
+}
+
+#[test]
+fn test_first_match() {
+    let routes = Routes::load(
+        &[
+            "zone-d 10.2.9.0/24".to_string(),
+            "zone-b 10.2.0.0/16".to_string(),
+            "zone-c *.c.internal 443".to_string(),
+        ],
+        &None,
+    )
+    .unwrap();
+
+    assert_eq!(routes.get_group("10.2.9.1:22"), Some("zone-d"));
+    assert_eq!(routes.get_group("10.2.1.1:22"), Some("zone-b"));
+    assert_eq!(routes.get_group("api.c.internal:443"), Some("zone-c"));
+
+    // Nothing is matched.
+    assert_eq!(routes.get_group("api.c.internal:80"), None);
+    assert_eq!(routes.get_group("example.com:443"), None);
+}
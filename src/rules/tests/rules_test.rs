@@ -0,0 +1,236 @@
+use std::net::IpAddr;
+
+use super::super::{glob_match, is_local_ip, parse_ip, split_host_port, Entry, Rule, Rules};
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("*", "example.com"));
+    assert!(glob_match("*.example.com", "a.example.com"));
+    assert!(glob_match("*.example.com", "a.b.example.com"));
+    assert!(!glob_match("*.example.com", "example.com"));
+    assert!(glob_match("EXAMPLE.com", "example.COM"));
+    assert!(glob_match("api-?.example.com", "api-1.example.com"));
+    assert!(!glob_match("api-?.example.com", "api-10.example.com"));
+    assert!(glob_match("*a*b", "xxaxxbxb"));
+    assert!(!glob_match("*a*b", "xxaxxbx"));
+}
+
+#[test]
+fn test_split_host_port() {
+    assert_eq!(
+        split_host_port("example.com:443"),
+        ("example.com", Some(443))
+    );
+    assert_eq!(split_host_port("example.com"), ("example.com", None));
+    assert_eq!(split_host_port("[::1]:8080"), ("::1", Some(8080)));
+    assert_eq!(split_host_port("[::1]"), ("::1", None));
+    assert_eq!(split_host_port("::1"), ("::1", None));
+    assert_eq!(split_host_port("fe80::1:80"), ("fe80::1:80", None));
+    assert_eq!(split_host_port("1.2.3.4:80"), ("1.2.3.4", Some(80)));
+}
+
+#[test]
+fn test_parse() {
+    assert!(Rule::parse("allow *").is_ok());
+    assert!(Rule::parse("DENY 10.0.0.0/8 *").is_ok());
+    assert!(Rule::parse("allow *.example.com 80,443,8000-9000").is_ok());
+    assert!(Rule::parse("allow").is_err());
+    assert!(Rule::parse("permit *").is_err());
+    assert!(Rule::parse("deny 10.0.0.0/33").is_err());
+    assert!(Rule::parse("deny * 443-80").is_err());
+    assert!(Rule::parse("deny * 70000").is_err());
+    assert!(Rule::parse("deny * 80 extra").is_err());
+    assert_eq!(
+        Rule::parse("  allow   *.example.com  443 ")
+            .unwrap()
+            .to_string(),
+        "allow *.example.com 443"
+    );
+}
+
+#[test]
+fn test_first_match() {
+    let rules = Rules::load(
+        &[
+            "allow 10.1.2.0/24 22".to_string(),
+            "deny 10.0.0.0/8".to_string(),
+            "deny * 25".to_string(),
+            "allow *.example.com 443".to_string(),
+            "deny *.example.com".to_string(),
+        ],
+        &None,
+    )
+    .unwrap();
+
+    let check = |addr: &str| {
+        let d = rules.check(addr);
+        (d.allowed, d.rule.map(|r| r.to_string()))
+    };
+
+    assert_eq!(
+        check("10.1.2.3:22"),
+        (true, Some("allow 10.1.2.0/24 22".into()))
+    );
+    assert_eq!(
+        check("10.1.2.3:80"),
+        (false, Some("deny 10.0.0.0/8".into()))
+    );
+    assert_eq!(
+        check("10.9.9.9:22"),
+        (false, Some("deny 10.0.0.0/8".into()))
+    );
+    assert_eq!(
+        check("mail.example.com:25"),
+        (false, Some("deny * 25".into()))
+    );
+    assert_eq!(
+        check("a.example.com:443"),
+        (true, Some("allow *.example.com 443".into()))
+    );
+    assert_eq!(
+        check("a.example.com:80"),
+        (false, Some("deny *.example.com".into()))
+    );
+
+    // Nothing is matched.
+    assert_eq!(check("example.org:80"), (true, None));
+    assert_eq!(check("11.0.0.1:80"), (true, None));
+}
+
+#[test]
+fn test_ipv6() {
+    let rules = Rules::load(
+        &["deny fd00::/8".to_string(), "deny 127.0.0.0/8".to_string()],
+        &None,
+    )
+    .unwrap();
+
+    assert!(!rules.check("[fd12::1]:80").allowed);
+    assert!(rules.check("[fe80::1]:80").allowed);
+
+    // IPv4-mapped IPv6 addresses are matched by IPv4 blocks.
+    assert!(!rules.check("[::ffff:127.0.0.1]:80").allowed);
+}
+
+#[test]
+fn test_check_resolved() {
+    let rules = Rules::load(
+        &[
+            "allow *.example.com 443".to_string(),
+            "allow 10.1.2.0/24".to_string(),
+            "deny 10.0.0.0/8".to_string(),
+            "deny 192.168.0.0/16 22".to_string(),
+            "deny *".to_string(),
+        ],
+        &None,
+    )
+    .unwrap();
+
+    let check = |addr: &str| {
+        let d = rules.check_resolved(&addr.parse().unwrap());
+        (d.allowed, d.rule.map(|r| r.to_string()))
+    };
+
+    // Only the IP and CIDR rules are applied, the domain and "*" rules are skipped.
+    assert_eq!(
+        check("10.9.9.9:443"),
+        (false, Some("deny 10.0.0.0/8".into()))
+    );
+    assert_eq!(
+        check("10.1.2.3:443"),
+        (true, Some("allow 10.1.2.0/24".into()))
+    );
+    assert_eq!(
+        check("192.168.1.1:22"),
+        (false, Some("deny 192.168.0.0/16 22".into()))
+    );
+    assert_eq!(check("192.168.1.1:443"), (true, None));
+    assert_eq!(check("93.184.216.34:443"), (true, None));
+    assert_eq!(
+        check("[::ffff:10.0.0.1]:443"),
+        (false, Some("deny 10.0.0.0/8".into()))
+    );
+}
+
+#[test]
+fn test_parse_ip() {
+    let ip = |host: &str| parse_ip(host).map(|ip| ip.to_string());
+
+    assert_eq!(ip("10.0.0.1"), Some("10.0.0.1".into()));
+    assert_eq!(ip("167772161"), Some("10.0.0.1".into()));
+    assert_eq!(ip("0x0a000001"), Some("10.0.0.1".into()));
+    assert_eq!(ip("0X0A000001"), Some("10.0.0.1".into()));
+    assert_eq!(ip("012.0.0.1"), Some("10.0.0.1".into()));
+    assert_eq!(ip("0xa.0.0.1"), Some("10.0.0.1".into()));
+    assert_eq!(ip("10.1"), Some("10.0.0.1".into()));
+    assert_eq!(ip("127.1"), Some("127.0.0.1".into()));
+    assert_eq!(ip("10.0.1"), Some("10.0.0.1".into()));
+    assert_eq!(ip("::ffff:10.0.0.1"), Some("10.0.0.1".into()));
+    assert_eq!(ip("fd00::1"), Some("fd00::1".into()));
+
+    // Not an IP address.
+    assert_eq!(ip("example.com"), None);
+    assert_eq!(ip("0x10.com"), None);
+    assert_eq!(ip("4294967296"), None);
+    assert_eq!(ip("10.256.0.1"), None);
+    assert_eq!(ip("10.0.65536"), None);
+    assert_eq!(ip("1.2.3.4.5"), None);
+    assert_eq!(ip("08.0.0.1"), None);
+    assert_eq!(ip("0x"), None);
+    assert_eq!(ip("+10.0.0.1"), None);
+    assert_eq!(ip(""), None);
+}
+
+#[test]
+fn test_normalized_hosts() {
+    let rules = Rules::load(
+        &[
+            "deny 10.0.0.0/8".to_string(),
+            "deny ::ffff:192.168.0.0/112".to_string(),
+            "deny example.com".to_string(),
+            "deny internal.example.org.".to_string(),
+        ],
+        &None,
+    )
+    .unwrap();
+
+    // The numeric forms of an IPv4 address.
+    assert!(!rules.check("167772161:80").allowed);
+    assert!(!rules.check("0x0a000001:80").allowed);
+    assert!(!rules.check("10.1:80").allowed);
+    assert!(!rules.check("[::ffff:10.0.0.1]:80").allowed);
+    assert!(!rules.check("[::ffff:a00:1]:80").allowed);
+    assert!(rules.check("184549377:80").allowed);
+
+    // An IPv4-mapped block is matched by IPv4 hosts.
+    assert!(!rules.check("192.168.1.1:80").allowed);
+    assert!(rules.check("192.169.1.1:80").allowed);
+
+    // A trailing dot is ignored, both in hosts and in patterns.
+    assert!(!rules.check("example.com.:80").allowed);
+    assert!(!rules.check("10.0.0.1.:80").allowed);
+    assert!(!rules.check("internal.example.org:80").allowed);
+    assert!(rules.check("example.com..:80").allowed);
+}
+
+#[test]
+fn test_is_local_ip() {
+    let local = |ip: &str| is_local_ip(&ip.parse::<IpAddr>().unwrap());
+
+    assert!(local("127.0.0.1"));
+    assert!(local("127.1.2.3"));
+    assert!(local("0.0.0.0"));
+    assert!(local("169.254.169.254"));
+    assert!(local("100.100.100.200"));
+    assert!(local("::1"));
+    assert!(local("::"));
+    assert!(local("fe80::1"));
+    assert!(local("fd00:ec2::254"));
+    assert!(local("::ffff:127.0.0.1"));
+
+    assert!(!local("10.0.0.1"));
+    assert!(!local("192.168.1.1"));
+    assert!(!local("8.8.8.8"));
+    assert!(!local("100.100.100.201"));
+    assert!(!local("2001:db8::1"));
+}
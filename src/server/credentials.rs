@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::utils::{parse_hex_digest, read_list_file, NeckError, NeckResult};
+
+/// The prefix of a hashed password, it is followed by the hex-encoded SHA-256 digest of the password.
+const SHA256_PREFIX: &str = "{SHA256}";
+
+type Digest256 = [u8; 32];
+
+fn sha256(data: &[u8]) -> Digest256 {
+    Sha256::digest(data).into()
+}
+
+/// Compare two digests without short-circuiting,
+/// so that the time taken does not reveal how many leading bytes are matched.
+fn digest_eq(a: &Digest256, b: &Digest256) -> bool {
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+/// A set of username and password pairs.
+/// Only the SHA-256 digests of passwords are kept in memory, regardless of whether they are provided in plain.
+#[derive(Debug, Default)]
+pub struct Credentials(HashMap<String, Digest256>);
+
+impl Credentials {
+    /// Load credentials from a list of entries and an optional file.
+    /// Return None if nothing is provided, which means that authentication is not required.
+    pub fn load(entries: &[String], file: &Option<String>) -> NeckResult<Option<Self>> {
+        if entries.is_empty() && file.is_none() {
+            return Ok(None);
+        }
+        let mut credentials = Self::default();
+        for entry in entries {
+            credentials.add(entry)?;
+        }
+        if let Some(path) = file {
+            credentials.load_file(path)?;
+        }
+        Ok(Some(credentials))
+    }
+
+    /// Add a credential in the format of "username:password" or "username:{SHA256}hex".
+    pub fn add(&mut self, entry: &str) -> NeckResult<()> {
+        let (user, secret) = match entry.split_once(':') {
+            Some(it) => it,
+            None => {
+                return NeckError::wrap(format!(
+                    "Bad credential '{}', expecting 'username:password'",
+                    entry
+                ))
+            }
+        };
+
+        let digest = match secret.strip_prefix(SHA256_PREFIX) {
+            // The password has been hashed.
+            Some(hex) => match parse_hex_digest(hex) {
+                Some(it) => it,
+                None => return NeckError::wrap(format!("Bad SHA-256 digest for '{}'", user)),
+            },
+            // The password is provided in plain.
+            None => sha256(secret.as_bytes()),
+        };
+
+        self.0.insert(user.to_string(), digest);
+        Ok(())
+    }
+
+    /// Load credentials from a file, one credential per line.
+    pub fn load_file(&mut self, path: &str) -> NeckResult<()> {
+        for line in read_list_file(path)? {
+            self.add(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Check if the password matches the username.
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        self.0
+            .get(user)
+            .is_some_and(|digest| digest_eq(digest, &sha256(password.as_bytes())))
+    }
+
+    /// Verify the value of an `Authorization` or a `Proxy-Authorization` header with the Basic scheme.
+    /// Return the username if the credential is valid.
+    pub fn verify_basic(&self, value: &str) -> Option<String> {
+        let (scheme, token) = value.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("Basic") {
+            return None;
+        }
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(token.trim())
+            .ok()?;
+        let (user, password) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+        if self.verify(user, password) {
+            Some(user.to_string())
+        } else {
+            None
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::sync::{atomic::Ordering::SeqCst, Arc};
 
 use tokio::io::AsyncWriteExt;
 
@@ -7,7 +7,28 @@ use crate::{
     utils::{NeckResult, NeckStream},
 };
 
-use super::super::{static_manager::get_static_matcher, NeckServer};
+use super::super::{manager::WorkerInfo, static_manager::get_static_matcher, NeckServer};
+
+/// Get the number of available connections of all tenants.
+async fn total_len(ctx: &NeckServer) -> usize {
+    let mut total = 0;
+    for tenant in ctx.tenants.iter() {
+        total += tenant.manager.len().await;
+    }
+    total
+}
+
+/// List the workers of all tenants, each worker is marked with its tenant.
+async fn all_workers(ctx: &NeckServer) -> Vec<WorkerInfo> {
+    let mut workers = Vec::new();
+    for tenant in ctx.tenants.iter() {
+        for mut worker in tenant.manager.workers().await {
+            worker.tenant = tenant.path.clone();
+            workers.push(worker);
+        }
+    }
+    workers
+}
 
 pub async fn api_handler(
     stream: NeckStream,
@@ -17,7 +38,23 @@ pub async fn api_handler(
     let uri = req.get_uri();
     if uri.eq("/api/len") && req.get_method().eq("GET") {
         HttpResponse::new(200, "OK", req.get_version())
-            .add_payload(ctx.manager.len().await.to_string().as_bytes())
+            .add_payload(total_len(ctx).await.to_string().as_bytes())
+            .add_payload(b"\n")
+            .write_to_stream(&stream)
+            .await?;
+    } else if uri.eq("/api/workers") && req.get_method().eq("GET") {
+        HttpResponse::new(200, "OK", req.get_version())
+            .add_payload(
+                serde_json::to_string(&all_workers(ctx).await)
+                    .unwrap()
+                    .as_bytes(),
+            )
+            .add_header("Content-Type: application/json")
+            .write_to_stream(&stream)
+            .await?;
+    } else if uri.eq("/api/failed-joins") && req.get_method().eq("GET") {
+        HttpResponse::new(200, "OK", req.get_version())
+            .add_payload(ctx.failed_joins.load(SeqCst).to_string().as_bytes())
             .add_payload(b"\n")
             .write_to_stream(&stream)
             .await?;
@@ -27,6 +64,12 @@ pub async fn api_handler(
             .add_header("Content-Type: application/json")
             .write_to_stream(&stream)
             .await?;
+    } else if uri.eq("/api/denials") && req.get_method().eq("GET") {
+        HttpResponse::new(200, "OK", req.get_version())
+            .add_payload(ctx.session_manager.list_denials().unwrap().as_bytes())
+            .add_header("Content-Type: application/json")
+            .write_to_stream(&stream)
+            .await?;
     } else if uri.eq("/api/events") && req.get_method().eq("GET") {
         HttpResponse::new(200, "OK", req.get_version())
             .add_header("Content-Type: text/event-stream")
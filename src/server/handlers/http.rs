@@ -6,25 +6,35 @@ use crate::{
 };
 
 use super::{
-    super::NeckServer,
+    super::{tenant::Tenant, NeckServer},
     api::api_handler,
     join::join_handler,
     proxy::{http_proxy_handler, https_proxy_handler},
 };
 
-pub async fn http_handler(stream: NeckStream, ctx: Arc<NeckServer>) -> NeckResult<()> {
+pub async fn http_handler(
+    stream: NeckStream,
+    ctx: Arc<NeckServer>,
+    tenant: Option<Arc<Tenant>>,
+) -> NeckResult<()> {
+    let tenant = tenant.as_ref();
+
     // Read the first request.
     // NOTE: Do not read payload here, because payload may be a huge stream.
     let req = HttpRequest::read_header_from(&stream).await?;
 
     // Dispatch to different handlers.
     if let "CONNECT" = req.get_method() {
-        https_proxy_handler(stream, &req, &ctx).await
+        https_proxy_handler(stream, &req, &ctx, tenant).await
+    } else
+    // It is a simple HTTP proxy request, including an Upgrade request such as WebSocket.
+    if req.get_uri().starts_with("http://") {
+        http_proxy_handler(stream, req, &ctx, tenant).await
     } else
     // For HTTP Upgrade.
     if let Some(upgrade) = req.headers.get_header_value("Upgrade") {
         if upgrade.eq("neck") {
-            join_handler(stream, &req, &ctx).await
+            join_handler(stream, &req, &ctx, tenant).await
         } else {
             HttpResponse::new(400, "Bad Request", req.get_version())
                 .add_payload(format!("The protocol '{}' is not supported.", upgrade).as_bytes())
@@ -32,10 +42,14 @@ pub async fn http_handler(stream: NeckStream, ctx: Arc<NeckServer>) -> NeckResul
                 .await
                 .map_err(|e| e.into())
         }
-    } else
-    // It is a simple HTTP proxy request.
-    if req.get_uri().starts_with("http://") {
-        http_proxy_handler(stream, &req, &ctx).await
+    }
+    // The API and the dashboard show all tenants, so they are served on the main listener only.
+    else if tenant.is_some() {
+        HttpResponse::new(404, "Not Found", req.get_version())
+            .add_payload(b"Not Found\n")
+            .write_to_stream(&stream)
+            .await
+            .map_err(|e| e.into())
     }
     // Others.
     else {
@@ -1,26 +1,165 @@
-use std::sync::Arc;
+use std::sync::{atomic::Ordering::SeqCst, Arc};
 
 use crate::{
+    framed::FRAMED,
     http::{HttpRequest, HttpResponse},
-    utils::{NeckResult, NeckStream},
+    mux::MUX,
+    rules::is_valid_group,
+    utils::{
+        get_protocol_version, select_protocol_version, Capabilities, NeckResult, NeckStream,
+        BIND_RELAY, CAPABILITIES_HEADER, DEMAND_HEADER, GROUP_HEADER, HEARTBEAT,
+        MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, SOFTWARE, UDP_RELAY, VERSION_HEADER,
+    },
 };
 
-use super::super::NeckServer;
+use super::super::{tenant::Tenant, NeckServer};
+
+/// Check the `Authorization` header if the tenant requires worker credentials.
+fn is_authorized(req: &HttpRequest, tenant: &Tenant) -> bool {
+    match &tenant.worker_credentials {
+        Some(credentials) => req
+            .headers
+            .get_header_value("Authorization")
+            .and_then(|v| credentials.verify_basic(v))
+            .is_some(),
+        None => true,
+    }
+}
+
+/// Check the client certificate if the server requires it.
+/// NOTE: The certificate has been verified during the TLS handshake, so only its presence is checked here.
+fn is_certified(stream: &NeckStream, ctx: &Arc<NeckServer>) -> bool {
+    match &ctx.tls {
+        Some(tls) if tls.verifies_client() => stream.peer_subject.is_some(),
+        _ => true,
+    }
+}
+
+/// Select the capabilities offered by the worker and supported by the manager of the tenant.
+fn select_capabilities(version: u32, req: &HttpRequest, tenant: &Tenant) -> Capabilities {
+    let mut offered = Capabilities::from_headers(&req.headers);
+
+    // Workers of version 1 announce nothing, but they answer any request (PING with a 405), which works as a heartbeat.
+    if version == 1 {
+        offered.add(HEARTBEAT);
+    }
+
+    let usable = offered.intersection(&tenant.manager.capabilities());
+    let mut selected = Capabilities::default();
+    if usable.has(HEARTBEAT) {
+        selected.add(HEARTBEAT);
+    }
+    if usable.has(UDP_RELAY) {
+        selected.add(UDP_RELAY);
+    }
+    if usable.has(BIND_RELAY) {
+        selected.add(BIND_RELAY);
+    }
+
+    // Accept the multiplexed mode if the worker offers it, so that the worker can carry many tunnels at once.
+    // Otherwise, accept the framed mode if offered, so that the worker can be reused after each tunnel.
+    if usable.has(MUX) {
+        selected.add(MUX);
+    } else if usable.has(FRAMED) {
+        selected.add(FRAMED);
+    }
+
+    selected
+}
+
+/// Reject a join attempt, and count it.
+async fn reject(
+    stream: &NeckStream,
+    ctx: &Arc<NeckServer>,
+    mut res: HttpResponse,
+    reason: &str,
+) -> NeckResult<()> {
+    let count = ctx.failed_joins.fetch_add(1, SeqCst) + 1;
+    println!(
+        "[{}] Rejected a worker with {} ({} failed joins)",
+        stream.peer_addr, reason, count
+    );
+
+    res.add_payload(format!("Rejected with {}\n", reason).as_bytes())
+        .write_to_stream(stream)
+        .await?;
+
+    Ok(())
+}
 
 pub async fn join_handler(
-    stream: NeckStream,
+    mut stream: NeckStream,
     req: &HttpRequest,
     ctx: &Arc<NeckServer>,
+    bound: Option<&Arc<Tenant>>,
 ) -> NeckResult<()> {
-    // Respond a status with 101 Switching Protocols.
-    HttpResponse::new(101, "Switching Protocols", req.get_version())
-        .add_header("Connection: Upgrade")
+    // The worker belongs to the tenant of the listener, or the tenant of the join path.
+    let tenant = match bound.or_else(|| ctx.tenants.get_by_path(req.get_uri())) {
+        Some(it) => it.clone(),
+        None => {
+            let res = HttpResponse::new(404, "Not Found", req.get_version());
+            let reason = format!("unknown tenant path '{}'", req.get_uri());
+            return reject(&stream, ctx, res, &reason).await;
+        }
+    };
+
+    // Reject the worker before it can join the manager if it does not provide valid credentials.
+    if !is_authorized(req, &tenant) {
+        let mut res = HttpResponse::new(401, "Unauthorized", req.get_version());
+        res.add_header("WWW-Authenticate: Basic realm=\"neck\"");
+        return reject(&stream, ctx, res, "invalid credentials").await;
+    }
+
+    // Likewise, if it does not provide a valid client certificate.
+    if !is_certified(&stream, ctx) {
+        let res = HttpResponse::new(403, "Forbidden", req.get_version());
+        return reject(&stream, ctx, res, "no client certificate").await;
+    }
+
+    // Reject the worker with a clear reason if it is too old (or malformed) to work with, instead of pooling it.
+    // A newer worker is fine, it speaks the older protocol version selected by the server.
+    let offered = get_protocol_version(&req.headers);
+    let version = match select_protocol_version(offered, MIN_PROTOCOL_VERSION) {
+        Some(v) => v,
+        None => {
+            let mut res = HttpResponse::new(426, "Upgrade Required", req.get_version());
+            res.add_header("Upgrade: neck")
+                .add_header_kv(VERSION_HEADER, &PROTOCOL_VERSION.to_string())
+                .add_header_kv("Server", SOFTWARE);
+            let offered = req.headers.get_header_value(VERSION_HEADER).unwrap_or("");
+            let reason = format!(
+                "unsupported protocol version '{}' (supported {} to {})",
+                offered, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            );
+            return reject(&stream, ctx, res, &reason).await;
+        }
+    };
+
+    // The group name is shown in listings and matched with routes, so reject a malformed one.
+    let group = req.headers.get_header_value(GROUP_HEADER).map(str::trim);
+    if let Some(name) = group.filter(|v| !is_valid_group(v)) {
+        let res = HttpResponse::new(400, "Bad Request", req.get_version());
+        return reject(&stream, ctx, res, &format!("invalid group name '{}'", name)).await;
+    }
+    stream.group = group.map(String::from);
+
+    stream.capabilities = select_capabilities(version, req, &tenant);
+    stream.peer_agent = req.headers.get_header_value("User-Agent").map(String::from);
+
+    // Respond a status with 101 Switching Protocols, with the selected version and capabilities.
+    let mut res = HttpResponse::new(101, "Switching Protocols", req.get_version());
+    res.add_header("Connection: Upgrade")
         .add_header("Upgrade: neck")
-        .write_to_stream(&stream)
-        .await?;
+        .add_header_kv(VERSION_HEADER, &version.to_string())
+        .add_header_kv(CAPABILITIES_HEADER, &stream.capabilities.to_string())
+        .add_header_kv("Server", SOFTWARE);
+    if let Some(demand) = tenant.manager.demand(&stream).await {
+        res.add_header_kv(DEMAND_HEADER, &demand.to_string());
+    }
+    res.write_to_stream(&stream).await?;
 
-    // Join the manager (ownership for the stream is moved to the manager)
-    ctx.manager.join(stream).await;
+    // Join the manager of the tenant (ownership for the stream is moved to the manager)
+    tenant.manager.join(stream).await;
 
     Ok(())
 }
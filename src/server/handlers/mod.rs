@@ -3,6 +3,8 @@ mod http;
 mod join;
 mod proxy;
 mod request;
+mod socks4;
 mod socks5;
+mod udp;
 
 pub use request::*;
@@ -1,31 +1,50 @@
-use std::{borrow::Cow, sync::Arc};
+use std::{sync::Arc, time::Duration};
+
+use tokio::{
+    io::{self, duplex},
+    time::timeout,
+    try_join,
+};
 
 use crate::{
-    http::{HttpCommon, HttpRequest, HttpResponse},
+    framed::FRAMED,
+    http::{copy_body, Body, BodyLength, Headers, HttpCommon, HttpRequest, HttpResponse},
     server::session_manager::Session,
-    utils::{NeckError, NeckResult, NeckStream},
+    utils::{proxy_status, Failure, NeckError, NeckResult, NeckStream, SupportedStream},
 };
 
-use super::super::{manager::ConnectingResult, NeckServer};
+/// The buffer size of the pipe between a persistent HTTP proxy connection and its upstream.
+const PIPE_BUFFER: usize = 64 * 1024;
+
+use super::super::{
+    manager::ConnectingResult,
+    tenant::{Tenant, UserCredential},
+    NeckServer,
+};
 
 async fn connect_upstream(
     stream: &NeckStream,
     session: &Session,
     version: &str,
-    ctx: &Arc<NeckServer>,
+    tenant: &Tenant,
 ) -> NeckResult<Arc<NeckStream>> {
-    match stream.wait_together(ctx.manager.connect(session)).await? {
+    match stream
+        .wait_together(tenant.manager.connect(session))
+        .await?
+    {
         ConnectingResult::Ok(v) => Ok(v),
 
         // Not enough available worker connections in the manager.
-        ConnectingResult::BadGateway() => {
+        ConnectingResult::BadGateway(msg) => {
             println!(
-                "[{}] No available connections for {}",
-                stream.peer_addr, session.host
+                "[{}] No available connections for {}: {}",
+                stream.peer_addr, session.host, msg
             );
 
             HttpResponse::new(502, "Bad Gateway", version)
-                .add_payload(b"Connections are not available\n")
+                .add_header_kv("Proxy-Status", &proxy_status("proxy_internal_error", &msg))
+                .add_payload(msg.as_bytes())
+                .add_payload(b"\n")
                 .write_to_stream(stream)
                 .await?;
 
@@ -33,33 +52,149 @@ async fn connect_upstream(
             NeckError::wrap("Bad Gateway")
         }
 
-        // Cannot establish a connection with the provided host.
-        ConnectingResult::ServiceUnavailable(msg) => {
-            println!("[{}] Failed to connect {}", stream.peer_addr, session.host);
+        // Cannot establish a connection with the provided host, answer a status by the reason.
+        ConnectingResult::ServiceUnavailable(failure, msg) => {
+            println!(
+                "[{}] Failed to connect {}: {}",
+                stream.peer_addr, session.host, failure
+            );
 
-            HttpResponse::new(503, "Service Unavailable", version)
+            let (status, text) = failure.http_status();
+            HttpResponse::new(status, text, version)
+                .add_header_kv("Proxy-Status", &proxy_status(failure.proxy_error(), &msg))
                 .add_payload(msg.as_bytes())
                 .write_to_stream(stream)
                 .await?;
 
             stream.shutdown().await?;
-            NeckError::wrap("Service Unavailable")
+            NeckError::wrap(text)
         }
     }
 }
 
+/// Weld the client connection with upstream.
+/// If the upstream is a framed worker, and the tunnel is ended cleanly, put the worker back to the manager of the tenant.
+pub async fn weld_upstream(stream: NeckStream, upstream: Arc<NeckStream>, tenant: &Arc<Tenant>) {
+    if !upstream.capabilities.has(FRAMED) {
+        stream.weld(&upstream).await;
+        return;
+    }
+
+    if stream.weld_framed(&upstream).await.is_ok() {
+        // The client connection is no longer needed, close it before the worker is idle again.
+        drop(stream);
+
+        // The manager holds the worker until it is used or closed, so release it in another routine.
+        let tenant = tenant.clone();
+        tokio::spawn(async move { tenant.manager.release(upstream).await });
+    }
+}
+
+/// Authenticate the proxy user with the `Proxy-Authorization` header, and return its tenant and username.
+/// If the credential is missing or wrong, answer a 407 challenge and return an error.
+async fn authenticate(
+    stream: &NeckStream,
+    req: &HttpRequest,
+    ctx: &Arc<NeckServer>,
+    bound: Option<&Arc<Tenant>>,
+) -> NeckResult<(Arc<Tenant>, Option<String>)> {
+    let credential = req
+        .headers
+        .get_header_value("Proxy-Authorization")
+        .map(UserCredential::Basic);
+    if let Some(it) = ctx.tenants.bind_user(bound, credential) {
+        return Ok(it);
+    }
+
+    println!("[{}] Proxy authentication failed", stream.peer_addr);
+
+    HttpResponse::new(407, "Proxy Authentication Required", req.get_version())
+        .add_header("Proxy-Authenticate: Basic realm=\"neck\"")
+        .add_payload(b"Proxy authentication required\n")
+        .write_to_stream(stream)
+        .await?;
+
+    stream.shutdown().await?;
+    NeckError::wrap("Proxy Authentication Required")
+}
+
+/// Check the destination with the access rules, and return the matched rule.
+/// If the destination is denied, record the denial, answer a 403 status and return an error.
+async fn check_access(
+    stream: &NeckStream,
+    ctx: &NeckServer,
+    proto: &'static str,
+    host: &str,
+    version: &str,
+    tenant: &Tenant,
+    user: &Option<String>,
+) -> NeckResult<Option<String>> {
+    let decision = tenant.rules.check(host);
+    let rule = decision.rule.map(|r| r.to_string());
+
+    if decision.allowed {
+        return Ok(rule);
+    }
+
+    println!(
+        "[{}] Denied {} by rule '{}'",
+        stream.peer_addr,
+        host,
+        rule.as_deref().unwrap_or_default()
+    );
+    ctx.session_manager.record_denial(
+        proto,
+        stream.peer_addr,
+        host.to_string(),
+        user.clone(),
+        rule,
+        tenant,
+    );
+
+    let message = format!("Access to {} is denied\n", host);
+    HttpResponse::new(403, "Forbidden", version)
+        .add_header_kv(
+            "Proxy-Status",
+            &proxy_status(Failure::Forbidden.proxy_error(), &message),
+        )
+        .add_payload(message.as_bytes())
+        .write_to_stream(stream)
+        .await?;
+
+    stream.shutdown().await?;
+    NeckError::wrap("Forbidden")
+}
+
 /// Process an HTTPS proxy request.
 pub async fn https_proxy_handler(
     stream: NeckStream,
     req: &HttpRequest,
     ctx: &Arc<NeckServer>,
+    bound: Option<&Arc<Tenant>>,
 ) -> NeckResult<()> {
-    let session =
-        ctx.session_manager
-            .create_session("https", stream.peer_addr, req.get_uri().to_string());
+    let (tenant, user) = authenticate(&stream, req, ctx, bound).await?;
+    let rule = check_access(
+        &stream,
+        ctx,
+        "https",
+        req.get_uri(),
+        req.get_version(),
+        &tenant,
+        &user,
+    )
+    .await?;
+
+    let session = ctx.session_manager.create_session(
+        "https",
+        stream.peer_addr,
+        req.get_uri().to_string(),
+        user,
+        rule,
+        &tenant,
+    );
 
     // Attempt to connect upstream server via the proxy connection manager.
-    let upstream = connect_upstream(&stream, &session, req.get_version(), ctx).await?;
+    let upstream = connect_upstream(&stream, &session, req.get_version(), &tenant).await?;
 
     // Now, a successful connection has been established with the upstream server.
 
@@ -75,66 +210,285 @@ pub async fn https_proxy_handler(
         .write_to_stream(&stream)
         .await?;
 
-    // Weld the client connection with upstream.
-    stream.weld(&upstream).await;
+    weld_upstream(stream, upstream, &tenant).await;
 
     drop(session);
 
     Ok(())
 }
 
-pub async fn http_proxy_handler(
-    stream: NeckStream,
-    req: &HttpRequest,
-    ctx: &Arc<NeckServer>,
-) -> NeckResult<()> {
+/// Split an absolute URI of HTTP into the host (with a port) and the path.
+/// For example, "http://example.com/xxx" results ("example.com:80", "/xxx").
+fn split_http_uri(uri: &str) -> Option<(String, &str)> {
     // Remove "http://" from left
-    let uri = &req.get_uri()[7..];
+    let uri = uri.strip_prefix("http://")?;
 
     // Split host and path.
-    // For example:
-    // "example.com/xxx" result ("example.com", "/xxx")
-    // "example.com" result ("example.com", "/")
-    let (mut host, path) = match uri.find('/') {
-        Some(pos) => (Cow::Borrowed(&uri[..pos]), &uri[pos..]),
-        None => (Cow::Borrowed(uri), "/"),
+    let (host, path) = match uri.find('/') {
+        Some(pos) => (&uri[..pos], &uri[pos..]),
+        None => (uri, "/"),
     };
+    if host.is_empty() {
+        return None;
+    }
 
     // Fix host (append a default HTTP port).
     if !host.contains(':') {
-        host = Cow::Owned(format!("{}:80", host));
+        return Some((format!("{}:80", host), path));
     }
+    Some((host.to_string(), path))
+}
 
-    let session = ctx
-        .session_manager
-        .create_session("http", stream.peer_addr, host.to_string());
+/// Check if a connection persists after a message, by its version and the Connection header (RFC 9112 section 9.3).
+fn is_persistent(version: &str, headers: &Headers) -> bool {
+    let tokens = headers
+        .get_header_value("Connection")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let has = |token| tokens.split(',').any(|v| v.trim() == token);
+    if version == "HTTP/1.1" {
+        !has("close")
+    } else {
+        has("keep-alive")
+    }
+}
 
-    // Attempt to connect upstream server via the proxy connection manager.
-    let upstream = connect_upstream(&stream, &session, req.get_version(), ctx).await?;
+/// The upstream of a persistent HTTP proxy connection, which is reused by the following requests to the same host.
+struct Upstream {
+    host: String,
+    /// The tenant which the worker belongs to.
+    tenant: Arc<Tenant>,
+    /// A pipe welded with the upstream in another routine, so that a framed worker is read like a plain connection.
+    pipe: NeckStream,
+    /// The session of the upstream, with the user and the worker group.
+    session: Session,
+}
 
-    // Now, a successful connection has been established with the upstream server.
+impl Upstream {
+    fn new(
+        host: String,
+        upstream: Arc<NeckStream>,
+        tenant: &Arc<Tenant>,
+        session: Session,
+    ) -> Self {
+        let (near, far) = duplex(PIPE_BUFFER);
+        let (peer_addr, local_addr) = (upstream.peer_addr, upstream.local_addr);
+        let far = SupportedStream::Virtual(far, peer_addr, local_addr).into();
+        let welded = tenant.clone();
+        tokio::spawn(async move { weld_upstream(far, upstream, &welded).await });
+        Self {
+            host,
+            tenant: tenant.clone(),
+            pipe: SupportedStream::Virtual(near, peer_addr, local_addr).into(),
+            session,
+        }
+    }
 
-    println!(
-        "[{}] Connect to {} for http://{}",
-        stream.peer_addr, upstream.peer_addr, host
-    );
+    /// Check if the upstream serves a request to the `host` by the `user` of the `tenant`.
+    /// NOTE: Each request is authenticated again, so a request bound to another tenant, user or worker group
+    /// must not go out through this upstream, even if the host is the same.
+    fn serves(&self, host: &str, tenant: &Arc<Tenant>, user: &Option<String>) -> bool {
+        self.host == host
+            && Arc::ptr_eq(&self.tenant, tenant)
+            && self.session.user == *user
+            && self.session.group.as_deref() == tenant.routes.get_group(host)
+    }
 
+    /// Check if the upstream can be reused, which is not closed and has sent nothing unexpected.
+    async fn is_idle(&self) -> bool {
+        timeout(Duration::ZERO, self.pipe.quick_check_eof())
+            .await
+            .is_err()
+    }
+}
+
+/// The result of forwarding a request and its response.
+enum Exchanged {
+    /// Both the client connection and the upstream can be reused.
+    KeepAlive,
+    /// Either side has asked to close, or the response body ends with the connection.
+    Close,
+    /// The protocol has been switched by a 101 response, so that both sides are welded.
+    Upgraded,
+}
+
+/// Forward a request to the upstream (the `pipe`), and forward its response to the client.
+/// The bodies are passed through by their framing, without buffering them.
+async fn forward(
+    stream: &NeckStream,
+    req: &HttpRequest,
+    path: &str,
+    length: BodyLength,
+    pipe: &NeckStream,
+) -> NeckResult<Exchanged> {
     // Send an HTTP request (with the host part removed from original URI, leaving only the path part).
     let mut m_req = HttpRequest::new(req.get_method(), path, req.get_version());
 
-    // Copy headers excluding Proxy-Connection.
+    // Copy headers excluding Proxy-Connection and Proxy-Authorization.
+    // NOTE: The proxy credential must not be leaked to the upstream server.
     for h in req.get_headers().iter() {
-        if !h.eq_name("Proxy-Connection") {
+        if !h.eq_name("Proxy-Connection") && !h.eq_name("Proxy-Authorization") {
             m_req.headers.push(h.clone());
         }
     }
 
-    m_req.write_to_stream(&upstream).await?;
+    // Send the request body while waiting for the response, because the server may answer "100 Continue" first.
+    let send = async {
+        m_req.write_to_stream(pipe).await?;
+        let (mut r, mut w) = tokio::join!(stream.reader.lock(), pipe.writer.lock());
+        copy_body(&mut r, &mut *w, length).await
+    };
+    let receive = async {
+        loop {
+            // The interim responses are passed through, except the 101 which switches the protocol.
+            let res = HttpResponse::read_header_from(pipe).await?;
+            if (100..200).contains(&res.get_status()) && res.get_status() != 101 {
+                res.write_to_stream(stream).await?;
+                continue;
+            }
+            return Ok(res);
+        }
+    };
 
-    // Weld the client connection with upstream.
-    stream.weld(&upstream).await;
+    let mut res = match try_join!(send, receive) {
+        Ok((_, res)) => res,
+        Err(e) => {
+            println!(
+                "[{}] Failed to forward {}: {}",
+                stream.peer_addr,
+                req.get_uri(),
+                e
+            );
+            let message = e.to_string();
+            let _ = HttpResponse::new(502, "Bad Gateway", req.get_version())
+                .add_header_kv(
+                    "Proxy-Status",
+                    &proxy_status("http_response_incomplete", &message),
+                )
+                .add_payload(message.as_bytes())
+                .add_payload(b"\n")
+                .write_to_stream(stream)
+                .await;
+            return Ok(Exchanged::Close);
+        }
+    };
 
-    drop(session);
+    if res.get_status() == 101 {
+        res.write_to_stream(stream).await?;
+        return Ok(Exchanged::Upgraded);
+    }
 
-    Ok(())
+    let length = BodyLength::of_response(req.get_method(), res.get_status(), &res.headers)?;
+
+    // An HTTP/1.0 client does not know the chunked coding, so the body is decoded and ends with the connection.
+    // NOTE: The trailers are discarded, because the header section has been sent (RFC 9110 section 6.5.1).
+    if length == BodyLength::Chunked && req.get_version() == "HTTP/1.0" {
+        res.headers.remove("Transfer-Encoding");
+        res.write_to_stream(stream).await?;
+        let (mut r, mut w) = tokio::join!(pipe.reader.lock(), stream.writer.lock());
+        io::copy(&mut Body::new(&mut r, length), &mut *w).await?;
+        return Ok(Exchanged::Close);
+    }
+
+    res.write_to_stream(stream).await?;
+    let (mut r, mut w) = tokio::join!(pipe.reader.lock(), stream.writer.lock());
+    copy_body(&mut r, &mut *w, length).await?;
+
+    if length == BodyLength::UntilClose
+        || !is_persistent(req.get_version(), &req.headers)
+        || !is_persistent(res.get_version(), &res.headers)
+    {
+        return Ok(Exchanged::Close);
+    }
+    Ok(Exchanged::KeepAlive)
+}
+
+/// Process HTTP proxy requests on a persistent connection.
+/// Each request is routed by its absolute URI, and the upstream is reused by the following requests to the same host.
+pub async fn http_proxy_handler(
+    stream: NeckStream,
+    mut req: HttpRequest,
+    ctx: &Arc<NeckServer>,
+    bound: Option<&Arc<Tenant>>,
+) -> NeckResult<()> {
+    let mut upstream: Option<Upstream> = None;
+
+    loop {
+        // Only the absolute URIs of HTTP can be routed, and the body must be framed properly.
+        let routed = split_http_uri(req.get_uri())
+            .ok_or_else(|| String::from("An absolute URI is required"))
+            .and_then(|(host, path)| match BodyLength::of_request(&req.headers) {
+                Ok(length) => Ok((host, path, length)),
+                Err(e) => Err(e.to_string()),
+            });
+        let (host, path, length) = match routed {
+            Ok(it) => it,
+            Err(message) => {
+                HttpResponse::new(400, "Bad Request", req.get_version())
+                    .add_payload(message.as_bytes())
+                    .add_payload(b"\n")
+                    .write_to_stream(&stream)
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let (tenant, user) = authenticate(&stream, &req, ctx, bound).await?;
+        let rule = check_access(
+            &stream,
+            ctx,
+            "http",
+            &host,
+            req.get_version(),
+            &tenant,
+            &user,
+        )
+        .await?;
+
+        // Connect another upstream if the host, tenant, user or group is changed, or the previous one is no longer idle.
+        let reusable = match &upstream {
+            Some(it) => it.serves(&host, &tenant, &user) && it.is_idle().await,
+            None => false,
+        };
+        if !reusable {
+            drop(upstream.take());
+
+            let session = ctx.session_manager.create_session(
+                "http",
+                stream.peer_addr,
+                host.clone(),
+                user,
+                rule,
+                &tenant,
+            );
+
+            // Attempt to connect upstream server via the proxy connection manager.
+            let link = connect_upstream(&stream, &session, req.get_version(), &tenant).await?;
+
+            println!(
+                "[{}] Connect to {} for http://{}",
+                stream.peer_addr, link.peer_addr, host
+            );
+
+            upstream = Some(Upstream::new(host, link, &tenant, session));
+        }
+        let Some(current) = &upstream else {
+            unreachable!()
+        };
+
+        match forward(&stream, &req, path, length, &current.pipe).await? {
+            Exchanged::KeepAlive => (),
+            Exchanged::Close => return Ok(()),
+            Exchanged::Upgraded => {
+                stream.weld(&current.pipe).await;
+                return Ok(());
+            }
+        }
+
+        // Wait for the next request, the connection is closed by the client if nothing is received.
+        req = match HttpRequest::read_header_from(&stream).await {
+            Ok(it) => it,
+            Err(_) => return Ok(()),
+        };
+    }
 }
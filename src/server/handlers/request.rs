@@ -1,26 +1,47 @@
 use std::sync::Arc;
 
-use tokio::io::AsyncBufReadExt;
+use tokio::{io::AsyncBufReadExt, net::TcpStream};
 
 use crate::{
-    server::NeckServer,
+    server::{tenant::Tenant, NeckServer},
     utils::{NeckResult, NeckStream},
 };
 
-use super::{http::http_handler, socks5::sock5_handler};
+use super::{http::http_handler, socks4::sock4_handler, socks5::sock5_handler};
 
-pub async fn is_socks5(stream: &NeckStream) -> bool {
-    // Wait and peek the first buffer to check if the first byte is 5u8.
+/// Wait and peek the first byte, which is the version of SOCKS (4 or 5), or the method of HTTP.
+pub async fn peek_first_byte(stream: &NeckStream) -> Option<u8> {
     match stream.reader.lock().await.fill_buf().await {
-        Ok(v) => v.first().map_or(false, |x| *x == 5),
-        Err(_) => false,
+        Ok(v) => v.first().copied(),
+        Err(_) => None,
     }
 }
 
-pub async fn request_handler(stream: NeckStream, ctx: Arc<NeckServer>) -> NeckResult<()> {
-    if is_socks5(&stream).await {
-        sock5_handler(stream, ctx).await
-    } else {
-        http_handler(stream, ctx).await
+/// Peek the raw TcpStream to check if the first byte is 0x16, which is the record type of a TLS handshake.
+pub async fn is_tls(stream: &TcpStream) -> bool {
+    let mut buf = [0; 1];
+    match stream.peek(&mut buf).await {
+        Ok(1) => buf[0] == 0x16,
+        _ => false,
+    }
+}
+
+/// Handle a connection accepted by a listener, the `tenant` is set if the listener is dedicated to it.
+pub async fn request_handler(
+    stream: TcpStream,
+    ctx: Arc<NeckServer>,
+    tenant: Option<Arc<Tenant>>,
+) -> NeckResult<()> {
+    // Wrap the raw TcpStream with a NeckStream.
+    // If the TLS is enabled, perform the handshake first, unless it is a plain connection that is allowed.
+    let stream: NeckStream = match &ctx.tls {
+        Some(tls) if !ctx.allow_plain || is_tls(&stream).await => tls.accept(stream).await?.into(),
+        _ => stream.into(),
+    };
+
+    match peek_first_byte(&stream).await {
+        Some(5) => sock5_handler(stream, ctx, tenant).await,
+        Some(4) => sock4_handler(stream, ctx, tenant).await,
+        _ => http_handler(stream, ctx, tenant).await,
     }
 }
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use crate::{
+    socks4::{Socks4Request, CONNECT, GRANTED, REJECTED},
+    utils::{NeckError, NeckResult, NeckStream},
+};
+
+use super::{
+    super::{
+        manager::ConnectingResult,
+        tenant::{Tenant, UserCredential},
+        NeckServer,
+    },
+    proxy::weld_upstream,
+};
+
+pub async fn sock4_handler(
+    stream: NeckStream,
+    ctx: Arc<NeckServer>,
+    bound: Option<Arc<Tenant>>,
+) -> NeckResult<()> {
+    let req = Socks4Request::read_from(&mut *stream.reader.lock().await).await?;
+
+    if req.command != CONNECT {
+        reply(&stream, &req, REJECTED).await?;
+        NeckError::wrap("Unsupported socks4 cmd")?
+    }
+
+    // SOCKS4 has no password, so a USERID like "user:password" is verified as a credential,
+    // and any other USERID is regarded as anonymous.
+    let credential = req
+        .userid
+        .split_once(':')
+        .map(|(user, password)| UserCredential::Password(user, password));
+    let Some((tenant, user)) = ctx.tenants.bind_user(bound.as_ref(), credential) else {
+        println!(
+            "[{}] Socks4 user '{}' is not allowed",
+            stream.peer_addr, req.userid
+        );
+        return reply(&stream, &req, REJECTED).await;
+    };
+
+    // Check the destination with the access rules of the tenant.
+    let host = req.to_string();
+    let decision = tenant.rules.check(&host);
+    let rule = decision.rule.map(|r| r.to_string());
+    if !decision.allowed {
+        println!(
+            "[{}] Denied {} by rule '{}' [socks4]",
+            stream.peer_addr,
+            host,
+            rule.as_deref().unwrap_or_default()
+        );
+        ctx.session_manager
+            .record_denial("sock4", stream.peer_addr, host, user, rule, &tenant);
+        return reply(&stream, &req, REJECTED).await;
+    }
+
+    let session =
+        ctx.session_manager
+            .create_session("sock4", stream.peer_addr, host, user, rule, &tenant);
+
+    // SOCKS4 has a single code for all failures.
+    let upstream = match stream
+        .wait_together(tenant.manager.connect(&session))
+        .await?
+    {
+        ConnectingResult::Ok(it) => it,
+        ConnectingResult::BadGateway(msg) => {
+            println!(
+                "[{}] No available connections for {}: {}",
+                stream.peer_addr, req, msg
+            );
+            return reply(&stream, &req, REJECTED).await;
+        }
+        ConnectingResult::ServiceUnavailable(failure, _) => {
+            println!(
+                "[{}] Failed to connect {}: {}",
+                stream.peer_addr, req, failure
+            );
+            return reply(&stream, &req, REJECTED).await;
+        }
+    };
+
+    println!(
+        "[{}] Connect to {} for {} [socks4]",
+        stream.peer_addr, upstream.peer_addr, req
+    );
+    reply(&stream, &req, GRANTED).await?;
+
+    // Weld the client connection with upstream.
+    weld_upstream(stream, upstream, &tenant).await;
+
+    drop(session);
+
+    Ok(())
+}
+
+async fn reply(stream: &NeckStream, req: &Socks4Request, code: u8) -> NeckResult<()> {
+    req.reply(&mut *stream.writer.lock().await, code).await?;
+    Ok(())
+}
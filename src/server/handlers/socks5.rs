@@ -1,67 +1,223 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use crate::{
-    socks5::{ClientGreeting, ServerChoice, Socks5Message},
-    utils::{NeckError, NeckResult, NeckStream},
+    http::HttpResponse,
+    socks5::{
+        ClientGreeting, Host, ServerChoice, Socks5Message, UserPassRequest, UserPassResponse, BIND,
+        CONNECT, NO_AUTHENTICATION, UDP_ASSOCIATE, USERNAME_PASSWORD,
+    },
+    utils::{Failure, NeckError, NeckResult, NeckStream, ADDRESS_HEADER},
 };
 
-use super::super::{manager::ConnectingResult, NeckServer};
+use super::{
+    super::{
+        manager::ConnectingResult,
+        tenant::{Tenant, UserCredential},
+        NeckServer,
+    },
+    proxy::weld_upstream,
+    udp::udp_associate_handler,
+};
+
+pub async fn sock5_handler(
+    stream: NeckStream,
+    ctx: Arc<NeckServer>,
+    bound: Option<Arc<Tenant>>,
+) -> NeckResult<()> {
+    let (req, tenant, user) = read_sock5_request(&stream, &ctx, bound.as_ref()).await?;
 
-pub async fn sock5_handler(stream: NeckStream, ctx: Arc<NeckServer>) -> NeckResult<()> {
-    let req = read_sock5_request(&stream).await?;
+    // The datagrams of a UDP association are checked with the access rules one by one.
+    if req.action == UDP_ASSOCIATE {
+        return udp_associate_handler(stream, req, &ctx, &tenant, user).await;
+    }
+
+    // Check the destination with the access rules of the tenant.
+    let proto = if req.action == BIND { "bind" } else { "sock5" };
+    let host = req.host.to_string();
+    let decision = tenant.rules.check(&host);
+    let rule = decision.rule.map(|r| r.to_string());
+    if !decision.allowed {
+        println!(
+            "[{}] Denied {} by rule '{}' [socks5]",
+            stream.peer_addr,
+            host,
+            rule.as_deref().unwrap_or_default()
+        );
+        ctx.session_manager
+            .record_denial(proto, stream.peer_addr, host, user, rule, &tenant);
+        // The reply code 2 indicates "connection not allowed by ruleset".
+        req.clone().set_action(2).write_to_stream(&stream).await?;
+        return Ok(());
+    }
 
     let session =
         ctx.session_manager
-            .create_session("sock5", stream.peer_addr, req.host.to_string());
+            .create_session(proto, stream.peer_addr, host, user, rule, &tenant);
 
-    match stream.wait_together(ctx.manager.connect(&session)).await? {
-        ConnectingResult::Ok(upstream) => {
-            println!(
-                "[{}] Connect to {} for {} [socks5]",
-                stream.peer_addr, upstream.peer_addr, req.host
-            );
-            req.clone().set_action(0).write_to_stream(&stream).await?;
+    let connecting = if req.action == BIND {
+        tenant.manager.bind(&session)
+    } else {
+        tenant.manager.connect(&session)
+    };
+    let upstream = match stream.wait_together(connecting).await? {
+        ConnectingResult::Ok(it) => it,
+        failed => return reply_failure(&stream, &req, failed).await,
+    };
 
-            // Weld the client connection with upstream.
-            stream.weld(&upstream).await;
+    if req.action == BIND {
+        if !reply_bind_notices(&stream, &req, &upstream).await? {
+            return Ok(());
         }
-        ConnectingResult::BadGateway() => {
+    } else {
+        println!(
+            "[{}] Connect to {} for {} [socks5]",
+            stream.peer_addr, upstream.peer_addr, req.host
+        );
+        req.clone().set_action(0).write_to_stream(&stream).await?;
+    }
+
+    // Weld the client connection with upstream.
+    weld_upstream(stream, upstream, &tenant).await;
+
+    drop(session);
+
+    Ok(())
+}
+
+/// Reply the failure of acquiring an upstream, with a reply code by the reason, such as "connection refused".
+pub async fn reply_failure(
+    stream: &NeckStream,
+    req: &Socks5Message,
+    failed: ConnectingResult,
+) -> NeckResult<()> {
+    let code = match failed {
+        ConnectingResult::BadGateway(msg) => {
             println!(
-                "[{}] No available connections for {}",
-                stream.peer_addr, req.host
+                "[{}] No available connections for {}: {}",
+                stream.peer_addr, req.host, msg
             );
-            req.clone().set_action(1).write_to_stream(&stream).await?;
+            1
         }
-        ConnectingResult::ServiceUnavailable(_) => {
-            println!("[{}] Failed to connect {}", stream.peer_addr, req.host);
-            req.clone().set_action(1).write_to_stream(&stream).await?;
+        ConnectingResult::ServiceUnavailable(failure, _) => {
+            println!(
+                "[{}] Failed to connect {}: {}",
+                stream.peer_addr, req.host, failure
+            );
+            failure.socks5_reply()
         }
+        ConnectingResult::Ok(_) => 0,
     };
+    req.clone().set_action(code).write_to_stream(stream).await?;
+    Ok(())
+}
 
-    drop(session);
+/// Read a notice of a BIND request from the worker, which is the bound address or the connected peer.
+async fn read_bind_notice(link: &NeckStream) -> Result<SocketAddr, Failure> {
+    let res = HttpResponse::read_from(link)
+        .await
+        .map_err(|_| Failure::Transport)?;
+    if res.get_status() != 200 {
+        return Err(Failure::from_headers(&res.headers));
+    }
+    res.headers
+        .get_header_value(ADDRESS_HEADER)
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or(Failure::Transport)
+}
 
-    Ok(())
+/// Send the two replies of a BIND request (RFC 1928), with the address bound by the worker,
+/// and then the peer connected to it. Return false if the peer has not connected.
+async fn reply_bind_notices(
+    stream: &NeckStream,
+    req: &Socks5Message,
+    link: &NeckStream,
+) -> NeckResult<bool> {
+    for notice in ["Bound", "Accepted"] {
+        let mut reply = req.clone();
+        match stream.wait_together(read_bind_notice(link)).await? {
+            Ok(addr) => {
+                println!(
+                    "[{}] {} {} for {} [socks5]",
+                    stream.peer_addr, notice, addr, req.host
+                );
+                reply.host = Host::from(addr);
+                reply.set_action(0).write_to_stream(stream).await?;
+            }
+            Err(failure) => {
+                println!(
+                    "[{}] Failed to bind for {}: {}",
+                    stream.peer_addr, req.host, failure
+                );
+                reply
+                    .set_action(failure.socks5_reply())
+                    .write_to_stream(stream)
+                    .await?;
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
 }
 
-async fn read_sock5_request(stream: &NeckStream) -> NeckResult<Socks5Message> {
+async fn read_sock5_request(
+    stream: &NeckStream,
+    ctx: &Arc<NeckServer>,
+    bound: Option<&Arc<Tenant>>,
+) -> NeckResult<(Socks5Message, Arc<Tenant>, Option<String>)> {
     let mut reader = stream.reader.lock().await;
     let mut writer = stream.writer.lock().await;
 
     // Read a socks5 ClientGreeting reqeuest.
     let greeting = ClientGreeting::read_from(&mut reader).await?;
 
-    // Forcing the use of the 0 value, regardless of whether the ClientGreeting supports.
-    ServerChoice::new(greeting.ver, 0)
+    let method = greeting.choose_method(
+        ctx.tenants.has_users(bound),
+        ctx.tenants.allows_anonymous(bound),
+    );
+    ServerChoice::new(greeting.ver, method)
         .write_to(&mut *writer)
         .await?;
 
+    let (tenant, user) = match method {
+        // Perform the username/password sub-negotiation (RFC 1929), the user is bound to a tenant by the credential.
+        USERNAME_PASSWORD => {
+            let auth = UserPassRequest::read_from(&mut reader).await?;
+            let credential = UserCredential::Password(&auth.user, &auth.password);
+            let bound = ctx.tenants.bind_user(bound, Some(credential));
+
+            UserPassResponse::new(if bound.is_some() { 0 } else { 1 })
+                .write_to(&mut *writer)
+                .await?;
+
+            match bound {
+                Some(it) => it,
+                None => {
+                    println!("[{}] Socks5 authentication failed", stream.peer_addr);
+                    NeckError::wrap("Socks5 authentication failed")?
+                }
+            }
+        }
+        NO_AUTHENTICATION => match ctx.tenants.bind_user(bound, None) {
+            Some(it) => it,
+            None => NeckError::wrap("Socks5 anonymous user is not allowed")?,
+        },
+        // The client must close the connection after receiving 0xFF.
+        _ => {
+            println!(
+                "[{}] No acceptable socks5 authentication methods",
+                stream.peer_addr
+            );
+            NeckError::wrap("No acceptable socks5 authentication methods")?
+        }
+    };
+
     let req = Socks5Message::read_from(&mut reader).await?;
     // println!("{:#?}", req);
 
-    if req.action != 1 {
+    if ![CONNECT, BIND, UDP_ASSOCIATE].contains(&req.action) {
         req.clone().set_action(7).write_to(&mut *writer).await?;
         NeckError::wrap("Unsupported socks5 cmd")?
     }
 
-    Ok(req)
+    Ok((req, tenant, user))
 }
@@ -0,0 +1,173 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use tokio::{
+    io::AsyncReadExt,
+    net::UdpSocket,
+    select,
+    time::{sleep_until, Instant},
+};
+
+use crate::{
+    framed::{Frame, END, MAX_PAYLOAD},
+    socks5::{build_udp_reply, parse_udp_request, Host, Socks5Message},
+    utils::{NeckResult, NeckStream},
+};
+
+use super::{
+    super::{manager::ConnectingResult, session_manager::Session, tenant::Tenant, NeckServer},
+    socks5::reply_failure,
+};
+
+/// Process a SOCKS5 UDP ASSOCIATE request.
+/// The datagrams are received by a relay socket of the server, and carried by DATA frames over a worker,
+/// where each payload is the destination (or the source) encoded like SOCKS5, followed by the data.
+pub async fn udp_associate_handler(
+    stream: NeckStream,
+    req: Socks5Message,
+    ctx: &Arc<NeckServer>,
+    tenant: &Arc<Tenant>,
+    user: Option<String>,
+) -> NeckResult<()> {
+    let session = ctx.session_manager.create_session(
+        "udp",
+        stream.peer_addr,
+        req.host.to_string(),
+        user,
+        None,
+        tenant,
+    );
+
+    let link = match stream
+        .wait_together(tenant.manager.associate(&session))
+        .await?
+    {
+        ConnectingResult::Ok(it) => it,
+        failed => return reply_failure(&stream, &req, failed).await,
+    };
+
+    // Open the relay socket on the address which the client has connected to, and tell the client its address.
+    let socket = UdpSocket::bind(SocketAddr::new(stream.local_addr.ip(), 0)).await?;
+    let relay = socket.local_addr()?;
+    println!(
+        "[{}] Associate UDP at {} over {} [socks5]",
+        stream.peer_addr, relay, link.peer_addr
+    );
+    let mut reply = req.clone();
+    reply.host = Host::from(relay);
+    reply.set_action(0).write_to_stream(&stream).await?;
+
+    relay_datagrams(&stream, &socket, &link, ctx, tenant, &session).await;
+    println!("[{}] UDP association at {} ended", stream.peer_addr, relay);
+
+    drop(session);
+
+    Ok(())
+}
+
+/// Relay datagrams between the client and the worker, until the control connection is closed,
+/// the worker ends the association, or nothing is relayed for the `timeout`.
+async fn relay_datagrams(
+    stream: &NeckStream,
+    socket: &UdpSocket,
+    link: &NeckStream,
+    ctx: &NeckServer,
+    tenant: &Tenant,
+    session: &Session,
+) {
+    let timeout = ctx.udp_timeout;
+    let (mut lr, mut lw) = tokio::join!(link.reader.lock(), link.writer.lock());
+
+    // The client address is fixed by the first datagram, which must come from the host of the control connection.
+    let client = OnceLock::<SocketAddr>::new();
+    let active = Mutex::new(Instant::now());
+    let touch = || *active.lock().unwrap() = Instant::now();
+
+    // Send the datagrams from the client to the worker, the denied or fragmented ones are dropped.
+    let outgoing = async {
+        let mut buf = vec![0; 65536];
+        while let Ok((size, from)) = socket.recv_from(&mut buf).await {
+            if from.ip() != stream.peer_addr.ip() || *client.get_or_init(|| from) != from {
+                continue;
+            }
+            let Some((host, _)) = parse_udp_request(&buf[..size]) else {
+                continue;
+            };
+            let host = host.to_string();
+            let decision = tenant.rules.check(&host);
+            if !decision.allowed {
+                println!("[{}] Denied {} [udp]", stream.peer_addr, host);
+                let rule = decision.rule.map(|r| r.to_string());
+                ctx.session_manager.record_denial(
+                    "udp",
+                    stream.peer_addr,
+                    host,
+                    session.user.clone(),
+                    rule,
+                    tenant,
+                );
+                continue;
+            }
+            // The payload is the request without the RSV and FRAG fields.
+            if size - 3 > MAX_PAYLOAD {
+                continue;
+            }
+            if Frame::data(&buf[3..size]).write_to(&mut *lw).await.is_err() {
+                break;
+            }
+            touch();
+        }
+    };
+
+    // Send the datagrams from the worker to the client, until an END frame is received.
+    let incoming = async {
+        while let Ok(frame) = Frame::read_from(&mut *lr).await {
+            if frame.kind == END {
+                break;
+            }
+            let Some(client) = client.get() else {
+                continue;
+            };
+            let Some((host, data)) = Host::decode(&frame.payload) else {
+                continue;
+            };
+            if socket
+                .send_to(&build_udp_reply(&host, data), client)
+                .await
+                .is_err()
+            {
+                break;
+            }
+            touch();
+        }
+    };
+
+    // The client must keep the control connection open, and never send anything on it.
+    let closed = async {
+        let mut reader = stream.reader.lock().await;
+        let mut buf = [0; 64];
+        while reader.read(&mut buf).await.unwrap_or(0) > 0 {}
+    };
+
+    let idle = async {
+        loop {
+            let deadline = *active.lock().unwrap() + timeout;
+            if deadline <= Instant::now() {
+                break;
+            }
+            sleep_until(deadline).await;
+        }
+    };
+
+    select! {
+        _ = outgoing => (),
+        _ = incoming => (),
+        _ = closed => (),
+        _ = idle => println!("[{}] UDP association timed out", stream.peer_addr),
+    }
+
+    // Tell the worker to end the association, the worker is not reused anyway.
+    let _ = Frame::end().write_to(&mut *lw).await;
+}
@@ -2,10 +2,10 @@ use std::sync::Arc;
 
 use crate::{
     server::session_manager::Session,
-    utils::{connect, NeckStream},
+    utils::{connect, resolve, Capabilities, Failure, NeckStream},
 };
 
-use super::{ConnectingResult, ConnectionManager, PBF};
+use super::{ConnectingResult, ConnectionManager, WorkerInfo, PBF};
 
 pub struct DirectModeManager {}
 
@@ -15,19 +15,63 @@ impl ConnectionManager for DirectModeManager {
         Box::pin(async { 0 })
     }
 
+    fn workers(&self) -> PBF<'_, Vec<WorkerInfo>> {
+        // There is no worker.
+        Box::pin(async { Vec::new() })
+    }
+
+    fn demand<'a>(&'a self, _stream: &'a NeckStream) -> PBF<'a, Option<i64>> {
+        // Joined workers are never used.
+        Box::pin(async { None })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Joined workers are never used.
+        Capabilities::default()
+    }
+
     fn join(&self, _stream: NeckStream) -> PBF<()> {
         // There is nothing to do.
         // Joined connection will lose all references and will be recycled later.
         Box::pin(async move {})
     }
 
+    fn release(&self, _stream: Arc<NeckStream>) -> PBF<'_, ()> {
+        // There is no worker to release.
+        Box::pin(async move {})
+    }
+
     fn connect<'a>(&'a self, session: &'a Session) -> PBF<'a, ConnectingResult> {
         Box::pin(async move {
+            // Resolve the destination first, so that a DNS failure can be told.
+            let addrs = match resolve(&session.host).await {
+                Ok(it) => it,
+                Err(e) => return ConnectingResult::ServiceUnavailable(Failure::Dns, e.to_string()),
+            };
+
             // Pass through the tokio TcpStream::connect.
-            match connect(&session.host).await {
+            match connect(&addrs[..]).await {
                 Ok(stream) => ConnectingResult::Ok(Arc::new(stream.into())),
-                Err(e) => ConnectingResult::ServiceUnavailable(e.to_string()),
+                Err(e) => {
+                    ConnectingResult::ServiceUnavailable(Failure::from_error(&e), e.to_string())
+                }
             }
         })
     }
+
+    fn associate<'a>(&'a self, _session: &'a Session) -> PBF<'a, ConnectingResult> {
+        Box::pin(async move {
+            // There is no worker to relay datagrams.
+            let message = String::from("UDP is not supported in the direct mode");
+            ConnectingResult::ServiceUnavailable(Failure::Other, message)
+        })
+    }
+
+    fn bind<'a>(&'a self, _session: &'a Session) -> PBF<'a, ConnectingResult> {
+        Box::pin(async move {
+            // There is no worker to listen in the other zone.
+            let message = String::from("BIND is not supported in the direct mode");
+            ConnectingResult::ServiceUnavailable(Failure::Other, message)
+        })
+    }
 }
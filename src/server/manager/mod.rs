@@ -1,28 +1,86 @@
 mod direct;
 mod pool;
+mod selection;
 
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
-use crate::utils::{NeckStream, PBF};
+use serde::Serialize;
+
+use crate::utils::{Capabilities, Failure, NeckStream, PBF};
 
 pub use direct::*;
 pub use pool::*;
+pub use selection::*;
 
 use super::session_manager::Session;
 
 pub enum ConnectingResult {
     Ok(Arc<NeckStream>),
-    BadGateway(),
-    ServiceUnavailable(String),
+    /// No worker is available, with a reason.
+    BadGateway(String),
+    /// The destination cannot be connected, with the reason and a message.
+    ServiceUnavailable(Failure, String),
+}
+
+/// The information of a worker in the pool, which is shown in the pool listing.
+#[derive(Debug, Serialize)]
+pub struct WorkerInfo {
+    pub addr: SocketAddr,
+    /// The subject of the verified client certificate.
+    pub identity: Option<String>,
+    /// The join path of the tenant, None for the default tenant.
+    pub tenant: Option<String>,
+    /// The worker group declared when joining.
+    pub group: Option<String>,
+    /// The software version announced by the worker.
+    pub version: Option<String>,
+    /// The capabilities negotiated when joining.
+    pub capabilities: Capabilities,
+    /// The number of open streams, None if the worker is not multiplexed.
+    pub streams: Option<usize>,
+}
+
+impl WorkerInfo {
+    pub fn new(stream: &NeckStream, streams: Option<usize>) -> Self {
+        Self {
+            addr: stream.peer_addr,
+            identity: stream.peer_subject.clone(),
+            tenant: None,
+            group: stream.group.clone(),
+            version: stream.peer_agent.clone(),
+            capabilities: stream.capabilities.clone(),
+            streams,
+        }
+    }
 }
 
 pub trait ConnectionManager: Send + Sync {
     /// Get the number of current avaliable connections.
     fn len(&self) -> PBF<usize>;
 
+    /// List all workers in the pool, including multiplexed ones.
+    fn workers(&self) -> PBF<'_, Vec<WorkerInfo>>;
+
+    /// Get the capabilities supported by the manager, which are selected for workers when joining.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Get the demand for the client host of a joining worker, None if the manager does not pool idle workers.
+    fn demand<'a>(&'a self, stream: &'a NeckStream) -> PBF<'a, Option<i64>>;
+
     /// Join the manager.
     fn join(&self, stream: NeckStream) -> PBF<()>;
 
+    /// Return a framed worker to the manager after its tunnel is ended cleanly.
+    /// NOTE: Like `join`, the returned future does not complete until the worker leaves the manager again.
+    fn release(&self, stream: Arc<NeckStream>) -> PBF<'_, ()>;
+
     /// Attempt to acquire a NeckStream from the manager.
     fn connect<'a>(&'a self, session: &'a Session) -> PBF<'a, ConnectingResult>;
+
+    /// Attempt to acquire a NeckStream from the manager for a UDP association, which carries datagrams in frames.
+    fn associate<'a>(&'a self, session: &'a Session) -> PBF<'a, ConnectingResult>;
+
+    /// Attempt to acquire a NeckStream from the manager for a BIND request,
+    /// which is followed by the notices of the bound address and the connected peer.
+    fn bind<'a>(&'a self, session: &'a Session) -> PBF<'a, ConnectingResult>;
 }
@@ -1,85 +1,546 @@
-use std::{collections::HashMap, net::SocketAddr, ops::Add, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::{IpAddr, SocketAddr},
+    ops::Add,
+    sync::{Arc, Mutex as SyncMutex, Weak},
+    time::Duration,
+};
+
+use std::future::pending;
 
 use tokio::{
-    sync::{Mutex, Notify},
-    time::{timeout_at, Instant},
+    select,
+    sync::{oneshot, Mutex, MutexGuard},
+    time::{sleep, timeout, timeout_at, Instant},
 };
 
 use crate::{
+    framed::FRAMED,
     http::{HttpCommon, HttpRequest, HttpResponse},
+    mux::{Mux, MUX},
     server::session_manager::Session,
-    utils::NeckStream,
+    utils::{
+        Capabilities, Failure, NeckError, NeckResult, NeckStream, BIND_RELAY, DEMAND_HEADER,
+        HEARTBEAT, UDP_RELAY,
+    },
+};
+
+use super::{
+    selection::{Candidate, SelectionPolicy, Selector},
+    ConnectingResult, ConnectionManager, WorkerInfo, PBF,
 };
 
-use super::{ConnectingResult, ConnectionManager, PBF};
+/// The window to measure the rate of sessions taking idle workers.
+const DEMAND_WINDOW: Duration = Duration::from_secs(10);
+
+/// Idle workers are wanted for the sessions that will arrive within this duration, at the recent rate.
+const DEMAND_RESERVE: Duration = Duration::from_secs(2);
+
+/// The maximum number of workers tried for a session, if the workers fail by themselves.
+const MAX_WORKER_ATTEMPTS: usize = 3;
+
+/// Split the shortfall of idle workers among client hosts.
+/// A shortfall is rounded up, and a surplus is rounded down, so that clients do not shrink too much.
+pub fn split_demand(wanted: usize, idle: usize, hosts: usize) -> i64 {
+    let shortfall = wanted as i64 - idle as i64;
+    let hosts = hosts.max(1) as i64;
+    if shortfall > 0 {
+        (shortfall + hosts - 1) / hosts
+    } else {
+        shortfall / hosts
+    }
+}
+
+/// The heartbeat settings for idle workers in the pool.
+pub struct Heartbeat {
+    /// The idle time before sending a PING request to a worker.
+    pub interval: Duration,
+    /// The deadline for a worker to reply the PING request.
+    pub timeout: Duration,
+}
+
+/// The settings of the queue of sessions waiting for workers.
+pub struct WaitQueue {
+    /// The deadline for a session to get a worker.
+    pub timeout: Duration,
+    /// The maximum number of waiting sessions, further sessions fail immediately.
+    pub max_len: usize,
+}
+
+/// A session waiting for a worker of its group.
+struct Waiter {
+    session: Session,
+    /// The capability that the worker must have, such as the UDP relay or the BIND relay.
+    capability: Option<&'static str>,
+    sender: oneshot::Sender<Arc<NeckStream>>,
+}
+
+impl Waiter {
+    /// Check if the waiter can take the worker.
+    fn accepts(&self, stream: &NeckStream) -> bool {
+        self.session.group == stream.group && is_capable(stream, self.capability)
+    }
+}
+
+/// Check if a worker has the `capability`, any worker is capable if it is None.
+fn is_capable(stream: &NeckStream, capability: Option<&str>) -> bool {
+    capability.is_none_or(|c| stream.capabilities.has(c))
+}
+
+/// Renumber the waiting sessions of each group after the queue has changed, starting from 1.
+/// The sessions that have given up waiting are removed.
+fn update_positions(waiters: &mut VecDeque<Waiter>) {
+    waiters.retain(|w| !w.sender.is_closed());
+    let mut counts: HashMap<Option<&str>, usize> = HashMap::new();
+    for waiter in waiters.iter() {
+        let count = counts.entry(waiter.session.group.as_deref()).or_insert(0);
+        *count += 1;
+        waiter.session.set_queue_position(*count);
+    }
+}
+
+/// Remove the waiter of a session from the queue once `take` has returned, or its future has been dropped,
+/// such as when the user has gone away, so that the session neither holds a position nor gets a worker.
+struct WaitGuard<'a> {
+    waiters: &'a SyncMutex<VecDeque<Waiter>>,
+    session: &'a Session,
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        let mut waiters = self.waiters.lock().unwrap();
+        let len = waiters.len();
+        waiters.retain(|w| !Arc::ptr_eq(&w.session, self.session));
+        if waiters.len() != len {
+            update_positions(&mut waiters);
+        }
+        self.session.set_queue_position(0);
+    }
+}
+
+/// An idle worker in the pool.
+struct Idle {
+    stream: Arc<NeckStream>,
+    /// When the worker joined the pool, or was put back after its last tunnel.
+    /// NOTE: A heartbeat does not reset it, the worker is still idle.
+    since: Instant,
+}
 
 pub struct PoolModeManager {
     size: usize,
-    storage: Arc<Mutex<HashMap<SocketAddr, Arc<NeckStream>>>>,
-    conn_joined: Arc<Notify>,
+    storage: Arc<Mutex<HashMap<SocketAddr, Idle>>>,
+    selector: Mutex<Selector>,
+    /// The workers and multiplexed streams that have been taken out, to count the open sessions of client hosts.
+    taken: Mutex<Vec<Weak<NeckStream>>>,
+    /// When idle workers were taken recently, with their groups, to measure the demand.
+    takes: Mutex<VecDeque<(Instant, Option<String>)>>,
+    /// The multiplexed workers, which are shared by sessions instead of being taken out.
+    muxes: Arc<Mutex<Vec<Arc<Mux>>>>,
+    /// The sessions waiting for workers, in the order of arrival.
+    /// NOTE: It is changed only with the `storage` locked, so that a joining worker cannot be missed by a waiter,
+    /// except that a waiter is removed by its `WaitGuard`, which cannot wait for the lock.
+    waiters: SyncMutex<VecDeque<Waiter>>,
+    wait_queue: WaitQueue,
+    /// None if the heartbeat is disabled.
+    heartbeat: Option<Heartbeat>,
 }
 
 impl PoolModeManager {
-    pub fn new(size: usize) -> PoolModeManager {
+    pub fn new(
+        size: usize,
+        heartbeat: Option<Heartbeat>,
+        policy: SelectionPolicy,
+        wait_queue: WaitQueue,
+    ) -> PoolModeManager {
         Self {
             size,
             storage: Arc::new(Mutex::new(HashMap::new())),
-            conn_joined: Arc::new(Notify::new()),
+            selector: Mutex::new(Selector::new(policy)),
+            taken: Mutex::new(Vec::new()),
+            takes: Mutex::new(VecDeque::new()),
+            muxes: Arc::new(Mutex::new(Vec::new())),
+            waiters: SyncMutex::new(VecDeque::new()),
+            wait_queue,
+            heartbeat,
         }
     }
 
-    async fn take(&self) -> Option<Arc<NeckStream>> {
+    /// Take a worker of the group of the `session` (None for the workers without a group), which has the `capability`.
+    /// If no worker is available, wait in the queue until a worker is handed over, or return an error with a reason.
+    async fn take(
+        &self,
+        session: &Session,
+        capability: Option<&'static str>,
+    ) -> Result<Arc<NeckStream>, String> {
+        let group = session.group.as_deref();
+
         // Declare a deadline.
-        let deadline = Instant::now().add(Duration::from_secs(5));
+        let deadline = Instant::now().add(self.wait_queue.timeout);
+        let _guard = WaitGuard {
+            waiters: &self.waiters,
+            session,
+        };
         loop {
-            // Try to take a NeckStream from pool.
-            if let result @ Some(_) = {
+            // Prefer opening a logical stream on a multiplexed worker, which does not use up the worker.
+            if let Some(stream) = self.open_mux(group, capability).await {
+                let stream = Arc::new(stream);
+                self.track(&stream).await;
+                return Ok(stream);
+            }
+
+            let mut receiver = {
                 let mut map = self.storage.lock().await;
-                map.keys()
-                    .into_iter()
-                    .next()
-                    .map(|v| *v)
-                    .map_or(None, |k| map.remove(&k))
-            } {
-                // If the NeckStream is take successfully, return it directly.
-                return result;
-            }
-            // Otherwise, wait for a notification to retry it, and if the timeout occurs, return None.
-            if let Err(_) = timeout_at(deadline, self.conn_joined.notified()).await {
-                return None;
+
+                // A multiplexed worker may have joined in between, retry to open a stream on it.
+                if self.has_mux(group, capability).await {
+                    continue;
+                }
+
+                // Try to take a NeckStream from pool with the selection policy.
+                if let Some(stream) = self.take_idle(&mut map, group, capability).await {
+                    // If the NeckStream is take successfully, return it directly.
+                    self.record_take(&stream).await;
+                    self.track(&stream).await;
+                    return Ok(stream);
+                }
+
+                // Otherwise, join the end of the queue, unless the queue is full.
+                let mut waiters = self.waiters.lock().unwrap();
+                update_positions(&mut waiters);
+                if waiters.len() >= self.wait_queue.max_len {
+                    return Err(String::from("Too many sessions are waiting for workers"));
+                }
+                let (sender, receiver) = oneshot::channel();
+                waiters.push_back(Waiter {
+                    session: session.clone(),
+                    capability,
+                    sender,
+                });
+                update_positions(&mut waiters);
+                receiver
+            };
+
+            match timeout_at(deadline, &mut receiver).await {
+                // A worker has been handed over.
+                Ok(Ok(stream)) => {
+                    self.track(&stream).await;
+                    return Ok(stream);
+                }
+                // The handover has failed (a stream cannot be opened on a multiplexed worker), retry it.
+                Ok(Err(_)) => continue,
+                // Timed out, leave the queue.
+                // NOTE: The waiter is removed before checking the receiver, so no worker can be handed over after that.
+                Err(_) => {
+                    {
+                        let mut waiters = self.waiters.lock().unwrap();
+                        waiters.retain(|w| !Arc::ptr_eq(&w.session, session));
+                        update_positions(&mut waiters);
+                    }
+                    session.set_queue_position(0);
+
+                    // A worker may have been handed over right before leaving.
+                    if let Ok(stream) = receiver.try_recv() {
+                        self.track(&stream).await;
+                        return Ok(stream);
+                    }
+
+                    return Err(match (group, capability) {
+                        (_, Some(capability)) => {
+                            format!("No worker supporting '{}' is available", capability)
+                        }
+                        (Some(group), None) => {
+                            format!("No worker of group '{}' is available", group)
+                        }
+                        (None, None) => String::from("Connections are not available"),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Hand a worker over to the longest waiting session of its group, which the worker is capable for.
+    /// Return the worker if no session is waiting for it.
+    fn hand_over(
+        waiters: &mut VecDeque<Waiter>,
+        mut stream: Arc<NeckStream>,
+    ) -> Option<Arc<NeckStream>> {
+        while let Some(i) = waiters.iter().position(|w| w.accepts(&stream)) {
+            let waiter = waiters.remove(i)?;
+            waiter.session.set_queue_position(0);
+            // The session may have given up waiting, try the next one.
+            match waiter.sender.send(stream) {
+                Ok(_) => {
+                    update_positions(waiters);
+                    return None;
+                }
+                Err(it) => stream = it,
+            }
+        }
+        update_positions(waiters);
+        Some(stream)
+    }
+
+    /// Record that an idle worker is taken, and forget the records out of the window.
+    async fn record_take(&self, stream: &NeckStream) {
+        let mut takes = self.takes.lock().await;
+        while takes
+            .front()
+            .is_some_and(|(t, _)| t.elapsed() > DEMAND_WINDOW)
+        {
+            takes.pop_front();
+        }
+        takes.push_back((Instant::now(), stream.group.clone()));
+    }
+
+    /// Compute the demand for the client host of a worker, in the group of the worker.
+    /// If the worker is `idle`, it is counted as an idle worker, even if it is out of the pool for the moment.
+    async fn compute_demand(&self, stream: &NeckStream, idle: bool) -> i64 {
+        let group = &stream.group;
+
+        // The idle workers of the group, and their client hosts.
+        let (count, hosts) = {
+            let map = self.storage.lock().await;
+            let mut hosts = HashSet::from([stream.peer_addr.ip()]);
+            let mut count = 0;
+            for i in map.values().filter(|i| i.stream.group == *group) {
+                if !std::ptr::eq(&*i.stream, stream) {
+                    hosts.insert(i.stream.peer_addr.ip());
+                    count += 1;
+                }
+            }
+            (count + usize::from(idle), hosts.len())
+        };
+
+        // The waiting sessions want idle workers now, and the sessions arriving soon at the recent rate.
+        let waiting = self
+            .waiters
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|w| w.session.group == *group)
+            .count();
+        let recent = self
+            .takes
+            .lock()
+            .await
+            .iter()
+            .filter(|(t, g)| g == group && t.elapsed() <= DEMAND_WINDOW)
+            .count() as u128;
+        let soon = (recent * DEMAND_RESERVE.as_millis()).div_ceil(DEMAND_WINDOW.as_millis());
+
+        split_demand(waiting + soon as usize, count, hosts)
+    }
+
+    /// Check if there is a multiplexed worker of the `group` with the `capability`.
+    async fn has_mux(&self, group: Option<&str>, capability: Option<&str>) -> bool {
+        self.muxes
+            .lock()
+            .await
+            .iter()
+            .any(|m| m.get_link().group.as_deref() == group && is_capable(m.get_link(), capability))
+    }
+
+    /// Select an idle worker of the `group` with the `capability` and remove it from the pool.
+    async fn take_idle(
+        &self,
+        map: &mut MutexGuard<'_, HashMap<SocketAddr, Idle>>,
+        group: Option<&str>,
+        capability: Option<&str>,
+    ) -> Option<Arc<NeckStream>> {
+        let candidates: Vec<Candidate> = map
+            .values()
+            .filter(|i| i.stream.group.as_deref() == group && is_capable(&i.stream, capability))
+            .map(|i| Candidate {
+                addr: i.stream.peer_addr,
+                idle_since: i.since,
+            })
+            .collect();
+        let sessions = self.count_sessions().await;
+        let addr = self.selector.lock().await.select(&candidates, &sessions)?;
+        map.remove(&addr).map(|i| i.stream)
+    }
+
+    /// Remember a taken stream, until it is dropped or put back to the pool.
+    async fn track(&self, stream: &Arc<NeckStream>) {
+        let mut taken = self.taken.lock().await;
+        taken.retain(|w| w.strong_count() > 0);
+        taken.push(Arc::downgrade(stream));
+    }
+
+    /// Count the taken streams that are still in use, by client host.
+    async fn count_sessions(&self) -> HashMap<IpAddr, usize> {
+        let mut counts = HashMap::new();
+        for stream in self.taken.lock().await.iter().filter_map(Weak::upgrade) {
+            *counts.entry(stream.peer_addr.ip()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Take a worker with the `capability` from the pool, and send a request with the `method`, such as CONNECT, for the session.
+    /// A failure of the worker is retried on another worker, but a failure of the destination is returned at once.
+    async fn open(
+        &self,
+        session: &Session,
+        method: &str,
+        capability: Option<&'static str>,
+    ) -> ConnectingResult {
+        for _ in 0..MAX_WORKER_ATTEMPTS {
+            let stream = match self
+                .take_and_send_request(session, method, capability)
+                .await
+            {
+                Ok(it) => it,
+                Err(reason) => return ConnectingResult::BadGateway(reason),
+            };
+
+            session.set_it_connecting();
+
+            // Receive an HTTP response, an error means that the worker is broken.
+            let res = match HttpResponse::read_from(&stream).await {
+                Ok(it) => it,
+                Err(e) => {
+                    println!(
+                        "[{}] The worker failed for {}: {}",
+                        stream.peer_addr, session.host, e
+                    );
+                    continue;
+                }
+            };
+
+            // Got a non-200 status, this means proxy server cannot process this request.
+            // Older workers send no reason, so the failure is regarded as the destination's.
+            if res.get_status() != 200 {
+                let failure = Failure::from_headers(&res.headers);
+                if failure.is_worker_fault() {
+                    continue;
+                }
+                let payload = res
+                    .get_payload()
+                    .as_ref()
+                    .map_or_else(Vec::default, |v| v.to_vec());
+                let text = String::from_utf8(payload).unwrap_or_else(|e| e.to_string());
+                return ConnectingResult::ServiceUnavailable(failure, text);
             }
+
+            session.set_it_established();
+
+            // Success, return the NeckStream object (transfer ownership).
+            return ConnectingResult::Ok(stream);
         }
+        ConnectingResult::BadGateway(format!("Workers have failed {} times", MAX_WORKER_ATTEMPTS))
     }
 
-    async fn take_and_send_connect(&self, uri: &str) -> Option<Arc<NeckStream>> {
+    async fn take_and_send_request(
+        &self,
+        session: &Session,
+        method: &str,
+        capability: Option<&'static str>,
+    ) -> Result<Arc<NeckStream>, String> {
         // This is a retry loop, where certain operations can be retried, with a maximum of 5 retry attempts.
         for _ in 1..=5 {
             // Take a item from pool without retry.
             // If the pool is empty, retrying is pointless.
-            let stream = match self.take().await {
-                Some(k) => k,
-                None => break,
-            };
+            let stream = self.take(session, capability).await?;
 
-            // Send CONNECT reqeust.
-            if let Err(_) = HttpRequest::new("CONNECT", &uri, "HTTP/1.1")
+            // Send CONNECT reqeust (or another request with the `method`).
+            // The worker is no longer idle, and its client may start another one by the demand.
+            let demand = self.compute_demand(&stream, false).await;
+            if let Err(_) = HttpRequest::new(method, &session.host, "HTTP/1.1")
                 .add_header_kv("Host", &stream.peer_addr.to_string())
+                .add_header_kv(DEMAND_HEADER, &demand.to_string())
                 .write_to_stream(&stream)
                 .await
             {
                 continue;
             };
 
-            return Some(stream);
+            return Ok(stream);
+        }
+        Err(String::from("Connections are not available"))
+    }
+
+    /// Open a logical stream on the multiplexed worker of the `group` with the `capability` and the fewest open streams.
+    async fn open_mux(&self, group: Option<&str>, capability: Option<&str>) -> Option<NeckStream> {
+        let muxes = self.muxes.lock().await.clone();
+        let mut best: Option<(usize, Arc<Mux>)> = None;
+        for mux in muxes {
+            if mux.get_link().group.as_deref() != group || !is_capable(mux.get_link(), capability) {
+                continue;
+            }
+            let len = mux.len().await;
+            if best.as_ref().is_none_or(|(min, _)| len < *min) {
+                best = Some((len, mux));
+            }
         }
-        None
+        best?.1.open().await.ok()
     }
 
-    /// Try to insert a `stream` to the pool and send a notification if fuccessful.
+    /// Keep a multiplexed worker in the pool, until its link is broken or it misses a heartbeat.
+    async fn join_mux(&self, stream: NeckStream) {
+        let mux = Mux::server(stream);
+        let waiting = {
+            let _map = self.storage.lock().await;
+            let mut muxes = self.muxes.lock().await;
+            if muxes.len() >= self.size {
+                return;
+            }
+            muxes.push(mux.clone());
+
+            // The multiplexed worker can be shared, so take all waiting sessions it is capable for.
+            let mut waiters = self.waiters.lock().unwrap();
+            let (waiting, others): (VecDeque<Waiter>, VecDeque<Waiter>) =
+                waiters.drain(..).partition(|w| w.accepts(mux.get_link()));
+            *waiters = others;
+            update_positions(&mut waiters);
+            waiting
+        };
+
+        // Open a stream for each waiting session in order, a session whose stream cannot be opened will retry.
+        for waiter in waiting {
+            waiter.session.set_queue_position(0);
+            if let Ok(stream) = mux.open().await {
+                let _ = waiter.sender.send(Arc::new(stream));
+            }
+        }
+
+        let heartbeat = async {
+            match self.get_heartbeat(mux.get_link()) {
+                Some(heartbeat) => loop {
+                    sleep(heartbeat.interval).await;
+                    if let Err(e) = mux.ping(heartbeat.timeout).await {
+                        println!("[{}] Evicted a worker: {}", mux.get_link().peer_addr, e);
+                        return;
+                    }
+                },
+                None => pending().await,
+            }
+        };
+
+        select! {
+            _ = mux.run() => (),
+            _ = heartbeat => mux.close().await,
+        }
+
+        self.muxes.lock().await.retain(|m| !Arc::ptr_eq(m, &mux));
+    }
+
+    /// Try to hand a `stream` over to a waiting session, or insert it to the pool.
     /// If the pool is already full, the `stream` will be dropped.
-    async fn try_insert(&self, stream: Arc<NeckStream>) -> bool {
+    /// Return true if the `stream` is idle in the pool.
+    async fn try_insert(&self, stream: Arc<NeckStream>, since: Instant) -> bool {
         let mut s = self.storage.lock().await;
 
+        // The longest waiting session takes the `stream` directly, regardless of the selection policy.
+        let handed = stream.clone();
+        let remaining = Self::hand_over(&mut self.waiters.lock().unwrap(), stream);
+        let stream = match remaining {
+            Some(it) => it,
+            None => {
+                self.record_take(&handed).await;
+                return false;
+            }
+        };
+
         // Check the pool size, if it is already full, return false directly.
         // NOTE: The ownership of `stream` will not be returned here, it will be dropped.
         if s.len() >= self.size {
@@ -88,88 +549,184 @@ impl PoolModeManager {
 
         // Insert the `stream` into the pool (ownership has been moved).
         let addr = stream.peer_addr;
-        s.insert(addr, stream);
-
-        // When a stream is inserted to the pool, notify a waiting routine to attempt to retrieval.
-        self.conn_joined.notify_one();
+        s.insert(addr, Idle { stream, since });
 
         true
     }
 
-    /// If a connection is closed by peer, it will be remove fastly, to prevent it from being used in other routines.
-    async fn wait_close_or_use(&self, addr: SocketAddr) {
-        // Get the reader pointer.
-        let stream = match self.storage.lock().await.get(&addr) {
-            Some(s) => s.clone(),
-            None => return,
+    /// Remove the `stream` from the pool, only if it is still in the pool.
+    async fn remove(&self, stream: &Arc<NeckStream>) -> Option<Idle> {
+        let mut map = self.storage.lock().await;
+        match map.get(&stream.peer_addr) {
+            Some(i) if Arc::ptr_eq(&i.stream, stream) => map.remove(&stream.peer_addr),
+            _ => None,
+        }
+    }
+
+    /// Get the heartbeat settings for a worker, None if the heartbeat is disabled or not negotiated with the worker.
+    fn get_heartbeat(&self, stream: &NeckStream) -> Option<&Heartbeat> {
+        self.heartbeat
+            .as_ref()
+            .filter(|_| stream.capabilities.has(HEARTBEAT))
+    }
+
+    /// Send a PING request to the worker, and wait for its reply.
+    /// NOTE: Any reply is regarded as alive, even a 405 from an older worker that does not know the PING method.
+    async fn ping(&self, stream: &NeckStream, deadline: Duration) -> NeckResult<()> {
+        let demand = self.compute_demand(stream, true).await;
+        let reply = async {
+            HttpRequest::new("PING", "*", "HTTP/1.1")
+                .add_header_kv("Host", &stream.peer_addr.to_string())
+                .add_header_kv(DEMAND_HEADER, &demand.to_string())
+                .write_to_stream(stream)
+                .await?;
+            HttpResponse::read_from(stream).await
         };
+        match timeout(deadline, reply).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => NeckError::wrap("No reply to PING"),
+        }
+    }
 
-        // If the `reader` receives anything, remove it from the pool and stop the health check loop.
-        // There are two cases for this:
-        // 1. The `stream`, which is still in the pool, but closed by peer.
-        // 2. The `stream` has been taken out by another routine, and has been used.
-        // If this connection has closed by peer.
-        let _ = stream.quick_check_eof().await;
+    /// Watch an idle `stream` in the pool, until it is taken by another routine, closed by peer,
+    /// or evicted for missing a heartbeat.
+    async fn monitor(&self, stream: Arc<NeckStream>) {
+        loop {
+            // Wait until the `reader` receives anything, or until the next heartbeat is due.
+            // There are two cases for receiving anything:
+            // 1. The `stream`, which is still in the pool, but closed by peer.
+            // 2. The `stream` has been taken out by another routine, and has been used.
+            let received = match self.get_heartbeat(&stream) {
+                Some(heartbeat) => select! {
+                    _ = stream.quick_check_eof() => true,
+                    _ = sleep(heartbeat.interval) => false,
+                },
+                None => {
+                    let _ = stream.quick_check_eof().await;
+                    true
+                }
+            };
+
+            // Take the `stream` out of the pool, so that it cannot be used by other routines during the heartbeat.
+            // If it is not in the pool, it has been taken out by another routine, stop watching it.
+            let Idle { stream, since } = match self.remove(&stream).await {
+                Some(it) => it,
+                None => return,
+            };
 
-        // It probably has already been removed by another routine, but do not care about that.
-        self.storage.lock().await.remove(&addr);
+            // The idle `stream` is closed by peer (or has sent something unexpected), drop it.
+            if received {
+                return;
+            }
+
+            if let Some(heartbeat) = self.get_heartbeat(&stream) {
+                if let Err(e) = self.ping(&stream, heartbeat.timeout).await {
+                    println!("[{}] Evicted a worker: {}", stream.peer_addr, e);
+                    return;
+                }
+            }
+
+            // Put it back to the pool, which may have been filled by others during the heartbeat.
+            if !self.try_insert(stream.clone(), since).await {
+                return;
+            }
+        }
     }
 }
 
 impl ConnectionManager for PoolModeManager {
     /// Get the current size of the pool.
     fn len(&self) -> PBF<usize> {
-        Box::pin(async { self.storage.lock().await.len() })
+        Box::pin(async { self.storage.lock().await.len() + self.muxes.lock().await.len() })
+    }
+
+    /// List all workers in the pool.
+    fn workers(&self) -> PBF<'_, Vec<WorkerInfo>> {
+        Box::pin(async {
+            let mut workers: Vec<WorkerInfo> = self
+                .storage
+                .lock()
+                .await
+                .values()
+                .map(|i| WorkerInfo::new(&i.stream, None))
+                .collect();
+
+            let muxes = self.muxes.lock().await.clone();
+            for mux in muxes {
+                workers.push(WorkerInfo::new(mux.get_link(), Some(mux.len().await)));
+            }
+
+            workers
+        })
+    }
+
+    fn demand<'a>(&'a self, stream: &'a NeckStream) -> PBF<'a, Option<i64>> {
+        Box::pin(async move {
+            // A multiplexed worker carries many sessions, so it is not counted by the demand.
+            if stream.capabilities.has(MUX) {
+                return None;
+            }
+            // The joining worker is not idle until its client receives the response.
+            Some(self.compute_demand(stream, false).await)
+        })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let mut capabilities = Capabilities::default();
+        if self.heartbeat.is_some() {
+            capabilities.add(HEARTBEAT);
+        }
+        capabilities
+            .add(UDP_RELAY)
+            .add(BIND_RELAY)
+            .add(MUX)
+            .add(FRAMED);
+        capabilities
     }
 
     /// Join the pool.
     fn join(&self, stream: NeckStream) -> PBF<()> {
+        if stream.capabilities.has(MUX) {
+            return Box::pin(self.join_mux(stream));
+        }
+        self.release(Arc::new(stream))
+    }
+
+    /// Put a worker back to the pool.
+    fn release(&self, stream: Arc<NeckStream>) -> PBF<'_, ()> {
         Box::pin(async {
-            // The NeckStream will be moved later, so we need to clone necessary properties before moving.
-            let addr = stream.peer_addr;
+            // The worker is no longer in use.
+            self.taken
+                .lock()
+                .await
+                .retain(|w| w.as_ptr() != Arc::as_ptr(&stream));
 
             // Try to join the pool, if it is failed not, return this function.
-            if !self.try_insert(Arc::new(stream)).await {
+            if !self.try_insert(stream.clone(), Instant::now()).await {
                 return;
             }
 
             // Otherwise, the stream has joined the pool.
 
-            self.wait_close_or_use(addr).await;
+            self.monitor(stream).await;
         })
     }
 
     /// Attempt to acquire a NeckStream from the pool and establish the HTTP proxy connection.
     fn connect<'a>(&'a self, session: &'a Session) -> PBF<'a, ConnectingResult> {
-        let ss = Arc::new(session);
-        Box::pin(async move {
-            let stream = match self.take_and_send_connect(&ss.host).await {
-                Some(it) => it,
-                None => return ConnectingResult::BadGateway(),
-            };
-
-            session.set_it_connecting();
-
-            // Receive an HTTP response.
-            let res = match HttpResponse::read_from(&stream).await {
-                Ok(it) => it,
-                Err(e) => return ConnectingResult::ServiceUnavailable(e.to_string()),
-            };
-
-            // Got a non-200 status, this means proxy server cannot process this request, retrying is pointless.
-            if res.get_status() != 200 {
-                let payload = res
-                    .get_payload()
-                    .as_ref()
-                    .map_or_else(Vec::default, |v| v.to_vec());
-                let text = String::from_utf8(payload).unwrap_or_else(|e| e.to_string());
-                return ConnectingResult::ServiceUnavailable(text);
-            }
+        Box::pin(self.open(session, "CONNECT", None))
+    }
 
-            session.set_it_established();
+    /// Attempt to acquire a NeckStream supporting the UDP relay from the pool and establish a UDP association.
+    /// NOTE: Older workers cannot relay UDP, they are never selected.
+    fn associate<'a>(&'a self, session: &'a Session) -> PBF<'a, ConnectingResult> {
+        Box::pin(self.open(session, "ASSOCIATE", Some(UDP_RELAY)))
+    }
 
-            // Success, return the NeckStream object (transfer ownership).
-            return ConnectingResult::Ok(stream);
-        })
+    /// Attempt to acquire a NeckStream supporting the BIND relay from the pool and listen for an incoming connection.
+    /// NOTE: Older workers cannot relay BIND, they are never selected.
+    fn bind<'a>(&'a self, session: &'a Session) -> PBF<'a, ConnectingResult> {
+        Box::pin(self.open(session, "BIND", Some(BIND_RELAY)))
     }
 }
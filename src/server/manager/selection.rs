@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+use clap::ValueEnum;
+use tokio::time::Instant;
+
+/// The policy to select an idle worker from the pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SelectionPolicy {
+    /// The worker that has been idle for the longest time.
+    Fifo,
+    /// The worker that has been idle for the shortest time, whose connection is the warmest.
+    Lifo,
+    /// Take turns across client hosts, and the longest idle worker of the host.
+    #[default]
+    RoundRobin,
+    /// The client host with the fewest open sessions, and the longest idle worker of the host.
+    LeastSessions,
+}
+
+/// An idle worker in the pool, which can be selected.
+pub struct Candidate {
+    pub addr: SocketAddr,
+    /// When the worker joined the pool, or was put back after its last tunnel.
+    pub idle_since: Instant,
+}
+
+/// Select idle workers with a policy, remembering the last client host for the round-robin.
+pub struct Selector {
+    policy: SelectionPolicy,
+    last_host: Option<IpAddr>,
+}
+
+impl Selector {
+    pub fn new(policy: SelectionPolicy) -> Self {
+        Self {
+            policy,
+            last_host: None,
+        }
+    }
+
+    /// Select a worker from the `candidates`, with the number of open sessions of each client host.
+    /// Ties are broken by the address, so that the selection is stable.
+    pub fn select(
+        &mut self,
+        candidates: &[Candidate],
+        sessions: &HashMap<IpAddr, usize>,
+    ) -> Option<SocketAddr> {
+        let oldest = |host: IpAddr| {
+            candidates
+                .iter()
+                .filter(|c| c.addr.ip() == host)
+                .min_by_key(|c| (c.idle_since, c.addr))
+                .map(|c| c.addr)
+        };
+
+        let selected = match self.policy {
+            SelectionPolicy::Fifo => candidates
+                .iter()
+                .min_by_key(|c| (c.idle_since, c.addr))
+                .map(|c| c.addr),
+            SelectionPolicy::Lifo => candidates
+                .iter()
+                .max_by_key(|c| (c.idle_since, c.addr))
+                .map(|c| c.addr),
+            SelectionPolicy::RoundRobin => {
+                // The next host after the last one in order, or wrap around to the first host.
+                let hosts = candidates.iter().map(|c| c.addr.ip());
+                let next = match self.last_host {
+                    Some(last) => hosts.clone().filter(|h| *h > last).min(),
+                    None => None,
+                };
+                next.or_else(|| hosts.min()).and_then(oldest)
+            }
+            SelectionPolicy::LeastSessions => candidates
+                .iter()
+                .map(|c| c.addr.ip())
+                .min_by_key(|h| (sessions.get(h).copied().unwrap_or(0), *h))
+                .and_then(oldest),
+        };
+
+        if let Some(addr) = selected {
+            self.last_host = Some(addr.ip());
+        }
+        selected
+    }
+}
@@ -1,7 +1,14 @@
+mod credentials;
 mod handlers;
 mod manager;
 mod neck_server;
+mod options;
 mod session_manager;
 mod static_manager;
+mod tenant;
+mod tls;
+
+mod tests;
 
 pub use neck_server::*;
+pub use options::*;
@@ -1,13 +1,24 @@
-use std::{process::exit, sync::Arc};
+use std::{
+    process::exit,
+    sync::{atomic::AtomicUsize, Arc},
+    time::Duration,
+};
 
 use tokio::net::TcpListener;
 
-use crate::utils::{enable_keepalive, BoxedError, PBF};
+use crate::{
+    rules::{Routes, Rules},
+    utils::{enable_keepalive, BoxedError, PBF},
+};
 
 use super::{
+    credentials::Credentials,
     handlers::request_handler,
-    manager::{ConnectionManager, DirectModeManager, PoolModeManager},
+    manager::{ConnectionManager, DirectModeManager, Heartbeat, PoolModeManager, WaitQueue},
     session_manager::SessionManager,
+    tenant::{load_tenant_configs, Tenant, TenantConfig, Tenants},
+    tls::ServerTls,
+    ServerOptions,
 };
 
 fn fix_addr(addr: Option<String>) -> String {
@@ -19,15 +30,134 @@ fn fix_addr(addr: Option<String>) -> String {
     )
 }
 
-fn create_connection_manager(direct: bool, max_workers: Option<u32>) -> Box<dyn ConnectionManager> {
-    if direct {
+fn create_heartbeat(options: &ServerOptions) -> Option<Heartbeat> {
+    // The heartbeat interval defaults 30 seconds, and the zero value disables the heartbeat.
+    let interval = options.heartbeat_interval.unwrap_or(30);
+    if interval == 0 {
+        return None;
+    }
+    Some(Heartbeat {
+        interval: Duration::from_secs(interval),
+        // The heartbeat timeout defaults 10 seconds.
+        timeout: Duration::from_secs(options.heartbeat_timeout.unwrap_or(10)),
+    })
+}
+
+fn create_wait_queue(options: &ServerOptions) -> WaitQueue {
+    WaitQueue {
+        // The wait timeout defaults 5 seconds.
+        timeout: Duration::from_secs(options.wait_timeout.unwrap_or(5)),
+        // The maximum number of waiting sessions defaults 1000.
+        max_len: options.max_waiting.unwrap_or(1000),
+    }
+}
+
+fn create_connection_manager(
+    options: &ServerOptions,
+    max_workers: Option<u32>,
+) -> Box<dyn ConnectionManager> {
+    if options.direct {
         Box::new(DirectModeManager {})
     } else {
         // The maximum allowed number of workers defaults 200.
-        Box::new(PoolModeManager::new(max_workers.unwrap_or(200) as usize))
+        Box::new(PoolModeManager::new(
+            max_workers.unwrap_or(200) as usize,
+            create_heartbeat(options),
+            options.selection.unwrap_or_default(),
+            create_wait_queue(options),
+        ))
     }
 }
 
+fn load_credentials(entries: &[String], file: &Option<String>) -> Option<Credentials> {
+    match Credentials::load(entries, file) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+fn load_rules(entries: &[String], file: &Option<String>) -> Rules {
+    match Rules::load(entries, file) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+fn load_routes(entries: &[String], file: &Option<String>) -> Routes {
+    match Routes::load(entries, file) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+fn load_tls(options: &ServerOptions) -> Option<ServerTls> {
+    let (cert, key) = options.tls_cert.as_ref().zip(options.tls_key.as_ref())?;
+    match ServerTls::new(cert, key, &options.tls_client_ca) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+fn load_tenant(options: &ServerOptions, path: String, config: TenantConfig) -> Tenant {
+    Tenant {
+        manager: create_connection_manager(options, config.max_workers),
+        worker_credentials: load_credentials(&config.worker_credentials, &None),
+        users: load_credentials(&config.users, &None),
+        allow_anonymous: config.allow_anonymous,
+        rules: load_rules(&config.rules, &None),
+        routes: load_routes(&config.routes, &None),
+        listen: config.listen.map(|v| fix_addr(Some(v))),
+        path: Some(path),
+    }
+}
+
+fn load_tenants(options: &ServerOptions) -> Tenants {
+    // The default tenant is configured by the command-line options.
+    let default = Tenant {
+        path: None,
+        manager: create_connection_manager(options, options.max_workers),
+        worker_credentials: load_credentials(
+            &options.worker_credentials,
+            &options.worker_credentials_file,
+        ),
+        users: load_credentials(&options.users, &options.users_file),
+        allow_anonymous: options.allow_anonymous,
+        rules: load_rules(&options.rules, &options.rules_file),
+        routes: load_routes(&options.routes, &options.routes_file),
+        listen: None,
+    };
+
+    let configs = match &options.tenants_file {
+        Some(path) => match load_tenant_configs(path) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        },
+        None => Default::default(),
+    };
+
+    let named = configs
+        .into_iter()
+        .map(|(path, config)| load_tenant(options, path, config))
+        .collect();
+
+    Tenants::new(default, named)
+}
+
 fn error_handler(e: BoxedError) {
     #[cfg(debug_assertions)]
     println!("{:#?}", e);
@@ -35,38 +165,49 @@ fn error_handler(e: BoxedError) {
 
 pub struct NeckServer {
     pub addr: String,
-    pub manager: Box<dyn ConnectionManager>,
     pub session_manager: SessionManager,
+
+    /// The isolated pool namespaces, each with its own workers, credentials and access rules.
+    pub tenants: Tenants,
+
+    /// The TLS acceptor, None if the TLS is disabled.
+    pub tls: Option<ServerTls>,
+
+    /// Accept both plain and TLS connections if the TLS is enabled.
+    pub allow_plain: bool,
+
+    /// The number of join attempts that were rejected.
+    pub failed_joins: AtomicUsize,
+
+    /// End a UDP association if no datagram is relayed for this duration.
+    pub udp_timeout: Duration,
 }
 
 impl NeckServer {
     /// Creates a new [`ServerContext`].
-    pub fn new(addr: Option<String>, direct: bool, max_workers: Option<u32>) -> Arc<Self> {
+    pub fn new(options: ServerOptions) -> Arc<Self> {
         Arc::new(Self {
-            addr: fix_addr(addr),
-            manager: create_connection_manager(direct, max_workers),
+            addr: fix_addr(options.addr.clone()),
             session_manager: SessionManager::new(),
+            tenants: load_tenants(&options),
+            tls: load_tls(&options),
+            allow_plain: options.allow_plain,
+            failed_joins: AtomicUsize::new(0),
+            // The UDP idle timeout defaults 60 seconds.
+            udp_timeout: Duration::from_secs(options.udp_timeout.unwrap_or(60)),
         })
     }
 
-    pub async fn start(ns: Arc<NeckServer>) {
-        // Begin TCP listening on specified address.
-        let listener = match TcpListener::bind(&ns.addr).await {
-            Ok(v) => v,
-            Err(e) => {
-                eprint!("{}", e);
-                exit(1);
-            }
-        };
-
+    /// Accept connections from a `listener`, which may be dedicated to a tenant.
+    async fn serve(ns: Arc<NeckServer>, listener: TcpListener, tenant: Option<Arc<Tenant>>) {
         loop {
             // Accept all requests and dispatch each of them using a new thread.
             match listener.accept().await {
                 Ok((stream, _)) => {
                     let ctx = ns.clone();
+                    let tenant = tenant.clone();
                     tokio::spawn(async move {
-                        // Wrap the raw TcpStream with a NeckStream.
-                        request_handler(enable_keepalive(stream).into(), ctx)
+                        request_handler(enable_keepalive(stream), ctx, tenant)
                             .await
                             .unwrap_or_else(error_handler);
                     });
@@ -77,6 +218,33 @@ impl NeckServer {
             };
         }
     }
+
+    pub async fn start(ns: Arc<NeckServer>) {
+        // Begin TCP listening on specified address.
+        let listener = match TcpListener::bind(&ns.addr).await {
+            Ok(v) => v,
+            Err(e) => {
+                eprint!("{}", e);
+                exit(1);
+            }
+        };
+
+        // Begin TCP listening on the dedicated addresses of tenants.
+        for tenant in ns.tenants.iter() {
+            if let Some(addr) = &tenant.listen {
+                let listener = match TcpListener::bind(addr).await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        eprintln!("Cannot listen on '{}': {}", addr, e);
+                        exit(1);
+                    }
+                };
+                tokio::spawn(Self::serve(ns.clone(), listener, Some(tenant.clone())));
+            }
+        }
+
+        Self::serve(ns, listener, None).await;
+    }
 }
 
 pub trait Starter {
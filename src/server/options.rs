@@ -0,0 +1,99 @@
+use clap::Args;
+
+use super::manager::SelectionPolicy;
+
+#[derive(Args, Debug)]
+pub struct ServerOptions {
+    /// Binding the listening address defaults "0.0.0.0:1081"
+    pub addr: Option<String>,
+
+    /// The maximum allowed number of workers defaults 200.
+    #[arg(long)]
+    pub max_workers: Option<u32>,
+
+    /// Send a PING to each idle worker after this many seconds defaults 30, 0 to disable the heartbeat.
+    #[arg(long, value_name = "SECONDS")]
+    pub heartbeat_interval: Option<u64>,
+
+    /// Evict a worker if it does not reply a PING within this many seconds defaults 10.
+    #[arg(long, value_name = "SECONDS")]
+    pub heartbeat_timeout: Option<u64>,
+
+    /// The policy to select an idle worker for each session: fifo, lifo, round-robin (across client hosts) or least-sessions (per client host) defaults round-robin.
+    #[arg(long, value_name = "POLICY", value_enum, hide_possible_values = true)]
+    pub selection: Option<SelectionPolicy>,
+
+    /// Fail a session if no worker is available within this many seconds defaults 5.
+    #[arg(long, value_name = "SECONDS")]
+    pub wait_timeout: Option<u64>,
+
+    /// The maximum number of sessions waiting for workers defaults 1000, further sessions fail immediately.
+    #[arg(long, value_name = "N")]
+    pub max_waiting: Option<usize>,
+
+    /// End a UDP association if no datagram is relayed within this many seconds defaults 60.
+    #[arg(long, value_name = "SECONDS")]
+    pub udp_timeout: Option<u64>,
+
+    /// Proxy directly from the server without creating a worker pool.
+    #[clap(long, action)]
+    pub direct: bool,
+
+    /// Require workers to join with a credential like "user:password" or "user:{SHA256}hex" (repeatable).
+    #[arg(long = "worker-credential", value_name = "CREDENTIAL")]
+    pub worker_credentials: Vec<String>,
+
+    /// Load worker credentials from a file, one credential per line.
+    #[arg(long, value_name = "FILE")]
+    pub worker_credentials_file: Option<String>,
+
+    /// Require proxy users to authenticate with a credential like "user:password" or "user:{SHA256}hex" (repeatable).
+    #[arg(long = "user", value_name = "CREDENTIAL")]
+    pub users: Vec<String>,
+
+    /// Load proxy user credentials from a file, one credential per line.
+    #[arg(long, value_name = "FILE")]
+    pub users_file: Option<String>,
+
+    /// Allow proxy users to connect without credentials even if user credentials are configured.
+    #[clap(long, action)]
+    pub allow_anonymous: bool,
+
+    /// Add an access rule like "allow *.example.com 443" or "deny 10.0.0.0/8" (repeatable, the first match wins).
+    #[arg(long = "rule", value_name = "RULE")]
+    pub rules: Vec<String>,
+
+    /// Load access rules from a file, one rule per line.
+    #[arg(long, value_name = "FILE")]
+    pub rules_file: Option<String>,
+
+    /// Add a route like "zone-b 10.2.0.0/16" or "zone-c *.c.internal 443" to send the matched destinations to a worker group
+    /// (repeatable, the first match wins, and the other destinations are sent to the workers without a group).
+    #[arg(long = "route", value_name = "ROUTE")]
+    pub routes: Vec<String>,
+
+    /// Load routes from a file, one route per line.
+    #[arg(long, value_name = "FILE")]
+    pub routes_file: Option<String>,
+
+    /// Load tenants from a JSON file keyed by join paths, each tenant is an isolated pool
+    /// with its own max_workers, worker_credentials, users, rules, routes and an optional listen address.
+    #[arg(long, value_name = "FILE")]
+    pub tenants_file: Option<String>,
+
+    /// Serve TLS with a PEM certificate chain file.
+    #[arg(long, value_name = "FILE", requires = "tls_key")]
+    pub tls_cert: Option<String>,
+
+    /// The PEM private key file (PKCS#8) for the TLS certificate.
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    pub tls_key: Option<String>,
+
+    /// Also accept plain connections on the TLS port, by peeking for a TLS ClientHello.
+    #[clap(long, action, requires = "tls_cert")]
+    pub allow_plain: bool,
+
+    /// Require workers to present a client certificate signed by a CA in this PEM file.
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    pub tls_client_ca: Option<String>,
+}
@@ -1,9 +1,9 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, VecDeque},
     net::SocketAddr,
     sync::{
         atomic::{AtomicU8, AtomicUsize, Ordering::SeqCst},
-        Arc, Weak,
+        Arc, Mutex as SyncMutex, Weak,
     },
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -17,6 +17,8 @@ use tokio::{
     },
 };
 
+use super::tenant::Tenant;
+
 enum Action {
     Insert(usize, Weak<RawSession>),
     Remove(usize),
@@ -24,11 +26,18 @@ enum Action {
 
 type Storage = Arc<Mutex<BTreeMap<usize, Weak<RawSession>>>>;
 
+/// The maximum number of recent denials to keep.
+const MAX_DENIALS: usize = 100;
+
 pub struct SessionManager {
     inc: AtomicUsize,
     storage: Storage,
     sender: Sender<Action>,
     notify: Arc<Notify>,
+    /// The total number of denied destinations.
+    denied: AtomicUsize,
+    /// The most recent denials, the oldest one is dropped once it is full.
+    denials: SyncMutex<VecDeque<Denial>>,
 }
 
 async fn consumer_deamon(storage: Storage, mut receiver: Receiver<Action>, notify: Arc<Notify>) {
@@ -57,9 +66,25 @@ pub struct RawSession {
     pub host: String,
     pub from: SocketAddr,
 
+    /// The authenticated username, None if the proxy authentication is disabled.
+    pub user: Option<String>,
+
+    /// The access rule that allowed this session, None if no rule is matched.
+    pub rule: Option<String>,
+
+    /// The join path of the tenant, None for the default tenant.
+    pub tenant: Option<String>,
+
+    /// The worker group routed by the destination, None for the workers without a group.
+    pub group: Option<String>,
+
     /// 0: Waiting, 1: Connecting, 2: Established.
     pub state: AtomicU8,
 
+    /// The position in the queue of sessions waiting for a worker of the same group, starting from 1.
+    /// 0 if the session is not in the queue.
+    pub queue_position: AtomicUsize,
+
     #[serde(skip_serializing)]
     sender: Sender<Action>,
 
@@ -67,6 +92,19 @@ pub struct RawSession {
     notify: Arc<Notify>,
 }
 
+/// A destination denied by the access rules, which is kept for auditing.
+#[derive(Debug, Serialize)]
+pub struct Denial {
+    pub timestamp: u128,
+    pub proto: &'static str,
+    pub host: String,
+    pub from: SocketAddr,
+    pub user: Option<String>,
+    /// The access rule that denied the destination.
+    pub rule: Option<String>,
+    pub tenant: Option<String>,
+}
+
 impl RawSession {
     pub fn set_it_connecting(&self) {
         self.state.store(1, SeqCst);
@@ -77,6 +115,12 @@ impl RawSession {
         self.state.store(2, SeqCst);
         self.notify.notify_waiters();
     }
+
+    pub fn set_queue_position(&self, position: usize) {
+        if self.queue_position.swap(position, SeqCst) != position {
+            self.notify.notify_waiters();
+        }
+    }
 }
 
 impl Drop for RawSession {
@@ -97,6 +141,8 @@ impl SessionManager {
             storage,
             sender,
             notify: notify.clone(),
+            denied: AtomicUsize::new(0),
+            denials: SyncMutex::new(VecDeque::new()),
         };
         spawn(consumer_deamon(mc.storage.clone(), receiver, notify));
         mc
@@ -115,15 +161,31 @@ impl SessionManager {
             .as_millis()
     }
 
-    pub fn create_session(&self, proto: &'static str, from: SocketAddr, host: String) -> Session {
+    pub fn create_session(
+        &self,
+        proto: &'static str,
+        from: SocketAddr,
+        host: String,
+        user: Option<String>,
+        rule: Option<String>,
+        tenant: &Tenant,
+    ) -> Session {
+        // Route the destination to a worker group of the tenant.
+        let group = tenant.routes.get_group(&host).map(String::from);
+
         // Create the session.
         let session = Arc::new(RawSession {
             id: self.create_id(),
             state: AtomicU8::new(0),
+            queue_position: AtomicUsize::new(0),
             timestamp: self.now(),
             proto,
             host,
             from,
+            user,
+            rule,
+            tenant: tenant.path.clone(),
+            group,
             sender: self.sender.clone(),
             notify: self.notify.clone(),
         });
@@ -149,6 +211,42 @@ impl SessionManager {
         serde_json::to_string(&ptr_list)
     }
 
+    /// Record a destination denied by the access rules.
+    pub fn record_denial(
+        &self,
+        proto: &'static str,
+        from: SocketAddr,
+        host: String,
+        user: Option<String>,
+        rule: Option<String>,
+        tenant: &Tenant,
+    ) {
+        self.denied.fetch_add(1, SeqCst);
+        let denial = Denial {
+            timestamp: self.now(),
+            proto,
+            host,
+            from,
+            user,
+            rule,
+            tenant: tenant.path.clone(),
+        };
+        let mut denials = self.denials.lock().unwrap();
+        if denials.len() >= MAX_DENIALS {
+            denials.pop_front();
+        }
+        denials.push_back(denial);
+    }
+
+    /// List the total number of denials and the most recent ones.
+    pub fn list_denials(&self) -> Result<String, serde_json::Error> {
+        let denials = self.denials.lock().unwrap();
+        serde_json::to_string(&serde_json::json!({
+            "total": self.denied.load(SeqCst),
+            "recent": &*denials,
+        }))
+    }
+
     pub fn watch(&self) -> tokio::sync::futures::Notified<'_> {
         self.notify.notified()
     }
@@ -0,0 +1,202 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::{
+    rules::{Routes, Rules},
+    utils::{read_file, NeckError, NeckResult},
+};
+
+use super::{credentials::Credentials, manager::ConnectionManager};
+
+/// The configuration of a tenant in the tenants file, which is a JSON object keyed by join paths, for example:
+///
+/// {
+///   "/team-a": {
+///     "max_workers": 50,
+///     "worker_credentials": ["worker:secret"],
+///     "users": ["alice:{SHA256}2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"],
+///     "rules": ["deny 10.0.0.0/8"],
+///     "listen": "0.0.0.0:1082"
+///   }
+/// }
+///
+/// The entries of credentials, rules and routes are the same as the command-line options.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantConfig {
+    pub max_workers: Option<u32>,
+    pub worker_credentials: Vec<String>,
+    pub users: Vec<String>,
+    pub allow_anonymous: bool,
+    pub rules: Vec<String>,
+    pub routes: Vec<String>,
+    /// A dedicated listening address, where all proxy users and workers belong to this tenant.
+    pub listen: Option<String>,
+}
+
+/// Check if a join path is valid for a tenant, the "/" is reserved for the default tenant.
+fn is_valid_path(path: &str) -> bool {
+    path.len() > 1
+        && path.starts_with('/')
+        && !path.contains(|c: char| c == '?' || c == '#' || c.is_whitespace() || c.is_control())
+}
+
+/// Load the tenant configurations from a JSON file, which are sorted by join paths.
+pub fn load_tenant_configs(path: &str) -> NeckResult<BTreeMap<String, TenantConfig>> {
+    let configs: BTreeMap<String, TenantConfig> = serde_json::from_slice(&read_file(path)?)
+        .map_err(|e| NeckError::new(format!("Bad tenants file '{}': {}", path, e)))?;
+    if let Some(bad) = configs.keys().find(|p| !is_valid_path(p)) {
+        return NeckError::wrap(format!("Bad tenant path '{}' in '{}'", bad, path));
+    }
+    Ok(configs)
+}
+
+/// An isolated pool namespace with its own workers, credentials and access rules.
+/// Workers are bound to a tenant by the join path, and proxy users by their credentials or by the listener.
+pub struct Tenant {
+    /// The join path, such as "/team-a", None for the default tenant.
+    pub path: Option<String>,
+
+    pub manager: Box<dyn ConnectionManager>,
+
+    /// The credentials required for workers to join, None if the authentication is disabled.
+    pub worker_credentials: Option<Credentials>,
+
+    /// The credentials required for proxy users, None if the authentication is disabled.
+    pub users: Option<Credentials>,
+
+    /// Allow proxy users without credentials, even if `users` is provided.
+    pub allow_anonymous: bool,
+
+    /// The access rules for destinations.
+    pub rules: Rules,
+
+    /// The routes from destinations to worker groups.
+    pub routes: Routes,
+
+    /// The dedicated listening address, None if the tenant is served on the main address only.
+    pub listen: Option<String>,
+}
+
+impl Tenant {
+    /// Check if proxy users are allowed to connect without credentials.
+    pub fn allows_anonymous(&self) -> bool {
+        self.users.is_none() || self.allow_anonymous
+    }
+}
+
+#[cfg(test)]
+impl Tenant {
+    /// Create a tenant in the direct mode without any authentication, rules or routes,
+    /// the fields under test are set with the struct update syntax.
+    pub fn for_test(path: Option<&str>) -> Self {
+        Self {
+            path: path.map(String::from),
+            manager: Box::new(super::manager::DirectModeManager {}),
+            worker_credentials: None,
+            users: None,
+            allow_anonymous: false,
+            rules: Rules::default(),
+            routes: Routes::default(),
+            listen: None,
+        }
+    }
+}
+
+/// The default tenant configured by command-line options, and the tenants configured by the tenants file.
+pub struct Tenants {
+    default: Arc<Tenant>,
+    named: Vec<Arc<Tenant>>,
+}
+
+impl Tenants {
+    pub fn new(default: Tenant, named: Vec<Tenant>) -> Self {
+        Self {
+            default: Arc::new(default),
+            named: named.into_iter().map(Arc::new).collect(),
+        }
+    }
+
+    /// Iterate all tenants, starting with the default one.
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Tenant>> {
+        Some(&self.default).into_iter().chain(self.named.iter())
+    }
+
+    /// Get the tenant of a join path, the query string is ignored.
+    /// NOTE: Without named tenants, all paths belong to the default tenant, which is the same as before tenants.
+    pub fn get_by_path(&self, path: &str) -> Option<&Arc<Tenant>> {
+        let path = path.split('?').next().unwrap_or_default();
+        if self.named.is_empty() || path == "/" {
+            return Some(&self.default);
+        }
+        self.named.iter().find(|t| t.path.as_deref() == Some(path))
+    }
+
+    /// Bind a proxy user to a tenant by its credential, and return the tenant with the username.
+    /// On a listener dedicated to a tenant (`bound`), only that tenant is considered.
+    /// Otherwise, the user is bound to the first tenant whose credentials match, starting with the default tenant,
+    /// and an anonymous user is bound to the default tenant.
+    /// Return None if the user is not allowed.
+    pub fn bind_user(
+        &self,
+        bound: Option<&Arc<Tenant>>,
+        credential: Option<UserCredential>,
+    ) -> Option<(Arc<Tenant>, Option<String>)> {
+        let candidates: Vec<&Arc<Tenant>> = match bound {
+            Some(tenant) => vec![tenant],
+            None => self.iter().collect(),
+        };
+
+        if let Some(credential) = &credential {
+            for tenant in &candidates {
+                if let Some(user) = tenant.users.as_ref().and_then(|u| credential.verify(u)) {
+                    return Some((Arc::clone(tenant), Some(user)));
+                }
+            }
+        }
+
+        // The credential is not matched, or not provided.
+        let owner = candidates[0];
+        let allowed = match credential {
+            // A wrong credential is ignored only if the authentication is disabled.
+            Some(_) => owner.users.is_none(),
+            // Anonymous users are allowed only if no credential is provided.
+            None => owner.allows_anonymous(),
+        };
+        allowed.then(|| (owner.clone(), None))
+    }
+
+    /// Check if any tenant on the listener requires proxy users to authenticate.
+    pub fn has_users(&self, bound: Option<&Arc<Tenant>>) -> bool {
+        match bound {
+            Some(tenant) => tenant.users.is_some(),
+            None => self.iter().any(|t| t.users.is_some()),
+        }
+    }
+
+    /// Check if anonymous proxy users are allowed on the listener.
+    pub fn allows_anonymous(&self, bound: Option<&Arc<Tenant>>) -> bool {
+        bound.unwrap_or(&self.default).allows_anonymous()
+    }
+}
+
+/// The credential provided by a proxy user.
+pub enum UserCredential<'a> {
+    /// The value of a `Proxy-Authorization` header.
+    Basic(&'a str),
+    /// The username and password of the SOCKS5 sub-negotiation.
+    Password(&'a str, &'a str),
+}
+
+impl UserCredential<'_> {
+    /// Verify the credential, and return the username if it is valid.
+    fn verify(&self, users: &Credentials) -> Option<String> {
+        match self {
+            UserCredential::Basic(value) => users.verify_basic(value),
+            UserCredential::Password(user, password) => {
+                users.verify(user, password).then(|| user.to_string())
+            }
+        }
+    }
+}
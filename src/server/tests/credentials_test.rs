@@ -0,0 +1,83 @@
+use base64::Engine;
+
+use super::super::credentials::Credentials;
+
+fn basic(raw: &str) -> String {
+    format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(raw)
+    )
+}
+
+#[test]
+fn test_plain() {
+    let mut c = Credentials::default();
+    c.add("alice:secret").unwrap();
+    c.add("bob:with:colon").unwrap();
+
+    assert!(c.verify("alice", "secret"));
+    assert!(!c.verify("alice", "Secret"));
+    assert!(!c.verify("carol", "secret"));
+
+    // Only the first colon separates the username and password.
+    assert!(c.verify("bob", "with:colon"));
+}
+
+#[test]
+fn test_hashed() {
+    let mut c = Credentials::default();
+    // echo -n secret | sha256sum
+    c.add("alice:{SHA256}2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b")
+        .unwrap();
+
+    assert!(c.verify("alice", "secret"));
+    assert!(!c.verify(
+        "alice",
+        "{SHA256}2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+    ));
+}
+
+#[test]
+fn test_bad_entries() {
+    let mut c = Credentials::default();
+    assert!(c.add("no-colon").is_err());
+    assert!(c.add("alice:{SHA256}1234").is_err());
+    assert!(c
+        .add(&format!("alice:{{SHA256}}{}", "zz".repeat(32)))
+        .is_err());
+}
+
+#[test]
+fn test_verify_basic() {
+    let mut c = Credentials::default();
+    c.add("alice:secret").unwrap();
+
+    assert_eq!(
+        c.verify_basic(&basic("alice:secret")),
+        Some("alice".to_string())
+    );
+    assert_eq!(c.verify_basic(&basic("alice:wrong")), None);
+    assert_eq!(c.verify_basic(&basic("alice")), None);
+    assert_eq!(c.verify_basic("Bearer abc"), None);
+    assert_eq!(c.verify_basic("Basic !!!"), None);
+}
+
+#[test]
+fn test_load() {
+    assert!(Credentials::load(&[], &None).unwrap().is_none());
+
+    let path = std::env::temp_dir().join(format!("neck-credentials-{}", std::process::id()));
+    std::fs::write(&path, "# workers\n\nalice:secret\n  bob:123  \n").unwrap();
+    let c = Credentials::load(
+        &["carol:456".to_string()],
+        &Some(path.to_string_lossy().to_string()),
+    )
+    .unwrap()
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(c.verify("alice", "secret"));
+    assert!(c.verify("bob", "123"));
+    assert!(c.verify("carol", "456"));
+    assert!(Credentials::load(&[], &Some("/nonexistent/neck".to_string())).is_err());
+}
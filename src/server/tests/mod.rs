@@ -0,0 +1,17 @@
+#[cfg(test)]
+mod credentials_test;
+
+#[cfg(test)]
+mod pool_test;
+
+#[cfg(test)]
+mod proxy_test;
+
+#[cfg(test)]
+mod selection_test;
+
+#[cfg(test)]
+mod tenant_test;
+
+#[cfg(test)]
+mod tls_test;
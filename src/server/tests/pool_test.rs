@@ -0,0 +1,565 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::time::{sleep, timeout};
+
+use crate::{
+    http::{HttpRequest, HttpResponse},
+    mux::MUX,
+    rules::Routes,
+    utils::{
+        tests::stream_pair, Failure, NeckStream, BIND_RELAY, FAILURE_HEADER, HEARTBEAT, UDP_RELAY,
+    },
+};
+
+use super::super::{
+    manager::{
+        split_demand, ConnectingResult, ConnectionManager, Heartbeat, PoolModeManager,
+        SelectionPolicy, WaitQueue,
+    },
+    session_manager::{Session, SessionManager},
+    tenant::Tenant,
+};
+
+fn wait_queue(timeout_ms: u64, max_len: usize) -> WaitQueue {
+    WaitQueue {
+        timeout: Duration::from_millis(timeout_ms),
+        max_len,
+    }
+}
+
+/// Create a pool with a short heartbeat, and join a worker, which has negotiated the heartbeat or not.
+/// NOTE: The heartbeat tests run with the paused clock, which advances whenever all tasks are idle.
+async fn join_worker(heartbeat: bool) -> (Arc<PoolModeManager>, NeckStream) {
+    let pool = Arc::new(PoolModeManager::new(
+        10,
+        Some(Heartbeat {
+            interval: Duration::from_millis(100),
+            timeout: Duration::from_millis(100),
+        }),
+        SelectionPolicy::default(),
+        wait_queue(5000, 1000),
+    ));
+    let (mut server, worker) = stream_pair().await;
+    if heartbeat {
+        server.capabilities.add(HEARTBEAT);
+    }
+    let p = pool.clone();
+    tokio::spawn(async move { p.join(server).await });
+    sleep(Duration::from_millis(20)).await;
+    assert_eq!(pool.len().await, 1);
+    (pool, worker)
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_heartbeat_reply() {
+    let (pool, worker) = join_worker(true).await;
+
+    // Answer all PING requests.
+    let pings = Arc::new(AtomicUsize::new(0));
+    let counter = pings.clone();
+    tokio::spawn(async move {
+        while let Ok(req) = HttpRequest::read_from(&worker).await {
+            assert_eq!(req.get_method(), "PING");
+            counter.fetch_add(1, SeqCst);
+            HttpResponse::new(200, "OK", req.get_version())
+                .write_to_stream(&worker)
+                .await
+                .unwrap();
+        }
+    });
+
+    sleep(Duration::from_millis(550)).await;
+    assert!(pings.load(SeqCst) >= 3);
+
+    // The worker is put back after each heartbeat, it is only out of the pool for a moment.
+    let mut joined = false;
+    for _ in 0..10 {
+        if pool.len().await == 1 {
+            joined = true;
+            break;
+        }
+        sleep(Duration::from_millis(5)).await;
+    }
+    assert!(joined);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_heartbeat_eviction() {
+    let (pool, worker) = join_worker(true).await;
+
+    // The worker never replies, so it is evicted after the interval and the timeout.
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(pool.len().await, 0);
+
+    // The worker receives a PING, and then the connection is closed.
+    let req = HttpRequest::read_from(&worker).await.unwrap();
+    assert_eq!(req.get_method(), "PING");
+    assert!(HttpRequest::read_from(&worker).await.is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_heartbeat_not_negotiated() {
+    let (pool, worker) = join_worker(false).await;
+
+    // The worker is never pinged, and stays in the pool.
+    let received = timeout(Duration::from_millis(300), HttpRequest::read_from(&worker)).await;
+    assert!(received.is_err());
+    assert_eq!(pool.len().await, 1);
+}
+
+/// Create a tenant with routes, which are used to create sessions.
+fn create_tenant(routes: &[&str]) -> Tenant {
+    let routes = routes.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    Tenant {
+        routes: Routes::load(&routes, &None).unwrap(),
+        ..Tenant::for_test(None)
+    }
+}
+
+fn create_session(sessions: &SessionManager, tenant: &Tenant, host: &str) -> Session {
+    let addr = "127.0.0.1:1".parse().unwrap();
+    sessions.create_session("http", addr, host.into(), None, None, tenant)
+}
+
+/// Join a worker of the `group` to the pool, the worker answers the first CONNECT request.
+async fn join_group_worker(pool: &Arc<PoolModeManager>, group: Option<&str>) {
+    join_answering_worker(pool, |s| s.group = group.map(String::from)).await;
+}
+
+/// Join a worker to the pool after preparing its server side,
+/// the worker answers the first CONNECT request (or ASSOCIATE, BIND request).
+async fn join_answering_worker(pool: &Arc<PoolModeManager>, prepare: impl FnOnce(&mut NeckStream)) {
+    let (mut server, worker) = stream_pair().await;
+    prepare(&mut server);
+    let p = pool.clone();
+    tokio::spawn(async move { p.join(server).await });
+    tokio::spawn(async move {
+        let req = HttpRequest::read_from(&worker).await.unwrap();
+        assert!(["CONNECT", "ASSOCIATE", "BIND"].contains(&req.get_method()));
+        HttpResponse::new(200, "Connection Established", req.get_version())
+            .write_to_stream(&worker)
+            .await
+            .unwrap();
+        // Keep the worker alive.
+        let _ = HttpRequest::read_from(&worker).await;
+    });
+}
+
+/// Join a worker which fails the first CONNECT request, by closing the connection, or answering a 503 with the `reason`.
+async fn join_failing_worker(pool: &Arc<PoolModeManager>, reason: Option<&'static str>) {
+    let (server, worker) = stream_pair().await;
+    let p = pool.clone();
+    tokio::spawn(async move { p.join(server).await });
+    tokio::spawn(async move {
+        let req = HttpRequest::read_from(&worker).await.unwrap();
+        if let Some(reason) = reason {
+            HttpResponse::new(503, "Service Unavailable", req.get_version())
+                .add_header_kv(FAILURE_HEADER, reason)
+                .write_to_stream(&worker)
+                .await
+                .unwrap();
+            let _ = HttpRequest::read_from(&worker).await;
+        }
+    });
+    sleep(Duration::from_millis(20)).await;
+}
+
+/// Connect through the pool, and get the group of the taken worker.
+async fn connect_group(pool: &PoolModeManager, session: Session) -> Option<String> {
+    match pool.connect(&session).await {
+        ConnectingResult::Ok(stream) => stream.group.clone(),
+        _ => panic!("Failed to connect"),
+    }
+}
+
+#[tokio::test]
+async fn test_group_routing() {
+    let pool = Arc::new(PoolModeManager::new(
+        10,
+        None,
+        SelectionPolicy::default(),
+        wait_queue(5000, 1000),
+    ));
+    let sessions = SessionManager::new();
+    let tenant = create_tenant(&["zone-b b.example.com", "zone-c c.example.com"]);
+    let session = |host: &str| create_session(&sessions, &tenant, host);
+
+    join_group_worker(&pool, None).await;
+    join_group_worker(&pool, Some("zone-b")).await;
+    sleep(Duration::from_millis(20)).await;
+    assert_eq!(pool.len().await, 2);
+
+    // The session routed to the group only takes the worker of the group.
+    let group = connect_group(&pool, session("b.example.com:80")).await;
+    assert_eq!(group.as_deref(), Some("zone-b"));
+
+    // The session waits until a worker of its group joins.
+    let (p, s) = (pool.clone(), session("c.example.com:80"));
+    let waiting = tokio::spawn(async move { connect_group(&p, s).await });
+    sleep(Duration::from_millis(100)).await;
+    assert!(!waiting.is_finished());
+    join_group_worker(&pool, Some("zone-c")).await;
+    assert_eq!(waiting.await.unwrap().as_deref(), Some("zone-c"));
+
+    // The session without a group takes the worker without a group.
+    assert_eq!(connect_group(&pool, session("example.com:80")).await, None);
+    assert_eq!(pool.len().await, 0);
+}
+
+#[tokio::test]
+async fn test_relay_capabilities() {
+    let pool = Arc::new(PoolModeManager::new(
+        10,
+        None,
+        SelectionPolicy::default(),
+        wait_queue(5000, 1000),
+    ));
+    let sessions = SessionManager::new();
+    let tenant = create_tenant(&[]);
+    let session = create_session(&sessions, &tenant, "0.0.0.0:0");
+
+    // An older worker cannot relay UDP, so the association waits for a capable worker.
+    join_answering_worker(&pool, |_| ()).await;
+    let (p, s) = (pool.clone(), session.clone());
+    let waiting = tokio::spawn(async move {
+        match p.associate(&s).await {
+            ConnectingResult::Ok(stream) => stream.capabilities.has(UDP_RELAY),
+            _ => false,
+        }
+    });
+    sleep(Duration::from_millis(100)).await;
+    assert!(!waiting.is_finished());
+    assert_eq!(positions(std::slice::from_ref(&session)), vec![1]);
+
+    join_answering_worker(&pool, |s| {
+        s.capabilities.add(UDP_RELAY);
+    })
+    .await;
+    assert!(waiting.await.unwrap());
+    assert_eq!(pool.len().await, 1);
+
+    // Neither can the older worker relay BIND.
+    let (p, s) = (pool.clone(), session.clone());
+    let waiting = tokio::spawn(async move {
+        match p.bind(&s).await {
+            ConnectingResult::Ok(stream) => stream.capabilities.has(BIND_RELAY),
+            _ => false,
+        }
+    });
+    sleep(Duration::from_millis(100)).await;
+    assert!(!waiting.is_finished());
+    join_answering_worker(&pool, |s| {
+        s.capabilities.add(BIND_RELAY);
+    })
+    .await;
+    assert!(waiting.await.unwrap());
+    assert_eq!(pool.len().await, 1);
+
+    // The older worker is still used for CONNECT requests.
+    let session = create_session(&sessions, &tenant, "example.com:80");
+    assert!(matches!(
+        pool.connect(&session).await,
+        ConnectingResult::Ok(_)
+    ));
+}
+
+/// Join 3 workers from the client host 10.0.0.1 and then 1 worker from 10.0.0.2, and take 4 workers in turn.
+/// The taken workers are held, so that their sessions are open. Return the last octets of the client hosts.
+async fn take_spread(policy: SelectionPolicy) -> Vec<u8> {
+    let pool = Arc::new(PoolModeManager::new(
+        10,
+        None,
+        policy,
+        wait_queue(5000, 1000),
+    ));
+    // The clock is paused, so each worker is idle since a distinct instant, in the order of joining.
+    for (i, host) in [1, 1, 1, 2].into_iter().enumerate() {
+        let addr = format!("10.0.0.{}:{}", host, 1000 + i).parse().unwrap();
+        join_answering_worker(&pool, |s| s.peer_addr = addr).await;
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(pool.len().await, 4);
+
+    let sessions = SessionManager::new();
+    let tenant = create_tenant(&[]);
+    let mut held = Vec::new();
+    let mut hosts = Vec::new();
+    for _ in 0..4 {
+        match pool
+            .connect(&create_session(&sessions, &tenant, "example.com:80"))
+            .await
+        {
+            ConnectingResult::Ok(stream) => {
+                match stream.peer_addr.ip() {
+                    std::net::IpAddr::V4(ip) => hosts.push(ip.octets()[3]),
+                    _ => unreachable!(),
+                }
+                held.push(stream);
+            }
+            _ => panic!("Failed to connect"),
+        }
+    }
+    hosts
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_selection_spread() {
+    // The oldest idle workers are taken first, so one client host takes most of the traffic.
+    assert_eq!(take_spread(SelectionPolicy::Fifo).await, vec![1, 1, 1, 2]);
+    assert_eq!(take_spread(SelectionPolicy::Lifo).await, vec![2, 1, 1, 1]);
+
+    // The client hosts take turns, until one of them has no idle workers.
+    assert_eq!(
+        take_spread(SelectionPolicy::RoundRobin).await,
+        vec![1, 2, 1, 1]
+    );
+    assert_eq!(
+        take_spread(SelectionPolicy::LeastSessions).await,
+        vec![1, 2, 1, 1]
+    );
+}
+
+/// Get the queue positions of the sessions.
+fn positions(sessions: &[Session]) -> Vec<usize> {
+    sessions
+        .iter()
+        .map(|s| s.queue_position.load(SeqCst))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_wait_queue_order() {
+    let pool = Arc::new(PoolModeManager::new(
+        10,
+        None,
+        SelectionPolicy::default(),
+        wait_queue(2000, 10),
+    ));
+    let sessions = SessionManager::new();
+    let tenant = create_tenant(&[]);
+
+    // Three sessions wait in the order of arrival.
+    let mut waiting = Vec::new();
+    let mut tasks = Vec::new();
+    for _ in 0..3 {
+        let session = create_session(&sessions, &tenant, "example.com:80");
+        let (p, s) = (pool.clone(), session.clone());
+        tasks.push(tokio::spawn(async move {
+            matches!(p.connect(&s).await, ConnectingResult::Ok(_))
+        }));
+        waiting.push(session);
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(positions(&waiting), vec![1, 2, 3]);
+
+    // A joining worker is handed over to the longest waiting session, and the others move forward.
+    join_group_worker(&pool, None).await;
+    sleep(Duration::from_millis(50)).await;
+    assert!(tasks[0].is_finished());
+    assert!(!tasks[1].is_finished());
+    assert_eq!(positions(&waiting), vec![0, 1, 2]);
+    assert_eq!(pool.len().await, 0);
+
+    join_group_worker(&pool, None).await;
+    sleep(Duration::from_millis(50)).await;
+    assert!(tasks[1].is_finished());
+    assert!(!tasks[2].is_finished());
+    assert_eq!(positions(&waiting), vec![0, 0, 1]);
+
+    for task in tasks.drain(..2) {
+        assert!(task.await.unwrap());
+    }
+}
+
+#[tokio::test]
+async fn test_wait_queue_cancel() {
+    let pool = Arc::new(PoolModeManager::new(
+        10,
+        None,
+        SelectionPolicy::default(),
+        wait_queue(2000, 2),
+    ));
+    let sessions = SessionManager::new();
+    let tenant = create_tenant(&[]);
+
+    let mut waiting = Vec::new();
+    let mut tasks = Vec::new();
+    for _ in 0..2 {
+        let session = create_session(&sessions, &tenant, "example.com:80");
+        let (p, s) = (pool.clone(), session.clone());
+        tasks.push(tokio::spawn(async move {
+            matches!(p.connect(&s).await, ConnectingResult::Ok(_))
+        }));
+        waiting.push(session);
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(positions(&waiting), vec![1, 2]);
+
+    // The first session goes away, it leaves the queue at once, without waiting for anything else to prune it.
+    tasks.remove(0).abort();
+    sleep(Duration::from_millis(20)).await;
+    assert_eq!(positions(&waiting), vec![0, 1]);
+
+    // So the queue has room for another session, and a joining worker goes to the remaining one.
+    let session = create_session(&sessions, &tenant, "example.com:80");
+    let (p, s) = (pool.clone(), session.clone());
+    tasks.push(tokio::spawn(async move {
+        matches!(p.connect(&s).await, ConnectingResult::Ok(_))
+    }));
+    sleep(Duration::from_millis(20)).await;
+    waiting.push(session);
+    assert_eq!(positions(&waiting), vec![0, 1, 2]);
+
+    join_group_worker(&pool, None).await;
+    assert!(tasks.remove(0).await.unwrap());
+    assert_eq!(positions(&waiting), vec![0, 0, 1]);
+}
+
+#[tokio::test]
+async fn test_wait_queue_limit() {
+    let pool = Arc::new(PoolModeManager::new(
+        10,
+        None,
+        SelectionPolicy::default(),
+        wait_queue(200, 1),
+    ));
+    let sessions = SessionManager::new();
+    let tenant = create_tenant(&[]);
+
+    let first = create_session(&sessions, &tenant, "example.com:80");
+    let (p, s) = (pool.clone(), first.clone());
+    let task = tokio::spawn(async move {
+        match p.connect(&s).await {
+            ConnectingResult::BadGateway(reason) => reason,
+            _ => panic!("Unexpected result"),
+        }
+    });
+    sleep(Duration::from_millis(20)).await;
+    assert_eq!(first.queue_position.load(SeqCst), 1);
+
+    // The queue is full, so the second session fails immediately.
+    let second = create_session(&sessions, &tenant, "example.com:80");
+    let started = std::time::Instant::now();
+    match pool.connect(&second).await {
+        ConnectingResult::BadGateway(reason) => assert!(reason.contains("Too many sessions")),
+        _ => panic!("Unexpected result"),
+    }
+    assert!(started.elapsed() < Duration::from_millis(100));
+
+    // The first session leaves the queue after the timeout.
+    assert_eq!(task.await.unwrap(), "Connections are not available");
+    assert_eq!(first.queue_position.load(SeqCst), 0);
+}
+
+#[test]
+fn test_split_demand() {
+    assert_eq!(split_demand(5, 2, 1), 3);
+    // A shortfall is rounded up, and a surplus is rounded down.
+    assert_eq!(split_demand(5, 0, 2), 3);
+    assert_eq!(split_demand(0, 3, 2), -1);
+    assert_eq!(split_demand(2, 2, 3), 0);
+    assert_eq!(split_demand(1, 0, 0), 1);
+}
+
+/// Get the demand for a joining worker from the client host.
+async fn demand_from(pool: &PoolModeManager, host: &str) -> Option<i64> {
+    let (mut server, _worker) = stream_pair().await;
+    server.peer_addr = format!("{}:1", host).parse().unwrap();
+    pool.demand(&server).await
+}
+
+#[tokio::test]
+async fn test_demand() {
+    let pool = Arc::new(PoolModeManager::new(
+        10,
+        None,
+        SelectionPolicy::default(),
+        wait_queue(2000, 10),
+    ));
+    let sessions = SessionManager::new();
+    let tenant = create_tenant(&[]);
+
+    // Three sessions are waiting, so three more idle workers are wanted, which are split among client hosts.
+    let mut tasks = Vec::new();
+    for _ in 0..3 {
+        let (p, s) = (
+            pool.clone(),
+            create_session(&sessions, &tenant, "example.com:80"),
+        );
+        tasks.push(tokio::spawn(async move { p.connect(&s).await }));
+    }
+    sleep(Duration::from_millis(20)).await;
+    assert_eq!(demand_from(&pool, "10.0.0.1").await, Some(3));
+
+    // The waiting sessions take the joining workers, and the sessions taken recently want one more.
+    for i in 0..3 {
+        let addr = format!("10.0.0.1:{}", 1000 + i).parse().unwrap();
+        join_answering_worker(&pool, |s| s.peer_addr = addr).await;
+    }
+    for task in tasks {
+        assert!(matches!(task.await.unwrap(), ConnectingResult::Ok(_)));
+    }
+    assert_eq!(demand_from(&pool, "10.0.0.1").await, Some(1));
+
+    // Idle workers are shared by their client hosts.
+    for i in 0..3 {
+        let addr = format!("10.0.0.2:{}", 1000 + i).parse().unwrap();
+        join_answering_worker(&pool, |s| s.peer_addr = addr).await;
+    }
+    sleep(Duration::from_millis(20)).await;
+    assert_eq!(demand_from(&pool, "10.0.0.2").await, Some(-2));
+    assert_eq!(demand_from(&pool, "10.0.0.3").await, Some(-1));
+
+    // The multiplexed workers are not counted.
+    let (mut server, _worker) = stream_pair().await;
+    server.capabilities.add(MUX);
+    assert_eq!(pool.demand(&server).await, None);
+}
+
+#[tokio::test]
+async fn test_retry_on_worker_failure() {
+    let pool = Arc::new(PoolModeManager::new(
+        10,
+        None,
+        SelectionPolicy::Fifo,
+        wait_queue(200, 1000),
+    ));
+    let sessions = SessionManager::new();
+    let tenant = create_tenant(&[]);
+
+    // The broken worker is taken first, and the session is retried on the next worker.
+    join_failing_worker(&pool, None).await;
+    join_group_worker(&pool, None).await;
+    sleep(Duration::from_millis(20)).await;
+    let session = create_session(&sessions, &tenant, "example.com:80");
+    assert!(matches!(
+        pool.connect(&session).await,
+        ConnectingResult::Ok(_)
+    ));
+
+    // The failure of the destination is returned without trying another worker.
+    join_failing_worker(&pool, Some("refused")).await;
+    join_group_worker(&pool, None).await;
+    sleep(Duration::from_millis(20)).await;
+    match pool.connect(&session).await {
+        ConnectingResult::ServiceUnavailable(failure, _) => assert_eq!(failure, Failure::Refused),
+        _ => panic!("The failure is not returned"),
+    }
+    assert_eq!(pool.len().await, 1);
+
+    // Too many broken workers.
+    pool.connect(&session).await;
+    for _ in 0..3 {
+        join_failing_worker(&pool, None).await;
+    }
+    match pool.connect(&session).await {
+        ConnectingResult::BadGateway(reason) => assert!(reason.contains("failed 3 times")),
+        _ => panic!("The workers are not given up"),
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    sync::{atomic::AtomicUsize, Arc},
+    time::Duration,
+};
+
+use base64::Engine;
+use tokio::{
+    io::{self, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    http::{HttpCommon, HttpRequest, HttpResponse},
+    utils::{tests::stream_pair, NeckStream},
+};
+
+use super::super::{
+    credentials::Credentials,
+    handlers::request_handler,
+    manager::{PoolModeManager, SelectionPolicy, WaitQueue},
+    session_manager::SessionManager,
+    tenant::{Tenant, Tenants},
+    NeckServer,
+};
+
+/// Create a tenant in the pool mode, with a single user.
+fn create_tenant(path: Option<&str>, user: &str) -> Tenant {
+    let wait_queue = WaitQueue {
+        timeout: Duration::from_millis(500),
+        max_len: 10,
+    };
+    Tenant {
+        manager: Box::new(PoolModeManager::new(
+            10,
+            None,
+            SelectionPolicy::default(),
+            wait_queue,
+        )),
+        users: Credentials::load(&[user.to_string()], &None).unwrap(),
+        ..Tenant::for_test(path)
+    }
+}
+
+fn create_server(tenants: Tenants) -> Arc<NeckServer> {
+    Arc::new(NeckServer {
+        addr: String::from("127.0.0.1:0"),
+        session_manager: SessionManager::new(),
+        tenants,
+        tls: None,
+        allow_plain: false,
+        failed_joins: AtomicUsize::new(0),
+        udp_timeout: Duration::from_secs(60),
+    })
+}
+
+/// Join a worker to the `tenant`, which is the origin server of the host it is connected to.
+/// Each response tells the worker and the request, and the path "/switch" switches to an echo protocol.
+async fn join_origin(tenant: &Arc<Tenant>, name: &'static str) {
+    let (server, worker) = stream_pair().await;
+    let t = tenant.clone();
+    tokio::spawn(async move { t.manager.join(server).await });
+    tokio::spawn(async move {
+        let req = HttpRequest::read_from(&worker).await.unwrap();
+        let host = req.get_uri().to_string();
+        HttpResponse::new(200, "Connection Established", req.get_version())
+            .write_to_stream(&worker)
+            .await
+            .unwrap();
+        while let Ok(req) = HttpRequest::read_from(&worker).await {
+            if req.get_uri() == "/switch" {
+                HttpResponse::new(101, "Switching Protocols", req.get_version())
+                    .add_header_kv("Connection", "Upgrade")
+                    .add_header_kv("Upgrade", "echo")
+                    .write_to_stream(&worker)
+                    .await
+                    .unwrap();
+                let (mut r, mut w) = tokio::join!(worker.reader.lock(), worker.writer.lock());
+                let _ = io::copy(&mut *r, &mut *w).await;
+                return;
+            }
+            let body = format!("{} {}{}", name, host, req.get_uri());
+            HttpResponse::new(200, "OK", req.get_version())
+                .add_payload(body.as_bytes())
+                .write_to_stream(&worker)
+                .await
+                .unwrap();
+        }
+    });
+}
+
+/// Connect to the proxy, which handles the connection like a listener does.
+async fn connect_proxy(ctx: &Arc<NeckServer>) -> NeckStream {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (stream, _) = listener.accept().await.unwrap();
+    let ctx = ctx.clone();
+    tokio::spawn(async move { request_handler(stream, ctx, None).await });
+    client.into()
+}
+
+/// Send a GET request through the proxy as the `user`, and get the status with the body.
+async fn get(client: &NeckStream, uri: &str, user: &str, upgrade: bool) -> (u16, String) {
+    let credential = base64::engine::general_purpose::STANDARD.encode(user);
+    let mut req = HttpRequest::new("GET", uri, "HTTP/1.1");
+    req.add_header_kv("Proxy-Authorization", &format!("Basic {}", credential));
+    if upgrade {
+        req.add_header_kv("Connection", "Upgrade")
+            .add_header_kv("Upgrade", "echo");
+    }
+    req.write_to_stream(client).await.unwrap();
+    let res = HttpResponse::read_from(client).await.unwrap();
+    let body = res.get_payload().clone().unwrap_or_default();
+    (res.get_status(), String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_http_proxy_loop() {
+    let ctx = create_server(Tenants::new(
+        create_tenant(None, "alice:secret"),
+        vec![create_tenant(Some("/team-b"), "bob:secret")],
+    ));
+    let tenants: Vec<Arc<Tenant>> = ctx.tenants.iter().cloned().collect();
+    join_origin(&tenants[0], "a1").await;
+    join_origin(&tenants[1], "b1").await;
+    join_origin(&tenants[1], "b2").await;
+
+    let client = connect_proxy(&ctx).await;
+    let alice = "alice:secret";
+    let bob = "bob:secret";
+
+    // The upstream is reused by the following request to the same host.
+    let (status, body) = get(&client, "http://a.example.com/1", alice, false).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, "a1 a.example.com:80/1");
+    let (_, body) = get(&client, "http://a.example.com/2", alice, false).await;
+    assert_eq!(body, "a1 a.example.com:80/2");
+
+    // But not by a request bound to another tenant, which goes out through a worker of that tenant.
+    let (_, body) = get(&client, "http://a.example.com/3", bob, false).await;
+    assert!(body.starts_with('b'));
+    assert!(body.ends_with(" a.example.com:80/3"));
+
+    // A request to another host connects another upstream.
+    // An Upgrade request is not welded without a 101 response, so the connection goes on.
+    let (status, body) = get(&client, "http://b.example.com/4", bob, true).await;
+    assert_eq!(status, 200);
+    let worker = body.split(' ').next().unwrap().to_string();
+    assert_eq!(body, format!("{} b.example.com:80/4", worker));
+    let (_, body) = get(&client, "http://b.example.com/5", bob, false).await;
+    assert_eq!(body, format!("{} b.example.com:80/5", worker));
+
+    // The connection is welded with the upstream after the 101 response.
+    let (status, _) = get(&client, "http://b.example.com/switch", bob, true).await;
+    assert_eq!(status, 101);
+    client.writer.lock().await.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    client
+        .reader
+        .lock()
+        .await
+        .read_exact(&mut buf)
+        .await
+        .unwrap();
+    assert_eq!(&buf, b"ping");
+}
@@ -0,0 +1,87 @@
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use tokio::time::Instant;
+
+use super::super::manager::{Candidate, SelectionPolicy, Selector};
+
+/// Create candidates from "host:port" addresses, each one has been idle for a shorter time than the previous one.
+fn candidates(addrs: &[&str]) -> Vec<Candidate> {
+    let start = Instant::now();
+    addrs
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| Candidate {
+            addr: addr.parse().unwrap(),
+            idle_since: start + Duration::from_secs(i as u64),
+        })
+        .collect()
+}
+
+/// Select workers one by one, the selected ones are removed from the candidates and counted as open sessions.
+fn select_all(policy: SelectionPolicy, addrs: &[&str]) -> Vec<String> {
+    let mut selector = Selector::new(policy);
+    let mut candidates = candidates(addrs);
+    let mut sessions = HashMap::new();
+    let mut selected = Vec::new();
+    while let Some(addr) = selector.select(&candidates, &sessions) {
+        candidates.retain(|c| c.addr != addr);
+        *sessions.entry(addr.ip()).or_insert(0) += 1;
+        selected.push(addr.to_string());
+    }
+    selected
+}
+
+const ADDRS: [&str; 6] = [
+    "10.0.0.1:1",
+    "10.0.0.1:2",
+    "10.0.0.1:3",
+    "10.0.0.3:1",
+    "10.0.0.2:1",
+    "10.0.0.2:2",
+];
+
+#[test]
+fn test_fifo_and_lifo() {
+    let mut expected: Vec<String> = ADDRS.iter().map(|v| v.to_string()).collect();
+    assert_eq!(select_all(SelectionPolicy::Fifo, &ADDRS), expected);
+    expected.reverse();
+    assert_eq!(select_all(SelectionPolicy::Lifo, &ADDRS), expected);
+}
+
+#[test]
+fn test_round_robin() {
+    // The hosts take turns in order, and the longest idle worker of each host is selected.
+    assert_eq!(
+        select_all(SelectionPolicy::RoundRobin, &ADDRS),
+        vec![
+            "10.0.0.1:1",
+            "10.0.0.2:1",
+            "10.0.0.3:1",
+            "10.0.0.1:2",
+            "10.0.0.2:2",
+            "10.0.0.1:3",
+        ]
+    );
+}
+
+#[test]
+fn test_least_sessions() {
+    let mut selector = Selector::new(SelectionPolicy::LeastSessions);
+    let candidates = candidates(&ADDRS);
+
+    // The host with the fewest open sessions wins, even if it has fewer idle workers.
+    let sessions = HashMap::from([
+        ("10.0.0.1".parse().unwrap(), 2),
+        ("10.0.0.2".parse().unwrap(), 1),
+        ("10.0.0.3".parse().unwrap(), 3),
+    ]);
+    let expected: SocketAddr = "10.0.0.2:1".parse().unwrap();
+    assert_eq!(selector.select(&candidates, &sessions), Some(expected));
+
+    // A host without open sessions wins.
+    let sessions = HashMap::from([("10.0.0.1".parse().unwrap(), 1)]);
+    let expected: SocketAddr = "10.0.0.2:1".parse().unwrap();
+    assert_eq!(selector.select(&candidates, &sessions), Some(expected));
+
+    assert_eq!(selector.select(&[], &sessions), None);
+}
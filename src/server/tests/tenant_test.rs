@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use base64::Engine;
+
+use super::super::{
+    credentials::Credentials,
+    tenant::{load_tenant_configs, Tenant, Tenants, UserCredential},
+};
+
+fn tenant(path: Option<&str>, users: &[&str], allow_anonymous: bool) -> Tenant {
+    let users = users.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    Tenant {
+        users: Credentials::load(&users, &None).unwrap(),
+        allow_anonymous,
+        ..Tenant::for_test(path)
+    }
+}
+
+fn basic(raw: &str) -> String {
+    format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(raw)
+    )
+}
+
+/// Get the path of the tenant and the username bound by a credential.
+fn bind(
+    tenants: &Tenants,
+    bound: Option<&Arc<Tenant>>,
+    credential: Option<UserCredential>,
+) -> Option<(Option<String>, Option<String>)> {
+    tenants
+        .bind_user(bound, credential)
+        .map(|(t, user)| (t.path.clone(), user))
+}
+
+fn load(content: &str) -> Result<Vec<String>, String> {
+    let path = std::env::temp_dir().join(format!("neck-tenants-{}", std::process::id()));
+    std::fs::write(&path, content).unwrap();
+    let result = load_tenant_configs(&path.to_string_lossy());
+    std::fs::remove_file(&path).unwrap();
+    result
+        .map(|configs| configs.into_keys().collect())
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_load_configs() {
+    let paths = load(
+        r#"{
+            "/team-b": { "max_workers": 10, "routes": ["zone-b 10.2.0.0/16"] },
+            "/team-a": { "users": ["alice:secret"], "listen": "1082" }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(paths, vec!["/team-a", "/team-b"]);
+
+    // The "/" is reserved for the default tenant.
+    let e = load(r#"{ "/": {} }"#).unwrap_err();
+    assert!(e.contains("Bad tenant path '/'"));
+    assert!(load(r#"{ "team-a": {} }"#).is_err());
+    assert!(load(r#"{ "/team a": {} }"#).is_err());
+    assert!(load(r#"{ "/team-a?x=1": {} }"#).is_err());
+
+    // Unknown fields are rejected, so that a typo is not ignored silently.
+    let e = load(r#"{ "/team-a": { "max_worker": 10 } }"#).unwrap_err();
+    assert!(e.contains("Bad tenants file"));
+}
+
+#[test]
+fn test_get_by_path() {
+    // Without named tenants, all paths belong to the default tenant.
+    let tenants = Tenants::new(tenant(None, &[], false), vec![]);
+    assert!(tenants.get_by_path("/anything").unwrap().path.is_none());
+
+    let tenants = Tenants::new(
+        tenant(None, &[], false),
+        vec![tenant(Some("/team-a"), &[], false)],
+    );
+    assert!(tenants.get_by_path("/").unwrap().path.is_none());
+    let found = tenants.get_by_path("/team-a?token=1").unwrap();
+    assert_eq!(found.path.as_deref(), Some("/team-a"));
+    assert!(tenants.get_by_path("/team-b").is_none());
+    assert!(tenants.get_by_path("/team-a/").is_none());
+}
+
+#[test]
+fn test_bind_user() {
+    let tenants = Tenants::new(
+        tenant(None, &["root:0"], false),
+        vec![
+            tenant(Some("/team-a"), &["alice:1", "root:1"], false),
+            tenant(Some("/team-b"), &["bob:2"], true),
+        ],
+    );
+    let team_b = tenants.get_by_path("/team-b").unwrap().clone();
+    let ok = |path: Option<&str>, user: &str| Some((path.map(String::from), Some(user.into())));
+
+    // On the main listener, users are bound by credentials, and the default tenant is checked first.
+    let alice = basic("alice:1");
+    let credential = Some(UserCredential::Basic(&alice));
+    assert_eq!(
+        bind(&tenants, None, credential),
+        ok(Some("/team-a"), "alice")
+    );
+    let credential = Some(UserCredential::Password("root", "0"));
+    assert_eq!(bind(&tenants, None, credential), ok(None, "root"));
+    let credential = Some(UserCredential::Password("root", "1"));
+    assert_eq!(
+        bind(&tenants, None, credential),
+        ok(Some("/team-a"), "root")
+    );
+    let credential = Some(UserCredential::Password("bob", "0"));
+    assert_eq!(bind(&tenants, None, credential), None);
+
+    // Anonymous users belong to the default tenant, which requires credentials.
+    assert_eq!(bind(&tenants, None, None), None);
+    assert!(tenants.has_users(None));
+    assert!(!tenants.allows_anonymous(None));
+
+    // On a dedicated listener, only the tenant of the listener is considered.
+    let credential = Some(UserCredential::Password("bob", "2"));
+    assert_eq!(
+        bind(&tenants, Some(&team_b), credential),
+        ok(Some("/team-b"), "bob")
+    );
+    let credential = Some(UserCredential::Password("alice", "1"));
+    assert_eq!(bind(&tenants, Some(&team_b), credential), None);
+    assert_eq!(
+        bind(&tenants, Some(&team_b), None),
+        Some((Some("/team-b".into()), None))
+    );
+    assert!(tenants.allows_anonymous(Some(&team_b)));
+}
+
+#[test]
+fn test_bind_user_without_authentication() {
+    let tenants = Tenants::new(
+        tenant(None, &[], false),
+        vec![tenant(Some("/team-a"), &["alice:1"], false)],
+    );
+
+    // A wrong credential is ignored if the default tenant does not require credentials.
+    let credential = Some(UserCredential::Password("alice", "0"));
+    assert_eq!(bind(&tenants, None, credential), Some((None, None)));
+    assert_eq!(bind(&tenants, None, None), Some((None, None)));
+    assert!(tenants.has_users(None));
+    assert!(tenants.allows_anonymous(None));
+}
@@ -0,0 +1,88 @@
+use native_tls::{Certificate, Identity};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::utils::{tests::TestCert, NeckStream};
+
+use super::super::tls::ServerTls;
+
+/// Create a server TLS with a certificate for "localhost", which verifies clients with `client_ca`.
+fn create_server_tls(name: &str, server: &TestCert, client_ca: &TestCert) -> ServerTls {
+    let cert = TestCert::write_temp(&format!("{}-server.crt", name), &server.cert_pem());
+    let key = TestCert::write_temp(&format!("{}-server.key", name), &server.key_pem());
+    let ca = TestCert::write_temp(&format!("{}-client-ca.crt", name), &client_ca.cert_pem());
+    let tls = ServerTls::new(&cert, &key, &Some(ca.clone())).unwrap();
+    for path in [cert, key, ca] {
+        std::fs::remove_file(path).unwrap();
+    }
+    tls
+}
+
+/// Perform a handshake with an optional client identity,
+/// and return the subject of the client certificate if the server accepts it.
+async fn handshake(
+    tls: &ServerTls,
+    server: &TestCert,
+    identity: Option<Identity>,
+) -> Result<Option<String>, ()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.add_root_certificate(Certificate::from_pem(&server.cert_pem()).unwrap());
+    if let Some(identity) = identity {
+        builder.identity(identity);
+    }
+    let connector = tokio_native_tls::TlsConnector::from(builder.build().unwrap());
+
+    let client = async {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        connector.connect("localhost", stream).await
+    };
+    let server = async {
+        let (stream, _) = listener.accept().await.unwrap();
+        tls.accept(stream).await
+    };
+
+    // The client may finish its handshake before the server rejects the certificate, so only the server side matters.
+    let (_client, server) = tokio::join!(client, server);
+    server
+        .map(|stream| NeckStream::from(stream).peer_subject)
+        .map_err(|_| ())
+}
+
+#[tokio::test]
+async fn test_client_certificate() {
+    let ca = TestCert::self_signed("neck-ca");
+    let server = TestCert::self_signed("localhost");
+    let tls = create_server_tls("mtls", &server, &ca);
+    assert!(tls.verifies_client());
+
+    let worker = TestCert::signed_by("worker-1", &ca);
+    let identity = Identity::from_pkcs8(&worker.cert_pem(), &worker.key_pem()).unwrap();
+    assert_eq!(
+        handshake(&tls, &server, Some(identity)).await,
+        Ok(Some("CN=worker-1".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn test_missing_client_certificate() {
+    let ca = TestCert::self_signed("neck-ca");
+    let server = TestCert::self_signed("localhost");
+    let tls = create_server_tls("no-cert", &server, &ca);
+
+    // Clients without certificates complete the handshake, and are rejected later by the join handler.
+    assert_eq!(handshake(&tls, &server, None).await, Ok(None));
+}
+
+#[tokio::test]
+async fn test_untrusted_client_certificate() {
+    let ca = TestCert::self_signed("neck-ca");
+    let server = TestCert::self_signed("localhost");
+    let tls = create_server_tls("untrusted", &server, &ca);
+
+    let other_ca = TestCert::self_signed("other-ca");
+    let worker = TestCert::signed_by("worker-1", &other_ca);
+    let identity = Identity::from_pkcs8(&worker.cert_pem(), &worker.key_pem()).unwrap();
+    assert_eq!(handshake(&tls, &server, Some(identity)).await, Err(()));
+}
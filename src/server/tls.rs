@@ -0,0 +1,68 @@
+use std::pin::Pin;
+
+use openssl::{
+    ssl::{Ssl, SslAcceptor, SslFiletype, SslMethod, SslVerifyMode},
+    x509::X509Name,
+};
+use tokio::net::TcpStream;
+use tokio_native_tls::TlsAcceptor;
+use tokio_openssl::SslStream;
+
+use crate::utils::{read_file, NeckResult, SupportedStream};
+
+pub enum ServerTls {
+    /// The native TLS, which cannot request client certificates.
+    Native(TlsAcceptor),
+    /// The OpenSSL, which requests client certificates and verifies them with a private CA.
+    OpenSsl(SslAcceptor),
+}
+
+impl ServerTls {
+    /// Create a TLS acceptor with a PEM certificate chain and a PEM PKCS#8 private key.
+    /// If `client_ca` is provided, client certificates will be requested and verified with it.
+    pub fn new(cert: &str, key: &str, client_ca: &Option<String>) -> NeckResult<Self> {
+        let client_ca = match client_ca {
+            Some(it) => it,
+            None => {
+                let identity =
+                    native_tls::Identity::from_pkcs8(&read_file(cert)?, &read_file(key)?)?;
+                return Ok(ServerTls::Native(
+                    native_tls::TlsAcceptor::new(identity)?.into(),
+                ));
+            }
+        };
+
+        let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+        builder.set_certificate_chain_file(cert)?;
+        builder.set_private_key_file(key, SslFiletype::PEM)?;
+        builder.check_private_key()?;
+
+        // Trust the private CA for client certificates, and advertise it to clients.
+        builder.set_ca_file(client_ca)?;
+        builder.set_client_ca_list(X509Name::load_client_ca_file(client_ca)?);
+
+        // NOTE: The PEER mode requests a certificate, and fails the handshake if the certificate is invalid.
+        // But a client without any certificate is allowed, because proxy users may share the same port.
+        // The certificate is required later by the join handler.
+        builder.set_verify(SslVerifyMode::PEER);
+
+        Ok(ServerTls::OpenSsl(builder.build()))
+    }
+
+    /// Check if client certificates are verified.
+    pub fn verifies_client(&self) -> bool {
+        matches!(self, ServerTls::OpenSsl(_))
+    }
+
+    /// Perform a TLS handshake on an accepted TcpStream.
+    pub async fn accept(&self, stream: TcpStream) -> NeckResult<SupportedStream> {
+        match self {
+            ServerTls::Native(acceptor) => Ok(acceptor.accept(stream).await?.into()),
+            ServerTls::OpenSsl(acceptor) => {
+                let mut ssl_stream = SslStream::new(Ssl::new(acceptor.context())?, stream)?;
+                Pin::new(&mut ssl_stream).accept().await?;
+                Ok(ssl_stream.into())
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::{fmt::Display, net::Ipv4Addr};
+
+use tokio::io::{
+    self, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+
+mod tests;
+
+/// https://www.openssh.com/txt/socks4.protocol
+pub const CONNECT: u8 = 0x01;
+
+/// The reply codes, a SOCKS4 server has no way to tell why a request is rejected.
+pub const GRANTED: u8 = 0x5a;
+pub const REJECTED: u8 = 0x5b;
+
+/// The longest USERID or domain name which is accepted, the strings are terminated by NULL.
+const MAX_STRING: u64 = 256;
+
+/// A SOCKS4 request, or a SOCKS4a request with a domain name (https://www.openssh.com/txt/socks4a.protocol).
+#[derive(Debug, Clone)]
+pub struct Socks4Request {
+    pub command: u8,
+    pub port: u16,
+    pub ip: Ipv4Addr,
+    pub userid: String,
+    /// The domain name of SOCKS4a, which is sent if the IP is 0.0.0.x with a nonzero x.
+    pub domain: Option<String>,
+}
+
+async fn read_string<T: AsyncRead + Unpin>(reader: &mut BufReader<T>) -> io::Result<String> {
+    let mut buf = Vec::new();
+    (&mut *reader)
+        .take(MAX_STRING)
+        .read_until(0, &mut buf)
+        .await?;
+    if buf.pop() != Some(0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad protocol"));
+    }
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+impl Socks4Request {
+    pub async fn read_from<T: AsyncRead + Unpin>(reader: &mut BufReader<T>) -> io::Result<Self> {
+        reader.read_u8().await?; // version
+        let command = reader.read_u8().await?;
+        let port = reader.read_u16().await?;
+        let ip = Ipv4Addr::from(reader.read_u32().await?);
+        let userid = read_string(reader).await?;
+        let domain = match ip.octets() {
+            [0, 0, 0, x] if x != 0 => Some(read_string(reader).await?),
+            _ => None,
+        };
+        Ok(Self {
+            command,
+            port,
+            ip,
+            userid,
+            domain,
+        })
+    }
+
+    /// Write a reply with the `code`, where the port and IP are ignored by clients.
+    pub async fn reply<T: AsyncWrite + Unpin>(&self, writer: &mut T, code: u8) -> io::Result<()> {
+        let mut buf = vec![0, code]; // version of the reply, and the code
+        buf.extend(self.port.to_be_bytes());
+        buf.extend(self.ip.octets());
+        writer.write_all(&buf).await
+    }
+}
+
+/// Format the destination as "host:port".
+impl Display for Socks4Request {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.domain {
+            Some(domain) => write!(f, "{}:{}", domain, self.port),
+            None => write!(f, "{}:{}", self.ip, self.port),
+        }
+    }
+}
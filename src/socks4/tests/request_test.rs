@@ -0,0 +1,39 @@
+use tokio::io::BufReader;
+
+use super::super::{Socks4Request, CONNECT, GRANTED};
+
+async fn read(raw: &[u8]) -> std::io::Result<Socks4Request> {
+    Socks4Request::read_from(&mut BufReader::new(raw)).await
+}
+
+#[tokio::test]
+async fn test_socks4() {
+    let req = read(b"\x04\x01\x00\x50\x7f\x00\x00\x01alice\x00")
+        .await
+        .unwrap();
+    assert_eq!(req.command, CONNECT);
+    assert_eq!(req.userid, "alice");
+    assert_eq!(req.domain, None);
+    assert_eq!(req.to_string(), "127.0.0.1:80");
+
+    let mut reply = Vec::new();
+    req.reply(&mut reply, GRANTED).await.unwrap();
+    assert_eq!(reply, b"\x00\x5a\x00\x50\x7f\x00\x00\x01");
+}
+
+#[tokio::test]
+async fn test_socks4a() {
+    let req = read(b"\x04\x01\x01\xbb\x00\x00\x00\x01\x00example.com\x00")
+        .await
+        .unwrap();
+    assert_eq!(req.userid, "");
+    assert_eq!(req.to_string(), "example.com:443");
+
+    // The strings must be terminated.
+    assert!(read(b"\x04\x01\x00\x50\x7f\x00\x00\x01alice")
+        .await
+        .is_err());
+    assert!(read(b"\x04\x01\x00\x50\x00\x00\x00\x01\x00example.com")
+        .await
+        .is_err());
+}
@@ -1,5 +1,7 @@
 use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 
+use super::{NO_ACCEPTABLE_METHODS, NO_AUTHENTICATION, USERNAME_PASSWORD};
+
 #[derive(Debug)]
 pub struct ClientGreeting {
     pub ver: u8,
@@ -14,6 +16,19 @@ impl ClientGreeting {
         reader.take(size as u64).read_to_end(&mut auth_list).await?;
         Ok(Self { ver, auth_list })
     }
+
+    /// Choose an authentication method offered by the client, username/password is preferred if there are users,
+    /// and no authentication is accepted only if `anonymous` is allowed.
+    pub fn choose_method(&self, has_users: bool, anonymous: bool) -> u8 {
+        let offered = |method| self.auth_list.contains(&method);
+        if has_users && offered(USERNAME_PASSWORD) {
+            USERNAME_PASSWORD
+        } else if anonymous && offered(NO_AUTHENTICATION) {
+            NO_AUTHENTICATION
+        } else {
+            NO_ACCEPTABLE_METHODS
+        }
+    }
 }
 
 #[derive(Debug)]
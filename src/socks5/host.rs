@@ -1,4 +1,7 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    net::{IpAddr, SocketAddr},
+};
 
 use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 
@@ -24,8 +27,69 @@ impl Host {
     }
 }
 
+impl Host {
+    /// Decode a host from the head of a buffer, return the host and the rest, None if it is malformed.
+    pub fn decode(buf: &[u8]) -> Option<(Self, &[u8])> {
+        let (address, rest) = match *buf.first()? {
+            1 => (
+                Address::IPv4(u32::from_be_bytes(buf.get(1..5)?.try_into().ok()?)),
+                &buf[5..],
+            ),
+            3 => {
+                let size = *buf.get(1)? as usize;
+                let domain = String::from_utf8(buf.get(2..2 + size)?.to_vec()).ok()?;
+                (Address::Domain(domain), &buf[2 + size..])
+            }
+            4 => (
+                Address::IPv6(u128::from_be_bytes(buf.get(1..17)?.try_into().ok()?)),
+                &buf[17..],
+            ),
+            _ => return None,
+        };
+        let port = u16::from_be_bytes(rest.get(..2)?.try_into().ok()?);
+        Some((Self { address, port }, &rest[2..]))
+    }
+
+    /// Encode the host to the end of a buffer.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match &self.address {
+            Address::IPv4(value) => {
+                buf.push(1);
+                buf.extend(value.to_be_bytes());
+            }
+            Address::Domain(domain) => {
+                buf.push(3);
+                buf.push(domain.len() as u8);
+                buf.extend(domain.as_bytes());
+            }
+            Address::IPv6(value) => {
+                buf.push(4);
+                buf.extend(value.to_be_bytes());
+            }
+        }
+        buf.extend(self.port.to_be_bytes());
+    }
+}
+
+impl From<SocketAddr> for Host {
+    fn from(addr: SocketAddr) -> Self {
+        let address = match addr.ip() {
+            IpAddr::V4(ip) => Address::IPv4(ip.into()),
+            IpAddr::V6(ip) => Address::IPv6(ip.into()),
+        };
+        Self {
+            address,
+            port: addr.port(),
+        }
+    }
+}
+
 impl Display for Host {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}:{}", self.address, self.port)
+        match self.address {
+            // An IPv6 address must be enclosed in brackets, so that the port can be separated.
+            Address::IPv6(_) => write!(f, "[{}]:{}", self.address, self.port),
+            _ => write!(f, "{}:{}", self.address, self.port),
+        }
     }
 }
@@ -2,11 +2,27 @@ use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufRea
 mod address;
 mod greeting;
 mod host;
+mod udp;
+mod userpass;
+
+mod tests;
 
 use address::*;
-use host::*;
 
 pub use greeting::*;
+pub use host::*;
+pub use udp::*;
+pub use userpass::*;
+
+/// https://datatracker.ietf.org/doc/html/rfc1928#section-3
+pub const NO_AUTHENTICATION: u8 = 0x00;
+pub const USERNAME_PASSWORD: u8 = 0x02;
+pub const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+
+/// https://datatracker.ietf.org/doc/html/rfc1928#section-4
+pub const CONNECT: u8 = 0x01;
+pub const BIND: u8 = 0x02;
+pub const UDP_ASSOCIATE: u8 = 0x03;
 
 #[derive(Debug, Clone)]
 pub struct Socks5Message {
@@ -0,0 +1,79 @@
+use tokio::io::BufReader;
+
+use super::super::{
+    ClientGreeting, UserPassRequest, UserPassResponse, NO_ACCEPTABLE_METHODS, NO_AUTHENTICATION,
+    USERNAME_PASSWORD,
+};
+
+fn greeting(auth_list: &[u8]) -> ClientGreeting {
+    ClientGreeting {
+        ver: 5,
+        auth_list: auth_list.to_vec(),
+    }
+}
+
+#[test]
+fn test_choose_method() {
+    let both = greeting(&[NO_AUTHENTICATION, USERNAME_PASSWORD]);
+    assert_eq!(both.choose_method(true, true), USERNAME_PASSWORD);
+    assert_eq!(both.choose_method(true, false), USERNAME_PASSWORD);
+
+    // Falls back to no authentication if there are no users, or the client does not offer username/password.
+    assert_eq!(both.choose_method(false, true), NO_AUTHENTICATION);
+    let anonymous = greeting(&[NO_AUTHENTICATION]);
+    assert_eq!(anonymous.choose_method(true, true), NO_AUTHENTICATION);
+
+    // Nothing is acceptable.
+    assert_eq!(anonymous.choose_method(true, false), NO_ACCEPTABLE_METHODS);
+    assert_eq!(both.choose_method(false, false), NO_ACCEPTABLE_METHODS);
+    assert_eq!(
+        greeting(&[]).choose_method(true, true),
+        NO_ACCEPTABLE_METHODS
+    );
+    assert_eq!(
+        greeting(&[USERNAME_PASSWORD]).choose_method(false, true),
+        NO_ACCEPTABLE_METHODS
+    );
+}
+
+#[tokio::test]
+async fn test_userpass_request() {
+    let raw: &[u8] = b"\x01\x03bob\x02pw";
+    let req = UserPassRequest::read_from(&mut BufReader::new(raw))
+        .await
+        .unwrap();
+    assert_eq!(req.user, "bob");
+    assert_eq!(req.password, "pw");
+
+    // Empty username and password.
+    let raw: &[u8] = b"\x01\x00\x00";
+    let req = UserPassRequest::read_from(&mut BufReader::new(raw))
+        .await
+        .unwrap();
+    assert_eq!((req.user.as_str(), req.password.as_str()), ("", ""));
+
+    // The socks version is not the sub-negotiation version.
+    let raw: &[u8] = b"\x05\x03bob\x02pw";
+    let e = UserPassRequest::read_from(&mut BufReader::new(raw))
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+
+    // Truncated.
+    let raw: &[u8] = b"\x01\x03bob\x05pw";
+    assert!(UserPassRequest::read_from(&mut BufReader::new(raw))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_userpass_response() {
+    for (status, expected) in [(0, b"\x01\x00"), (1, b"\x01\x01")] {
+        let mut buf = Vec::new();
+        UserPassResponse::new(status)
+            .write_to(&mut buf)
+            .await
+            .unwrap();
+        assert_eq!(&buf, expected);
+    }
+}
@@ -0,0 +1,4 @@
+#[cfg(test)]
+mod auth_test;
+#[cfg(test)]
+mod udp_test;
@@ -0,0 +1,50 @@
+use std::net::SocketAddr;
+
+use super::super::{build_udp_reply, parse_udp_request, Host};
+
+fn encode(host: &Host) -> Vec<u8> {
+    let mut buf = Vec::new();
+    host.encode(&mut buf);
+    buf
+}
+
+#[test]
+fn test_host_codec() {
+    for raw in ["127.0.0.1:53", "[::1]:123"] {
+        let host = Host::from(raw.parse::<SocketAddr>().unwrap());
+        assert_eq!(host.to_string(), raw);
+        let buf = encode(&host);
+        let (decoded, rest) = Host::decode(&buf).unwrap();
+        assert_eq!(decoded.to_string(), raw);
+        assert!(rest.is_empty());
+    }
+
+    // A domain with the data after it.
+    let buf = b"\x03\x0bexample.com\x00\x35data";
+    let (host, rest) = Host::decode(buf).unwrap();
+    assert_eq!(host.to_string(), "example.com:53");
+    assert_eq!(rest, b"data");
+    assert_eq!(encode(&host), &buf[..buf.len() - 4]);
+
+    // Truncated or unknown addresses.
+    assert!(Host::decode(b"\x03\x0bexample").is_none());
+    assert!(Host::decode(b"\x01\x7f\x00\x00\x01\x00").is_none());
+    assert!(Host::decode(b"\x05").is_none());
+    assert!(Host::decode(b"").is_none());
+}
+
+#[test]
+fn test_udp_datagram() {
+    let host = Host::from("10.0.0.1:53".parse::<SocketAddr>().unwrap());
+    let reply = build_udp_reply(&host, b"hello");
+    assert_eq!(&reply[..4], b"\x00\x00\x00\x01");
+
+    let (parsed, data) = parse_udp_request(&reply).unwrap();
+    assert_eq!(parsed.to_string(), "10.0.0.1:53");
+    assert_eq!(data, b"hello");
+
+    // A fragment is not supported.
+    let mut fragment = reply.clone();
+    fragment[2] = 1;
+    assert!(parse_udp_request(&fragment).is_none());
+}
@@ -0,0 +1,18 @@
+use super::Host;
+
+/// Parse a UDP request (RFC 1928 section 7), return the destination and the data, None if it is malformed.
+/// NOTE: Fragmentation is not supported, so a fragment is regarded as malformed, which should be dropped.
+pub fn parse_udp_request(buf: &[u8]) -> Option<(Host, &[u8])> {
+    match buf.get(..3)? {
+        [0, 0, 0] => Host::decode(&buf[3..]),
+        _ => None,
+    }
+}
+
+/// Build a UDP reply (RFC 1928 section 7) with the source host and the data.
+pub fn build_udp_reply(host: &Host, data: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0, 0, 0];
+    host.encode(&mut buf);
+    buf.extend(data);
+    buf
+}
@@ -0,0 +1,54 @@
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// The version of the username/password sub-negotiation, which is not the socks version.
+pub const USERPASS_VERSION: u8 = 0x01;
+
+/// https://datatracker.ietf.org/doc/html/rfc1929#section-2
+#[derive(Debug)]
+pub struct UserPassRequest {
+    pub user: String,
+    pub password: String,
+}
+
+async fn read_string<T: AsyncRead + Unpin>(reader: &mut BufReader<T>) -> io::Result<String> {
+    let size = reader.read_u8().await?;
+    let mut buf = vec![0; size as usize];
+    reader.read_exact(&mut buf).await?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+impl UserPassRequest {
+    pub async fn read_from<T: AsyncRead + Unpin>(reader: &mut BufReader<T>) -> io::Result<Self> {
+        let ver = reader.read_u8().await?;
+        if ver != USERPASS_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported username/password version {}", ver),
+            ));
+        }
+        let user = read_string(reader).await?;
+        let password = read_string(reader).await?;
+        Ok(Self { user, password })
+    }
+}
+
+#[derive(Debug)]
+pub struct UserPassResponse {
+    pub ver: u8,
+    pub status: u8,
+}
+
+impl UserPassResponse {
+    /// The status 0 indicates success, and any other value indicates failure.
+    pub fn new(status: u8) -> Self {
+        Self {
+            ver: USERPASS_VERSION,
+            status,
+        }
+    }
+
+    pub async fn write_to<T: AsyncWrite + Unpin>(&self, writer: &mut T) -> io::Result<()> {
+        writer.write_all(&[self.ver, self.status]).await?;
+        Ok(())
+    }
+}
@@ -8,17 +8,18 @@ export const createMainTable = () => {
   const createRow = (data) => {
     const row = table.insertRow();
     row.dataset.id = data.id;
-    const cells = Array.from({ length: 6 }, () => row.insertCell());
+    const cells = Array.from({ length: 7 }, () => row.insertCell());
     row.update = (data) => {
       cells[0].textContent = data.id;
       cells[1].textContent = data.proto;
       cells[2].innerHTML = renderState(data.state);
       cells[3].textContent = data.host;
-      cells[4].textContent = data.from;
-      if (cells[5].timestampe !== data.timestamp) {
-        cells[5].timestampe = data.timestamp;
-        cells[5].innerHTML = "";
-        cells[5].appendChild(createLiveTime(data.timestamp));
+      cells[4].textContent = data.user ?? "";
+      cells[5].textContent = data.from;
+      if (cells[6].timestampe !== data.timestamp) {
+        cells[6].timestampe = data.timestamp;
+        cells[6].innerHTML = "";
+        cells[6].appendChild(createLiveTime(data.timestamp));
       }
     };
     row.update(data);
@@ -53,6 +54,7 @@ export const createMainTable = () => {
   row.insertCell().textContent = "Type";
   row.insertCell().textContent = "State";
   row.insertCell().textContent = "Host";
+  row.insertCell().textContent = "User";
   row.insertCell().textContent = "From";
   row.insertCell().textContent = "Uptime";
 
@@ -0,0 +1,111 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::http::Headers;
+
+/// The version of the join protocol, which is increased on every incompatible change.
+/// Version 1 is the original protocol, which sends no version header.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// The oldest protocol version that is still supported.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// The header to negotiate the protocol version, the worker offers its version and the server replies the selected one.
+pub const VERSION_HEADER: &str = "Neck-Version";
+
+/// The header to negotiate capabilities, its value is a comma-separated list.
+pub const CAPABILITIES_HEADER: &str = "Neck-Capabilities";
+
+/// The header to declare the worker group when joining.
+pub const GROUP_HEADER: &str = "Neck-Group";
+
+/// The header to tell a worker how many more idle workers the server wants from its client host,
+/// a negative value means that there are too many. It is sent with the join response, PING and CONNECT requests.
+pub const DEMAND_HEADER: &str = "Neck-Demand";
+
+/// The header to tell an address of a BIND request, which is the address bound by the worker, or the peer connected to it.
+pub const ADDRESS_HEADER: &str = "Neck-Address";
+
+/// The software version, which is sent with the `User-Agent` header by workers, and the `Server` header by the server.
+pub const SOFTWARE: &str = concat!("neck/", env!("CARGO_PKG_VERSION"));
+
+/// The capability name of the heartbeat, a worker offering it answers PING requests,
+/// and the server selecting it sends PING requests to the idle worker constantly.
+pub const HEARTBEAT: &str = "heartbeat";
+
+/// The capability name of the UDP relay, only a worker offering it is sent ASSOCIATE requests.
+pub const UDP_RELAY: &str = "udp";
+
+/// The capability name of the BIND relay, only a worker offering it is sent BIND requests.
+pub const BIND_RELAY: &str = "bind";
+
+/// Get the protocol version from the headers of a join request or response, None if it is malformed.
+/// NOTE: A missing header means version 1, which is the original protocol.
+pub fn get_protocol_version(headers: &Headers) -> Option<u32> {
+    match headers.get_header_value(VERSION_HEADER) {
+        Some(v) => v.trim().parse().ok(),
+        None => Some(1),
+    }
+}
+
+/// Select the protocol version to speak with a peer, None if the `offered` version is malformed or older than `min`.
+/// A newer peer is fine, it speaks the older version of this side.
+/// NOTE: The `min` is always `MIN_PROTOCOL_VERSION`, except in tests, where it is raised to reject the current versions.
+pub fn select_protocol_version(offered: Option<u32>, min: u32) -> Option<u32> {
+    offered
+        .filter(|v| *v >= min)
+        .map(|v| v.min(PROTOCOL_VERSION))
+}
+
+/// Get the demand from the headers of a message, None if it is absent or malformed.
+pub fn get_demand(headers: &Headers) -> Option<i64> {
+    headers.get_header_value(DEMAND_HEADER)?.trim().parse().ok()
+}
+
+/// A set of capability names, which are compared case-insensitively.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Capabilities(Vec<String>);
+
+impl Capabilities {
+    /// Parse a comma-separated list, empty items and duplicates are ignored.
+    pub fn parse(list: &str) -> Self {
+        let mut capabilities = Self::default();
+        for name in list.split(',') {
+            capabilities.add(name);
+        }
+        capabilities
+    }
+
+    /// Get the capabilities from the headers of a join request or response, which are empty if the header is missing.
+    pub fn from_headers(headers: &Headers) -> Self {
+        Self::parse(
+            headers
+                .get_header_value(CAPABILITIES_HEADER)
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.0.iter().any(|v| v.eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn add(&mut self, name: &str) -> &mut Self {
+        let name = name.trim();
+        if !name.is_empty() && !self.has(name) {
+            self.0.push(name.to_lowercase());
+        }
+        self
+    }
+
+    /// Get the capabilities that are also in `other`.
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self(self.0.iter().filter(|v| other.has(v)).cloned().collect())
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0.join(", "))
+    }
+}
@@ -0,0 +1,150 @@
+use std::{fmt::Display, io};
+
+use tokio::time::error::Elapsed;
+
+use crate::http::Headers;
+
+use super::BoxedError;
+
+/// The header to tell the reason why a worker has failed to connect the destination, which is sent with the response of CONNECT.
+pub const FAILURE_HEADER: &str = "Neck-Failure";
+
+/// The name of this proxy in the `Proxy-Status` header.
+const PROXY_NAME: &str = "neck";
+
+/// The reason why a destination cannot be connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// The connection with the worker is broken, or the worker answers garbage.
+    Transport,
+    /// The destination cannot be resolved.
+    Dns,
+    /// The destination has refused the connection.
+    Refused,
+    /// Connecting the destination has timed out.
+    Timeout,
+    /// The network or the host of the destination is unreachable.
+    Unreachable,
+    /// The destination is forbidden by the access rules.
+    Forbidden,
+    /// Any other error, including the failures reported by older workers without a reason.
+    Other,
+}
+
+impl Failure {
+    const NAMES: [(Failure, &'static str); 7] = [
+        (Failure::Transport, "transport"),
+        (Failure::Dns, "dns"),
+        (Failure::Refused, "refused"),
+        (Failure::Timeout, "timeout"),
+        (Failure::Unreachable, "unreachable"),
+        (Failure::Forbidden, "forbidden"),
+        (Failure::Other, "other"),
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        Self::NAMES.iter().find(|(f, _)| f == self).unwrap().1
+    }
+
+    /// Parse a reason case-insensitively, None if it is unknown.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        Self::NAMES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(raw))
+            .map(|(f, _)| *f)
+    }
+
+    /// Get the reason from the headers of a CONNECT response, which is `Other` if it is absent or unknown.
+    pub fn from_headers(headers: &Headers) -> Self {
+        headers
+            .get_header_value(FAILURE_HEADER)
+            .and_then(Self::parse)
+            .unwrap_or(Self::Other)
+    }
+
+    /// Classify an error of connecting a resolved destination.
+    pub fn from_error(e: &BoxedError) -> Self {
+        if e.is::<Elapsed>() {
+            return Self::Timeout;
+        }
+        match e.downcast_ref::<io::Error>().map(io::Error::kind) {
+            Some(io::ErrorKind::ConnectionRefused) => Self::Refused,
+            Some(io::ErrorKind::TimedOut) => Self::Timeout,
+            Some(io::ErrorKind::HostUnreachable | io::ErrorKind::NetworkUnreachable) => {
+                Self::Unreachable
+            }
+            _ => Self::Other,
+        }
+    }
+
+    /// Get the HTTP status to answer the user.
+    pub fn http_status(&self) -> (u16, &'static str) {
+        match self {
+            Self::Timeout => (504, "Gateway Timeout"),
+            Self::Forbidden => (403, "Forbidden"),
+            Self::Other => (503, "Service Unavailable"),
+            _ => (502, "Bad Gateway"),
+        }
+    }
+
+    /// Get the error type of the `Proxy-Status` header (RFC 9209).
+    pub fn proxy_error(&self) -> &'static str {
+        match self {
+            Self::Transport => "proxy_internal_error",
+            Self::Dns => "dns_error",
+            Self::Refused => "connection_refused",
+            Self::Timeout => "connection_timeout",
+            Self::Unreachable => "destination_ip_unroutable",
+            Self::Forbidden => "http_request_denied",
+            Self::Other => "destination_unavailable",
+        }
+    }
+
+    /// Get the reply code of SOCKS5 (RFC 1928).
+    pub fn socks5_reply(&self) -> u8 {
+        match self {
+            // Connection not allowed by ruleset.
+            Self::Forbidden => 2,
+            // Network unreachable.
+            Self::Unreachable => 3,
+            // Host unreachable, the name of the host cannot be resolved.
+            Self::Dns => 4,
+            // Connection refused.
+            Self::Refused => 5,
+            // TTL expired, which is the closest to a timeout.
+            Self::Timeout => 6,
+            // General SOCKS server failure.
+            Self::Transport | Self::Other => 1,
+        }
+    }
+
+    /// Check if the failure is caused by the worker rather than the destination, so that another worker may succeed.
+    pub fn is_worker_fault(&self) -> bool {
+        *self == Self::Transport
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Format a `Proxy-Status` header value with an error type and the details (RFC 9209).
+/// NOTE: The details is a quoted string, so that only printable ASCII characters are kept.
+pub fn proxy_status(error: &str, details: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in details.trim().chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            ' '..='~' => quoted.push(c),
+            _ => quoted.push('?'),
+        }
+    }
+    quoted.push('"');
+    format!("{}; error={}; details={}", PROXY_NAME, error, quoted)
+}
@@ -1,13 +1,20 @@
-use std::{future::Future, pin::Pin, time::Duration};
+use std::{fs, future::Future, net::SocketAddr, pin::Pin, time::Duration};
 
+mod capabilities;
 mod error;
+mod failure;
 mod stream;
 mod supported_stream;
 
+#[cfg(test)]
+pub mod tests;
+
+pub use capabilities::*;
 pub use error::*;
+pub use failure::*;
 use socket2::{Socket, TcpKeepalive};
 pub use stream::*;
-use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::net::{lookup_host, TcpStream, ToSocketAddrs};
 
 pub use supported_stream::*;
 
@@ -22,6 +29,37 @@ impl NeckError {
     }
 }
 
+/// Read a file with a readable error message.
+pub fn read_file(path: &str) -> NeckResult<Vec<u8>> {
+    fs::read(path).map_err(|e| NeckError::new(format!("Cannot read '{}': {}", path, e)).into())
+}
+
+/// Parse a hex-encoded SHA-256 digest.
+pub fn parse_hex_digest(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        // NOTE: The `get` returns None if the range is not on a char boundary.
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+/// Read a list file, one item per line.
+/// NOTE: Items are trimmed, empty lines and lines starting with '#' are ignored.
+pub fn read_list_file(path: &str) -> NeckResult<Vec<String>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| NeckError::new(format!("Cannot read '{}': {}", path, e)))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
 pub fn enable_keepalive(stream: TcpStream) -> TcpStream {
     let socket = Socket::from(stream.into_std().unwrap());
     let keepalive = TcpKeepalive::new()
@@ -35,3 +73,12 @@ pub async fn connect<T: ToSocketAddrs>(raw: T) -> NeckResult<TcpStream> {
     let stream = tokio::time::timeout(Duration::from_secs(10), TcpStream::connect(raw)).await??;
     Ok(enable_keepalive(stream))
 }
+
+/// Resolve a destination like "host:port", an error means that the DNS has failed.
+pub async fn resolve(host: &str) -> NeckResult<Vec<SocketAddr>> {
+    let addrs = lookup_host(host).await?.collect::<Vec<SocketAddr>>();
+    if addrs.is_empty() {
+        return NeckError::wrap(format!("No address is resolved for {}", host));
+    }
+    Ok(addrs)
+}
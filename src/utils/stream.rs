@@ -4,18 +4,24 @@ use std::{
 };
 
 use tokio::{
-    io::{self, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+    io::{
+        self, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+        BufWriter,
+    },
     select,
-    sync::Mutex,
+    sync::{Mutex, Notify},
+    try_join,
 };
 
 use crate::{
+    framed::{Frame, DATA, END, MAX_PAYLOAD},
     http::{HttpProtocol, HttpRequest, HttpResponse},
+    mux::MuxFrame,
     socks5::Socks5Message,
     utils::NeckError,
 };
 
-use super::{NeckResult, SupportedStream};
+use super::{Capabilities, NeckResult, SupportedStream};
 
 pub struct NeckStream {
     raw: Box<SupportedStream>,
@@ -26,6 +32,18 @@ pub struct NeckStream {
     pub peer_addr: SocketAddr,
     pub local_addr: SocketAddr,
 
+    /// The subject of the verified client certificate, if any.
+    pub peer_subject: Option<String>,
+
+    /// The software version of the peer, which is announced when joining.
+    pub peer_agent: Option<String>,
+
+    /// The capabilities negotiated when joining, such as the framed mode or the multiplexed mode.
+    pub capabilities: Capabilities,
+
+    /// The worker group declared when joining, None for the workers without a group.
+    pub group: Option<String>,
+
     // Pin this struct to prevent any properties from being taken out.
     // Because this struct contains unsafe pointers.
     // The `reader`, and `writer` refer to `raw`.
@@ -44,8 +62,8 @@ impl<T: Into<SupportedStream>> From<T> for NeckStream {
             Pin::new_unchecked(&mut *addr_of_mut!(*buss.as_mut()))
         });
 
-        let peer_addr = buss.get_tcp_stream_ref().peer_addr().unwrap();
-        let local_addr = buss.get_tcp_stream_ref().local_addr().unwrap();
+        let (peer_addr, local_addr) = buss.get_addrs();
+        let peer_subject = buss.get_peer_subject();
 
         Self {
             raw: buss,
@@ -53,6 +71,10 @@ impl<T: Into<SupportedStream>> From<T> for NeckStream {
             reader: Mutex::new(BufReader::with_capacity(10240, reader)),
             peer_addr,
             local_addr,
+            peer_subject,
+            peer_agent: None,
+            capabilities: Capabilities::default(),
+            group: None,
             _pinned: PhantomPinned,
         }
     }
@@ -83,6 +105,65 @@ impl NeckStream {
         }
     }
 
+    /// Weld with a framed `link` (Start a bidirectional stream copy, where the `link` side is carried by frames).
+    /// Each side sends an END frame when its source is finished, or when it has received an END frame from the peer.
+    /// Unlike `weld`, the `link` is left clean when both END frames are exchanged, so it can be reused.
+    /// If an error is returned, the `link` is broken and must not be reused.
+    pub async fn weld_framed(&self, link: &Self) -> io::Result<()> {
+        // Split and lock all half streams.
+        let (mut r, mut w, mut lr, mut lw) = tokio::join!(
+            self.reader.lock(),
+            self.writer.lock(),
+            link.reader.lock(),
+            link.writer.lock()
+        );
+
+        // Notified when the peer has sent an END frame, or when this side cannot be written anymore.
+        let stop = Notify::new();
+
+        // Copy this side to the link with DATA frames, until EOF or being stopped, then send an END frame.
+        let outgoing = async {
+            let mut buf = vec![0; MAX_PAYLOAD];
+            loop {
+                let size = select! {
+                    v = r.read(&mut buf) => v.unwrap_or(0),
+                    _ = stop.notified() => 0,
+                };
+                if size == 0 {
+                    break;
+                }
+                Frame::data(&buf[..size]).write_to(&mut *lw).await?;
+            }
+            Frame::end().write_to(&mut *lw).await
+        };
+
+        // Copy DATA frames from the link to this side, until an END frame is received.
+        // NOTE: Frames are still drained after this side failed to write, otherwise the link would be dirty.
+        let incoming = async {
+            let mut writable = true;
+            loop {
+                let frame = Frame::read_from(&mut *lr).await?;
+                match frame.kind {
+                    DATA => {
+                        if writable && w.write_all(&frame.payload).await.is_err() {
+                            writable = false;
+                            stop.notify_one();
+                        }
+                    }
+                    END => {
+                        let _ = w.shutdown().await;
+                        stop.notify_one();
+                        return Ok(());
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        };
+
+        // Any error here is from the link, so stop both directions immediately.
+        try_join!(outgoing, incoming).map(|_| ())
+    }
+
     /// Shutdown the connection immediately.
     pub async fn shutdown(&self) -> io::Result<()> {
         self.writer.lock().await.shutdown().await
@@ -103,7 +184,11 @@ impl NeckStream {
     /// Get the raw `TcpStream` and peek it.
     pub async fn peek_raw_tck_stream(&self) -> Result<usize, io::Error> {
         let mut buf = [0; 1];
-        self.raw.get_tcp_stream_ref().peek(&mut buf).await
+        match self.raw.get_tcp_stream_ref() {
+            Some(s) => s.peek(&mut buf).await,
+            // A virtual stream cannot be peeked, so regard it as nothing to receive.
+            None => Ok(0),
+        }
     }
 
     /// Wait until this connection closed by peer.
@@ -187,6 +272,22 @@ impl HttpResponse {
         let mut reader = stream.reader.lock().await;
         HttpProtocol::read_from(&mut reader).await.map(|v| v.into())
     }
+
+    /// Read an HTTP response header.
+    /// NOTE: The payload will not be readed.
+    pub async fn read_header_from(stream: &NeckStream) -> io::Result<HttpResponse> {
+        let mut reader = stream.reader.lock().await;
+        HttpProtocol::read_header_from(&mut reader)
+            .await
+            .map(|v| v.into())
+    }
+}
+
+impl MuxFrame {
+    pub async fn write_to_stream(&self, stream: &NeckStream) -> io::Result<()> {
+        let mut writer = stream.writer.lock().await;
+        self.write_to(&mut *writer).await
+    }
 }
 
 impl Socks5Message {
@@ -1,14 +1,34 @@
-use std::pin::Pin;
+use std::{net::SocketAddr, pin::Pin};
 
+use openssl::x509::X509NameRef;
 use tokio::{
-    io::{split, AsyncRead, AsyncWrite},
+    io::{split, AsyncRead, AsyncWrite, DuplexStream},
     net::TcpStream,
 };
 use tokio_native_tls::TlsStream;
+use tokio_openssl::SslStream;
 
 pub enum SupportedStream {
     Tls(TlsStream<TcpStream>),
+    /// A TLS stream accepted with OpenSSL, which is used when client certificates are required.
+    OpenSsl(SslStream<TcpStream>),
     Tcp(TcpStream),
+    /// A logical stream of a multiplexed connection, with the peer and local addresses of that connection.
+    Virtual(DuplexStream, SocketAddr, SocketAddr),
+}
+
+/// Format an X509 name like "CN=worker,O=Example".
+fn format_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|e| {
+            format!(
+                "{}={}",
+                e.object().nid().short_name().unwrap_or("?"),
+                e.data().to_string().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",")
 }
 
 impl SupportedStream {
@@ -23,17 +43,49 @@ impl SupportedStream {
                 let (r, w) = split(s);
                 (Box::new(r), Box::new(w))
             }
+            SupportedStream::OpenSsl(s) => {
+                let (r, w) = split(s);
+                (Box::new(r), Box::new(w))
+            }
             SupportedStream::Tcp(s) => {
                 let (r, w) = split(s);
                 (Box::new(r), Box::new(w))
             }
+            SupportedStream::Virtual(s, _, _) => {
+                let (r, w) = split(s);
+                (Box::new(r), Box::new(w))
+            }
+        }
+    }
+
+    /// Get the underlying TcpStream, None for a virtual stream.
+    pub fn get_tcp_stream_ref(&self) -> Option<&TcpStream> {
+        match self {
+            SupportedStream::Tls(s) => Some(s.get_ref().get_ref().get_ref()),
+            SupportedStream::OpenSsl(s) => Some(s.get_ref()),
+            SupportedStream::Tcp(s) => Some(s),
+            SupportedStream::Virtual(..) => None,
         }
     }
 
-    pub fn get_tcp_stream_ref(&self) -> &TcpStream {
+    /// Get the peer address and the local address.
+    pub fn get_addrs(&self) -> (SocketAddr, SocketAddr) {
+        if let SupportedStream::Virtual(_, peer, local) = self {
+            return (*peer, *local);
+        }
+        let s = self.get_tcp_stream_ref().unwrap();
+        (s.peer_addr().unwrap(), s.local_addr().unwrap())
+    }
+
+    /// Get the subject of the verified peer certificate.
+    /// NOTE: Only the OpenSSL stream verifies the certificate of the client.
+    pub fn get_peer_subject(&self) -> Option<String> {
         match self {
-            SupportedStream::Tls(s) => s.get_ref().get_ref().get_ref(),
-            SupportedStream::Tcp(s) => s,
+            SupportedStream::OpenSsl(s) => s
+                .ssl()
+                .peer_certificate()
+                .map(|cert| format_name(cert.subject_name())),
+            _ => None,
         }
     }
 }
@@ -49,3 +101,9 @@ impl Into<SupportedStream> for TlsStream<TcpStream> {
         SupportedStream::Tls(self)
     }
 }
+
+impl From<SslStream<TcpStream>> for SupportedStream {
+    fn from(stream: SslStream<TcpStream>) -> Self {
+        SupportedStream::OpenSsl(stream)
+    }
+}
@@ -0,0 +1,66 @@
+use crate::http::Headers;
+
+use super::super::{
+    get_protocol_version, select_protocol_version, Capabilities, MIN_PROTOCOL_VERSION,
+    PROTOCOL_VERSION,
+};
+
+#[test]
+fn test_parse() {
+    let capabilities = Capabilities::parse("heartbeat, Mux ,,mux, framed");
+    assert!(capabilities.has("MUX"));
+    assert!(capabilities.has("framed"));
+    assert!(!capabilities.has("mu"));
+    assert_eq!(capabilities.to_string(), "heartbeat, mux, framed");
+
+    assert_eq!(Capabilities::parse("").to_string(), "");
+}
+
+#[test]
+fn test_intersection() {
+    let offered = Capabilities::parse("heartbeat, mux, udp");
+    let supported = Capabilities::parse("framed, MUX, heartbeat");
+    assert_eq!(
+        offered.intersection(&supported).to_string(),
+        "heartbeat, mux"
+    );
+}
+
+#[test]
+fn test_protocol_version() {
+    let headers = |lines: &[&str]| lines.iter().map(|v| v.to_string()).collect::<Headers>();
+
+    // A missing header means the original protocol.
+    assert_eq!(get_protocol_version(&headers(&[])), Some(1));
+    assert_eq!(
+        get_protocol_version(&headers(&["Neck-Version: 2"])),
+        Some(2)
+    );
+    assert_eq!(
+        get_protocol_version(&headers(&["neck-version:  3 "])),
+        Some(3)
+    );
+    assert_eq!(get_protocol_version(&headers(&["Neck-Version: x"])), None);
+}
+
+#[test]
+fn test_select_protocol_version() {
+    let select = |offered| select_protocol_version(offered, MIN_PROTOCOL_VERSION);
+
+    // The original protocol is still supported, and a newer peer speaks the current version.
+    assert_eq!(select(Some(1)), Some(1));
+    assert_eq!(select(Some(PROTOCOL_VERSION)), Some(PROTOCOL_VERSION));
+    assert_eq!(select(Some(PROTOCOL_VERSION + 1)), Some(PROTOCOL_VERSION));
+
+    // Malformed or too old.
+    assert_eq!(select(None), None);
+    assert_eq!(select(Some(0)), None);
+
+    // Once the original protocol is dropped, a peer without the version header is rejected.
+    let headers = Vec::<String>::new().into_iter().collect::<Headers>();
+    assert_eq!(
+        select_protocol_version(get_protocol_version(&headers), 2),
+        None
+    );
+    assert_eq!(select_protocol_version(Some(2), 2), Some(2));
+}
@@ -0,0 +1,95 @@
+use openssl::{
+    asn1::Asn1Time,
+    bn::{BigNum, MsbOption},
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    x509::{
+        extension::{BasicConstraints, SubjectAlternativeName},
+        X509NameBuilder, X509NameRef, X509,
+    },
+};
+
+/// A certificate for "localhost" and its private key, which are generated for tests.
+pub struct TestCert {
+    pub cert: X509,
+    pub key: PKey<Private>,
+}
+
+impl TestCert {
+    /// Generate a self-signed certificate, which can also be used as a CA.
+    pub fn self_signed(cn: &str) -> Self {
+        Self::generate(cn, None)
+    }
+
+    /// Generate a certificate signed by a CA.
+    pub fn signed_by(cn: &str, ca: &TestCert) -> Self {
+        Self::generate(cn, Some(ca))
+    }
+
+    fn generate(cn: &str, ca: Option<&TestCert>) -> Self {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+        let name = name.build();
+
+        let mut serial = BigNum::new().unwrap();
+        serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+
+        let issuer: &X509NameRef = match ca {
+            Some(ca) => ca.cert.subject_name(),
+            None => &name,
+        };
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder
+            .set_serial_number(&serial.to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(issuer).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        if ca.is_none() {
+            builder
+                .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                .unwrap();
+        }
+        let san = SubjectAlternativeName::new()
+            .dns("localhost")
+            .build(&builder.x509v3_context(ca.map(|ca| ca.cert.as_ref()), None))
+            .unwrap();
+        builder.append_extension(san).unwrap();
+        builder
+            .sign(ca.map_or(&key, |ca| &ca.key), MessageDigest::sha256())
+            .unwrap();
+
+        Self {
+            cert: builder.build(),
+            key,
+        }
+    }
+
+    pub fn cert_pem(&self) -> Vec<u8> {
+        self.cert.to_pem().unwrap()
+    }
+
+    pub fn key_pem(&self) -> Vec<u8> {
+        self.key.private_key_to_pem_pkcs8().unwrap()
+    }
+
+    /// Write a file into the temporary directory, and return its path.
+    pub fn write_temp(name: &str, content: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("neck-{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+}
@@ -0,0 +1,82 @@
+use std::io;
+
+use crate::utils::{proxy_status, BoxedError, Failure};
+
+#[test]
+fn test_parse() {
+    assert_eq!(Failure::parse("refused"), Some(Failure::Refused));
+    assert_eq!(Failure::parse(" DNS "), Some(Failure::Dns));
+    assert_eq!(Failure::parse("unknown"), None);
+    for name in [
+        "transport",
+        "dns",
+        "refused",
+        "timeout",
+        "unreachable",
+        "forbidden",
+        "other",
+    ] {
+        assert_eq!(Failure::parse(name).unwrap().as_str(), name);
+    }
+}
+
+#[tokio::test]
+async fn test_from_error() {
+    let error = |kind: io::ErrorKind| -> BoxedError { io::Error::from(kind).into() };
+    assert_eq!(
+        Failure::from_error(&error(io::ErrorKind::ConnectionRefused)),
+        Failure::Refused
+    );
+    assert_eq!(
+        Failure::from_error(&error(io::ErrorKind::HostUnreachable)),
+        Failure::Unreachable
+    );
+    assert_eq!(
+        Failure::from_error(&error(io::ErrorKind::Other)),
+        Failure::Other
+    );
+
+    // The timeout of the connecting future.
+    let elapsed = tokio::time::timeout(std::time::Duration::ZERO, std::future::pending::<()>())
+        .await
+        .unwrap_err();
+    assert_eq!(Failure::from_error(&elapsed.into()), Failure::Timeout);
+
+    // Only a broken worker is worth retrying.
+    assert!(Failure::Transport.is_worker_fault());
+    assert!(!Failure::Refused.is_worker_fault());
+}
+
+#[test]
+fn test_mapping() {
+    assert_eq!(Failure::Timeout.http_status().0, 504);
+    assert_eq!(Failure::Forbidden.http_status().0, 403);
+    assert_eq!(Failure::Refused.http_status().0, 502);
+    assert_eq!(Failure::Other.http_status().0, 503);
+
+    let replies = [
+        Failure::Forbidden,
+        Failure::Unreachable,
+        Failure::Dns,
+        Failure::Refused,
+        Failure::Timeout,
+    ]
+    .map(|f| f.socks5_reply());
+    assert_eq!(replies, [2, 3, 4, 5, 6]);
+    assert_eq!(Failure::Other.socks5_reply(), 1);
+}
+
+#[test]
+fn test_proxy_status() {
+    assert_eq!(
+        proxy_status(
+            Failure::Dns.proxy_error(),
+            "failed to lookup \"a.invalid\"\n"
+        ),
+        r#"neck; error=dns_error; details="failed to lookup \"a.invalid\"""#
+    );
+    assert_eq!(
+        proxy_status("connection_refused", "C:\\ 拒绝"),
+        r#"neck; error=connection_refused; details="C:\\ ??""#
+    );
+}
@@ -0,0 +1,13 @@
+#[cfg(test)]
+mod capabilities_test;
+#[cfg(test)]
+mod certs;
+#[cfg(test)]
+mod failure_test;
+#[cfg(test)]
+mod pair;
+
+#[cfg(test)]
+pub use certs::*;
+#[cfg(test)]
+pub use pair::*;
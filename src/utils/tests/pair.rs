@@ -0,0 +1,13 @@
+use tokio::net::{TcpListener, TcpStream};
+
+use super::super::NeckStream;
+
+/// Create a pair of connected streams over the loopback.
+pub async fn stream_pair() -> (NeckStream, NeckStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let a = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (b, _) = listener.accept().await.unwrap();
+    (b.into(), a.into())
+}